@@ -41,6 +41,7 @@ pub fn add_watched_directory(
         enabled: true,
         created_at: chrono::Utc::now(),
         last_scanned_at: None,
+        tag_providers: None,
     };
 
     println!("[Rust] WatchedDirectory to create: {:?}", dir);
@@ -110,6 +110,8 @@ pub fn get_stats(
 }
 
 /// 修复标签使用计数
+///
+/// 计数已由数据库触发器维护，此命令仅作为手动校正的兜底手段。
 #[tauri::command]
 pub fn fix_tag_counts(
     state: tauri::State<Database>,
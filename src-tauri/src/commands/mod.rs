@@ -1,9 +1,11 @@
 mod tag;
+mod rule;
 mod search;
 mod file;
 mod directory_watcher;
 
 pub use tag::*;
+pub use rule::*;
 pub use search::*;
 pub use file::*;
 pub use directory_watcher::*;
@@ -17,6 +19,8 @@ pub fn register_commands(db: Database) -> tauri::Builder<tauri::Wry> {
         .setup(move |app| {
             // 将数据库实例存储到 app state 中
             app.manage(db);
+            // 每个本地零点重新评估“今日文件”等时间相关标签
+            crate::tagger::spawn_date_tag_scheduler(app.handle().clone());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -27,12 +31,47 @@ pub fn register_commands(db: Database) -> tauri::Builder<tauri::Wry> {
             add_tag_to_file,
             remove_tag_from_file,
             batch_add_tags,
+            batch_remove_tags,
+            batch_set_tags,
+            retag_by_query,
             get_files_by_tags,
             delete_tag,
             update_tag,
+            get_tag_tree,
+            move_tag,
+            rename_tag,
+            merge_tags,
+            get_tag_aliases,
+            add_tag_alias,
+            remove_tag_alias,
+            get_tag_implications,
+            add_tag_implication,
+            remove_tag_implication,
+            set_tag_value,
+            get_tag_values,
+            get_grouped_tags,
+            get_tag_groups,
+            create_tag_group,
+            update_tag_group,
+            delete_tag_group,
+            set_tag_group,
+            add_directory_tag,
+            remove_directory_tag,
+            get_directory_tags,
+            get_tag_explanation,
+            suggest_tags,
+
+            // 自动标签规则与提供者相关
+            preview_tag_rule,
+            apply_tag_rule,
+            export_tag_rules,
+            import_tag_rules,
+            get_tag_providers,
+            set_directory_tag_providers,
 
             // 搜索相关
             search_files,
+            parse_search_query,
 
             // 文件相关
             get_files,
@@ -0,0 +1,60 @@
+use crate::db::Database;
+use crate::tagger::{self, ImportMode, ImportSummary, ProviderInfo, ProviderRegistry, RulePreview, TagRule};
+use std::path::Path;
+
+/// 预览自动标签规则对已索引文件的影响（不写入数据）
+#[tauri::command]
+pub fn preview_tag_rule(
+    rule: TagRule,
+    state: tauri::State<Database>,
+) -> std::result::Result<RulePreview, String> {
+    tagger::preview_rule(&state, &rule).map_err(|e| e.to_string())
+}
+
+/// 启用自动标签规则，并在一个事务中应用到已索引文件
+#[tauri::command]
+pub fn apply_tag_rule(
+    rule: TagRule,
+    state: tauri::State<Database>,
+) -> std::result::Result<RulePreview, String> {
+    tagger::apply_rule(&state, rule).map_err(|e| e.to_string())
+}
+
+/// 导出当前启用的自动标签规则（按扩展名选择 TOML 或 JSON），返回导出的规则数
+#[tauri::command]
+pub fn export_tag_rules(
+    path: String,
+    state: tauri::State<Database>,
+) -> std::result::Result<usize, String> {
+    tagger::export_rules(&state, Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// 从 TOML 或 JSON 文件导入自动标签规则
+#[tauri::command]
+pub fn import_tag_rules(
+    path: String,
+    mode: ImportMode,
+    state: tauri::State<Database>,
+) -> std::result::Result<ImportSummary, String> {
+    tagger::import_rules(&state, Path::new(&path), mode).map_err(|e| e.to_string())
+}
+
+/// 获取已注册的标签提供者及其运行统计
+#[tauri::command]
+pub fn get_tag_providers(
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<ProviderInfo>, String> {
+    ProviderRegistry::load(&state)
+        .map(|registry| registry.providers())
+        .map_err(|e| e.to_string())
+}
+
+/// 设置监控目录启用的标签提供者，传入 null 表示启用全部
+#[tauri::command]
+pub fn set_directory_tag_providers(
+    id: i64,
+    providers: Option<Vec<String>>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    tagger::set_directory_providers(&state, id, providers).map_err(|e| e.to_string())
+}
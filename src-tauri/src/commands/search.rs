@@ -1,35 +1,51 @@
-use crate::db::{Database, SearchQuery, SearchOperator, FileType};
+use crate::db::{Database, FuzzyOptions, QueryParseError, SearchQuery};
+use crate::error::AppError;
+
+/// 搜索失败的原因，前端按 `kind` 区分
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SearchError {
+    /// 搜索语句有误，带出错的字符位置（`message`、`start`、`end`）
+    Parse(QueryParseError),
+    /// 执行搜索时出错
+    Database { message: String },
+}
+
+impl From<QueryParseError> for SearchError {
+    fn from(err: QueryParseError) -> Self {
+        SearchError::Parse(err)
+    }
+}
+
+impl From<AppError> for SearchError {
+    fn from(err: AppError) -> Self {
+        SearchError::Database { message: err.to_string() }
+    }
+}
 
 /// 搜索文件
+///
+/// `query` 为搜索语句，如 `tag:invoice type:image size:>10MB -tag:draft "exact phrase" (a OR b)`，
+/// 语法见 `SearchQuery::parse`。传入 `fuzzy` 时关键字按文件名模糊匹配，如 `{ "maxDistance": 1 }`。
+/// 搜索语句有误时返回 `SearchError::Parse`，前端可据此标出出错的位置。
 #[tauri::command]
 pub fn search_files(
-    keywords: Vec<String>,
-    operator: String,
-    file_type_filter: Option<String>,
-    tags: Option<Vec<String>>,
+    query: String,
     limit: usize,
     offset: usize,
+    fuzzy: Option<FuzzyOptions>,
     state: tauri::State<Database>,
-) -> std::result::Result<crate::db::SearchResultResponse, String> {
-    let op = match operator.as_str() {
-        "AND" | "and" => SearchOperator::And,
-        "OR" | "or" => SearchOperator::Or,
-        _ => return Err("无效的搜索运算符，请使用 AND 或 OR".to_string()),
-    };
-
-    let ft = file_type_filter.and_then(|s| {
-        let ext = s.split(',').next().unwrap_or("");
-        Some(FileType::from_extension(ext))
-    });
+) -> std::result::Result<crate::db::SearchResultResponse, SearchError> {
+    let mut query = SearchQuery::parse(&query, limit, offset)?;
+    query.fuzzy = fuzzy;
 
-    let query = SearchQuery {
-        keywords,
-        operator: op,
-        file_type_filter: ft,
-        tags,
-        limit,
-        offset,
-    };
+    Ok(state.search_files(&query)?)
+}
 
-    state.search_files(&query).map_err(|e| e.to_string())
+/// 解析搜索语句
+///
+/// 供前端在搜索前校验输入，出错时返回带字符位置的结构化错误以便标出问题所在。
+#[tauri::command]
+pub fn parse_search_query(query: String) -> std::result::Result<SearchQuery, QueryParseError> {
+    SearchQuery::parse(&query, 0, 0)
 }
@@ -1,4 +1,4 @@
-use crate::db::{Database, Tag, TagType};
+use crate::db::{Database, DirectoryTag, GroupedTags, RenameTagResult, SearchQuery, Tag, TagAlias, TagExplanation, TagGroup, TagImplication, TagNode, TagSuggestion, TagType, TagValue, TagValueCount};
 
 /// 获取所有标签
 #[tauri::command]
@@ -6,6 +6,12 @@ pub fn get_all_tags(state: tauri::State<Database>) -> std::result::Result<Vec<Ta
     state.get_all_tags().map_err(|e| e.to_string())
 }
 
+/// 获取标签树
+#[tauri::command]
+pub fn get_tag_tree(state: tauri::State<Database>) -> std::result::Result<Vec<TagNode>, String> {
+    state.get_tag_tree().map_err(|e| e.to_string())
+}
+
 /// 获取文件的标签
 #[tauri::command]
 pub fn get_tags_by_file(
@@ -35,6 +41,10 @@ pub fn create_tag(
         icon: icon,
         use_count: 0,
         created_at: chrono::Utc::now(),
+        parent_id: None,
+        value: None,
+        inherited_from: None,
+        implied_by: None,
     };
 
     println!("[Rust] Tag to create: {:?}", tag);
@@ -61,6 +71,26 @@ pub fn add_tag_to_file(
     state.add_tag_to_file_by_name(file_id, &tag_name, false).map_err(|e| e.to_string())
 }
 
+/// 获取文件标签的来源说明（手动添加或由哪条规则自动生成）
+#[tauri::command]
+pub fn get_tag_explanation(
+    file_id: i64,
+    tag_id: i64,
+    state: tauri::State<Database>,
+) -> std::result::Result<TagExplanation, String> {
+    state.get_tag_explanation(file_id, tag_id).map_err(|e| e.to_string())
+}
+
+/// 为文件推荐标签，依据共现统计、同目录文件和名称相似的文件，默认返回前 10 条
+#[tauri::command]
+pub fn suggest_tags(
+    file_id: i64,
+    limit: Option<usize>,
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<TagSuggestion>, String> {
+    state.suggest_tags(file_id, limit.unwrap_or(10)).map_err(|e| e.to_string())
+}
+
 /// 从文件移除标签
 #[tauri::command]
 pub fn remove_tag_from_file(
@@ -81,6 +111,40 @@ pub fn batch_add_tags(
     state.batch_add_tags(&file_ids, &tag_names).map_err(|e| e.to_string())
 }
 
+/// 批量从文件移除标签
+#[tauri::command]
+pub fn batch_remove_tags(
+    file_ids: Vec<i64>,
+    tag_names: Vec<String>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.batch_remove_tags(&file_ids, &tag_names).map_err(|e| e.to_string())
+}
+
+/// 批量设置文件的手动标签（替换原有的手动标签）
+#[tauri::command]
+pub fn batch_set_tags(
+    file_ids: Vec<i64>,
+    tag_names: Vec<String>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.batch_set_tags(&file_ids, &tag_names).map_err(|e| e.to_string())
+}
+
+/// 对搜索结果中的所有文件添加和移除标签，返回受影响的文件数
+///
+/// `query` 为搜索语句，语法同 `search_files`。
+#[tauri::command]
+pub fn retag_by_query(
+    query: String,
+    add: Vec<String>,
+    remove: Vec<String>,
+    state: tauri::State<Database>,
+) -> std::result::Result<usize, String> {
+    let query = SearchQuery::parse(&query, 0, 0).map_err(|e| e.to_string())?;
+    state.retag_by_query(&query, &add, &remove).map_err(|e| e.to_string())
+}
+
 /// 根据标签获取文件
 #[tauri::command]
 pub fn get_files_by_tags(
@@ -91,17 +155,25 @@ pub fn get_files_by_tags(
 }
 
 /// 删除标签
+///
+/// `reassign_children` 默认为 true：子标签上移一级；为 false 时连同子标签一起删除。
 #[tauri::command]
 pub fn delete_tag(
     tag_id: i64,
+    reassign_children: Option<bool>,
     state: tauri::State<Database>,
 ) -> std::result::Result<(), String> {
-    use rusqlite::params;
+    state.delete_tag(tag_id, reassign_children.unwrap_or(true)).map_err(|e| e.to_string())
+}
 
-    let conn = state.conn.lock();
-    conn.execute("DELETE FROM tags WHERE id = ?1", params![tag_id])
-        .map_err(|e| e.to_string())?;
-    Ok(())
+/// 移动标签（连同子标签）到新的父标签下，parent_id 为 null 时移动到根
+#[tauri::command]
+pub fn move_tag(
+    tag_id: i64,
+    parent_id: Option<i64>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.move_tag(tag_id, parent_id).map_err(|e| e.to_string())
 }
 
 /// 更新标签
@@ -121,3 +193,181 @@ pub fn update_tag(
         ).map_err(|e| e.to_string())?;
     Ok(())
 }
+
+/// 修改标签名称，新名称已被占用时返回冲突；merge_on_conflict 为 true 时直接合并到已有标签
+#[tauri::command]
+pub fn rename_tag(
+    tag_id: i64,
+    new_name: String,
+    merge_on_conflict: Option<bool>,
+    state: tauri::State<Database>,
+) -> std::result::Result<RenameTagResult, String> {
+    state
+        .rename_tag(tag_id, &new_name, merge_on_conflict.unwrap_or(false))
+        .map_err(|e| e.to_string())
+}
+
+/// 合并标签：源标签的文件关联、子标签和别名并入目标标签后删除源标签
+#[tauri::command]
+pub fn merge_tags(
+    source_ids: Vec<i64>,
+    target_id: i64,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.merge_tags(&source_ids, target_id).map_err(|e| e.to_string())
+}
+
+/// 获取标签别名，指定 tag_id 时只返回该标签的别名
+#[tauri::command]
+pub fn get_tag_aliases(
+    tag_id: Option<i64>,
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<TagAlias>, String> {
+    state.get_tag_aliases(tag_id).map_err(|e| e.to_string())
+}
+
+/// 为标签添加别名（如 pics、photos 指向 图片）
+#[tauri::command]
+pub fn add_tag_alias(
+    tag_id: i64,
+    alias: String,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.add_tag_alias(tag_id, &alias).map_err(|e| e.to_string())
+}
+
+/// 删除标签别名
+#[tauri::command]
+pub fn remove_tag_alias(
+    alias: String,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.remove_tag_alias(&alias).map_err(|e| e.to_string())
+}
+
+/// 获取标签推导规则，指定 tag_id 时只返回以该标签为来源的规则
+#[tauri::command]
+pub fn get_tag_implications(
+    tag_id: Option<i64>,
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<TagImplication>, String> {
+    state.get_tag_implications(tag_id).map_err(|e| e.to_string())
+}
+
+/// 添加标签推导规则（如 invoice 推导 finance），并应用到已有该标签的文件
+#[tauri::command]
+pub fn add_tag_implication(
+    tag_id: i64,
+    implied_tag_id: i64,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.add_tag_implication(tag_id, implied_tag_id).map_err(|e| e.to_string())
+}
+
+/// 删除标签推导规则，仅由该规则推导出的标签随之移除
+#[tauri::command]
+pub fn remove_tag_implication(
+    tag_id: i64,
+    implied_tag_id: i64,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.remove_tag_implication(tag_id, implied_tag_id).map_err(|e| e.to_string())
+}
+
+/// 设置文件上键值标签的值（如 rating=4），value 为空时清除值并保留标签
+#[tauri::command]
+pub fn set_tag_value(
+    file_id: i64,
+    key: String,
+    value: Option<TagValue>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.set_tag_value(file_id, &key, value.as_ref()).map_err(|e| e.to_string())
+}
+
+/// 列出键值标签的所有取值及使用次数
+#[tauri::command]
+pub fn get_tag_values(
+    key: String,
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<TagValueCount>, String> {
+    state.get_tag_values(&key).map_err(|e| e.to_string())
+}
+
+/// 按分组获取所有标签（标签面板使用），未分组的标签在最后
+#[tauri::command]
+pub fn get_grouped_tags(state: tauri::State<Database>) -> std::result::Result<Vec<GroupedTags>, String> {
+    state.get_grouped_tags().map_err(|e| e.to_string())
+}
+
+/// 获取所有标签分组
+#[tauri::command]
+pub fn get_tag_groups(state: tauri::State<Database>) -> std::result::Result<Vec<TagGroup>, String> {
+    state.get_tag_groups().map_err(|e| e.to_string())
+}
+
+/// 创建标签分组，exclusive 为 true 时组内标签互斥
+#[tauri::command]
+pub fn create_tag_group(
+    name: String,
+    exclusive: bool,
+    state: tauri::State<Database>,
+) -> std::result::Result<i64, String> {
+    state.create_tag_group(&name, exclusive).map_err(|e| e.to_string())
+}
+
+/// 修改标签分组
+#[tauri::command]
+pub fn update_tag_group(
+    group_id: i64,
+    name: String,
+    exclusive: bool,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.update_tag_group(group_id, &name, exclusive).map_err(|e| e.to_string())
+}
+
+/// 删除标签分组，组内标签变为未分组
+#[tauri::command]
+pub fn delete_tag_group(group_id: i64, state: tauri::State<Database>) -> std::result::Result<(), String> {
+    state.delete_tag_group(group_id).map_err(|e| e.to_string())
+}
+
+/// 设置标签所属分组，group_id 为空时移出分组
+#[tauri::command]
+pub fn set_tag_group(
+    tag_id: i64,
+    group_id: Option<i64>,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.set_tag_group(tag_id, group_id).map_err(|e| e.to_string())
+}
+
+/// 为目录添加标签，目录下的文件继承该标签
+#[tauri::command]
+pub fn add_directory_tag(
+    path: String,
+    tag_name: String,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.add_directory_tag(&path, &tag_name).map_err(|e| e.to_string())
+}
+
+/// 移除目录标签，目录下文件继承的该标签随之移除
+#[tauri::command]
+pub fn remove_directory_tag(
+    path: String,
+    tag_id: i64,
+    state: tauri::State<Database>,
+) -> std::result::Result<(), String> {
+    state.remove_directory_tag(&path, tag_id).map_err(|e| e.to_string())
+}
+
+/// 获取目录标签，指定 path 时只返回该目录的标签
+#[tauri::command]
+pub fn get_directory_tags(
+    path: Option<String>,
+    state: tauri::State<Database>,
+) -> std::result::Result<Vec<DirectoryTag>, String> {
+    state.get_directory_tags(path.as_deref()).map_err(|e| e.to_string())
+}
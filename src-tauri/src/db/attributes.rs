@@ -0,0 +1,141 @@
+use super::directory_tags::sync_derived_tags;
+use super::groups::enforce_exclusive_group;
+use super::models::*;
+use super::queries::promote_derived_tag;
+use super::tags::{get_or_create_tag_path, resolve_tag_name};
+use super::Database;
+use crate::error::{AppError, Result};
+use chrono::{NaiveDate, Utc};
+use rusqlite::params;
+use rusqlite::types::Value;
+
+impl Database {
+    /// 设置文件上键值标签的值（如 rating=4），文件尚无该标签时一并添加
+    ///
+    /// `value` 为 None 时清除值、保留标签。键名按别名解析，与普通标签共用同一个标签。
+    pub fn set_tag_value(&self, file_id: i64, key: &str, value: Option<&TagValue>) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let key = resolve_tag_name(&tx, key)?;
+        if key.is_empty() {
+            return Err(AppError::InvalidTag("标签名不能为空".to_string()));
+        }
+        let tag_id = get_or_create_tag_path(&tx, &key, &TagType::Custom, "#007ACC")?;
+
+        let rows_affected = tx.execute(
+            "INSERT OR IGNORE INTO file_tags (file_id, tag_id, is_auto, created_at) VALUES (?1, ?2, 0, ?3)",
+            params![file_id, tag_id, Utc::now().timestamp()],
+        )?;
+        if rows_affected == 0 {
+            promote_derived_tag(&tx, file_id, tag_id, false)?;
+        }
+        enforce_exclusive_group(&tx, file_id, tag_id)?;
+
+        tx.execute(
+            "UPDATE file_tags SET value = ?3, value_type = ?4 WHERE file_id = ?1 AND tag_id = ?2",
+            params![
+                file_id,
+                tag_id,
+                value.map(|v| v.to_db_string()),
+                value.map(|v| v.type_name()),
+            ],
+        )?;
+
+        sync_derived_tags(&tx, &[file_id])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 列出键值标签的所有取值及使用次数（仅统计活跃文件），按使用次数降序
+    pub fn get_tag_values(&self, key: &str) -> Result<Vec<TagValueCount>> {
+        let conn = self.conn.lock();
+        let key = resolve_tag_name(&conn, key)?;
+
+        let mut stmt = conn.prepare(
+            "SELECT ft.value_type, ft.value, COUNT(*) AS count
+             FROM file_tags ft
+             JOIN tags t ON ft.tag_id = t.id
+             JOIN files f ON ft.file_id = f.id
+             WHERE t.name = ?1 AND ft.value IS NOT NULL AND f.status = 'active'
+             GROUP BY ft.value_type, ft.value
+             ORDER BY count DESC, ft.value",
+        )?;
+
+        let mut values = Vec::new();
+        let mut rows = stmt.query(params![key])?;
+        while let Some(row) = rows.next()? {
+            let value_type: String = row.get(0)?;
+            let value: String = row.get(1)?;
+            if let Some(value) = TagValue::from_db(&value_type, &value) {
+                values.push(TagValueCount {
+                    value,
+                    count: row.get(2)?,
+                });
+            }
+        }
+
+        Ok(values)
+    }
+}
+
+/// 解析十进制数值（可带符号、小数和指数），不接受 `nan`、`inf` 等写法和溢出的值
+fn parse_number(value: &str) -> Option<f64> {
+    let body = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let (mantissa, exponent) = match body.find(['e', 'E']) {
+        Some(pos) => (&body[..pos], Some(&body[pos + 1..])),
+        None => (body, None),
+    };
+
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if integer.len() + fraction.len() == 0 || !digits(integer) || !digits(fraction) {
+        return None;
+    }
+    if let Some(exponent) = exponent {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        if exponent.is_empty() || !digits(exponent) {
+            return None;
+        }
+    }
+
+    value.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// 构建键值标签过滤子句（匹配 `f.id`），多个条件之间为 AND，返回子句和按顺序绑定的参数
+pub(super) fn attribute_filter_sql(
+    conn: &rusqlite::Connection,
+    filters: &[AttributeFilter],
+) -> Result<Option<(String, Vec<Value>)>> {
+    if filters.is_empty() {
+        return Ok(None);
+    }
+
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+
+    for filter in filters {
+        values.push(Value::Text(resolve_tag_name(conn, &filter.key)?));
+
+        let op = filter.op.as_sql();
+        let comparison = if let Some(number) = parse_number(&filter.value) {
+            values.push(Value::Real(number));
+            format!("ft.value_type = 'number' AND CAST(ft.value AS REAL) {} ?", op)
+        } else if let Ok(date) = NaiveDate::parse_from_str(&filter.value, "%Y-%m-%d") {
+            values.push(Value::Text(date.format("%Y-%m-%d").to_string()));
+            format!("ft.value_type = 'date' AND ft.value {} ?", op)
+        } else {
+            values.push(Value::Text(filter.value.clone()));
+            format!("ft.value_type = 'string' AND ft.value COLLATE NOCASE {} ?", op)
+        };
+
+        clauses.push(format!(
+            "EXISTS (SELECT 1 FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+                     WHERE ft.file_id = f.id AND t.name = ? AND {})",
+            comparison
+        ));
+    }
+
+    Ok(Some((clauses.join(" AND "), values)))
+}
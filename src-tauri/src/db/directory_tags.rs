@@ -0,0 +1,203 @@
+use super::groups::blocked_by_direct_tag;
+use super::implications::sync_implied_tags;
+use super::models::*;
+use super::tags::{get_or_create_tag_path, resolve_tag_name};
+use super::Database;
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::collections::HashMap;
+
+impl Database {
+    /// 为目录添加标签，目录下所有已索引文件（包括子目录中的）继承该标签
+    pub fn add_directory_tag(&self, path: &str, tag_name: &str) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let path = normalize_directory_path(path)?;
+        let tag_name = resolve_tag_name(&tx, tag_name)?;
+        if tag_name.is_empty() {
+            return Err(AppError::InvalidTag("标签名不能为空".to_string()));
+        }
+        let tag_id = get_or_create_tag_path(&tx, &tag_name, &TagType::Custom, "#007ACC")?;
+
+        tx.execute(
+            "INSERT OR IGNORE INTO directory_tags (path, tag_id, created_at) VALUES (?1, ?2, ?3)",
+            params![path, tag_id, Utc::now().timestamp()],
+        )?;
+
+        sync_directory_files(&tx, &path)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 移除目录标签，目录下文件继承的该标签随之移除（文件上直接添加的不受影响）
+    pub fn remove_directory_tag(&self, path: &str, tag_id: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let path = normalize_directory_path(path)?;
+        tx.execute(
+            "DELETE FROM directory_tags WHERE path = ?1 AND tag_id = ?2",
+            params![path, tag_id],
+        )?;
+
+        sync_directory_files(&tx, &path)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 获取目录标签，指定 path 时只返回该目录自身的标签
+    pub fn get_directory_tags(&self, path: Option<&str>) -> Result<Vec<DirectoryTag>> {
+        let conn = self.conn.lock();
+        let path = path.map(normalize_directory_path).transpose()?;
+
+        let mut stmt = conn.prepare(
+            "SELECT d.path, d.created_at, t.id, t.name, t.display_name, t.tag_type, t.color, t.icon, t.use_count, t.created_at, t.parent_id
+             FROM directory_tags d JOIN tags t ON d.tag_id = t.id
+             WHERE ?1 IS NULL OR d.path = ?1
+             ORDER BY d.path, t.name",
+        )?;
+
+        let mut tags = Vec::new();
+        let mut rows = stmt.query(params![path])?;
+        while let Some(row) = rows.next()? {
+            let created_at_ts: i64 = row.get(1)?;
+            let tag_type: String = row.get(5)?;
+            let tag_created_at_ts: i64 = row.get(9)?;
+            tags.push(DirectoryTag {
+                path: row.get(0)?,
+                tag: Tag {
+                    id: Some(row.get(2)?),
+                    name: row.get(3)?,
+                    display_name: row.get(4)?,
+                    tag_type: TagType::from_str(&tag_type),
+                    color: row.get(6)?,
+                    icon: row.get(7)?,
+                    use_count: row.get(8)?,
+                    created_at: DateTime::from_timestamp(tag_created_at_ts, 0)
+                        .ok_or_else(|| AppError::Unknown(format!("无效的时间戳: {}", tag_created_at_ts)))?,
+                    parent_id: row.get(10)?,
+                    value: None,
+                    inherited_from: None,
+                    implied_by: None,
+                },
+                created_at: DateTime::from_timestamp(created_at_ts, 0)
+                    .ok_or_else(|| AppError::Unknown(format!("无效的时间戳: {}", created_at_ts)))?,
+            });
+        }
+
+        Ok(tags)
+    }
+}
+
+/// 按目录标签重新计算文件继承的标签（供事务内使用）
+///
+/// 文件继承所有上级目录的标签，同一标签来自多级目录时记录最近的目录。文件上已直接添加的标签
+/// 保持不变，已作为推导标签存在的改为继承标签；不再适用的继承标签（如文件已移出目录、
+/// 文件已直接添加了同一互斥分组的标签）被移除。调用方在此之后应重新计算推导标签，
+/// 继承标签同样可以作为推导来源，一般直接使用 `sync_derived_tags`。
+fn sync_inherited_tags(conn: &rusqlite::Connection, file_ids: &[i64]) -> Result<()> {
+    let mut dir_stmt = conn.prepare(
+        "SELECT d.tag_id, d.path FROM directory_tags d JOIN files f ON f.id = ?1
+         WHERE substr(f.path, 1, length(d.path) + 1) IN (d.path || '/', d.path || '\\')
+         ORDER BY length(d.path)",
+    )?;
+    // 继承标签和推导标签，推导标签的 inherited_from 为 NULL
+    let mut current_stmt = conn.prepare(
+        "SELECT tag_id, inherited_from FROM file_tags
+         WHERE file_id = ?1 AND (inherited_from IS NOT NULL OR implied_by IS NOT NULL)",
+    )?;
+    let mut direct_stmt = conn.prepare(
+        "SELECT tag_id FROM file_tags WHERE file_id = ?1 AND inherited_from IS NULL AND implied_by IS NULL",
+    )?;
+
+    for &file_id in file_ids {
+        // 按目录深度升序读取，较深的目录覆盖较浅的目录
+        let mut inherited: HashMap<i64, String> = dir_stmt
+            .query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        // 与直接添加的标签同属互斥分组时让位于直接标签
+        let mut blocked = Vec::new();
+        for &tag_id in inherited.keys() {
+            if blocked_by_direct_tag(conn, file_id, tag_id)? {
+                blocked.push(tag_id);
+            }
+        }
+        for tag_id in blocked {
+            inherited.remove(&tag_id);
+        }
+        let current: HashMap<i64, Option<String>> = current_stmt
+            .query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        let direct: Vec<i64> = direct_stmt
+            .query_map(params![file_id], |row| row.get(0))?
+            .collect::<std::result::Result<_, _>>()?;
+
+        let stale = current
+            .iter()
+            .filter(|(id, from)| from.is_some() && !inherited.contains_key(id));
+        for (tag_id, _) in stale {
+            conn.execute(
+                "DELETE FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
+                params![file_id, tag_id],
+            )?;
+        }
+
+        for (tag_id, dir) in inherited.iter().filter(|(id, _)| !direct.contains(id)) {
+            match current.get(tag_id) {
+                Some(Some(existing)) if existing == dir => {}
+                Some(_) => {
+                    conn.execute(
+                        "UPDATE file_tags SET inherited_from = ?3, implied_by = NULL WHERE file_id = ?1 AND tag_id = ?2",
+                        params![file_id, tag_id, dir],
+                    )?;
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO file_tags (file_id, tag_id, is_auto, created_at, inherited_from)
+                         VALUES (?1, ?2, 1, ?3, ?4)",
+                        params![file_id, tag_id, Utc::now().timestamp(), dir],
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 重新计算文件的继承标签和推导标签（供事务内使用）
+///
+/// 文件的直接标签变化后也需要调用：互斥分组中让位于直接标签的继承标签随之移除或恢复。
+pub(super) fn sync_derived_tags(conn: &rusqlite::Connection, file_ids: &[i64]) -> Result<()> {
+    sync_inherited_tags(conn, file_ids)?;
+    sync_implied_tags(conn, file_ids)
+}
+
+/// 重新计算目录下所有文件的继承标签和推导标签（供事务内使用）
+fn sync_directory_files(conn: &rusqlite::Connection, path: &str) -> Result<()> {
+    let file_ids: Vec<i64> = {
+        let mut stmt = conn.prepare(
+            "SELECT id FROM files WHERE substr(path, 1, length(?1) + 1) IN (?1 || '/', ?1 || '\\')",
+        )?;
+        let ids = stmt
+            .query_map(params![path], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        ids
+    };
+
+    sync_derived_tags(conn, &file_ids)
+}
+
+/// 规范化目录路径：去除首尾空白和末尾的路径分隔符
+fn normalize_directory_path(path: &str) -> Result<String> {
+    let path = path.trim().trim_end_matches(['/', '\\']);
+    if path.is_empty() {
+        return Err(AppError::InvalidTag("目录路径不能为空".to_string()));
+    }
+
+    Ok(path.to_string())
+}
@@ -0,0 +1,121 @@
+use super::models::*;
+use super::search::quote_fts;
+use crate::error::Result;
+use rusqlite::params;
+
+/// 每个关键字最多取多少个三元组候选文件计算编辑距离
+const CANDIDATE_LIMIT: i64 = 500;
+
+/// 模糊搜索的候选文件及其与关键字的编辑距离
+///
+/// 先用文件名三元组索引找出至少包含关键字一个三元组的文件（按共有三元组多少排序），
+/// 再计算关键字与文件名中最相近的一段之间的编辑距离，只保留不超过允许距离的文件。
+/// 少于 3 个字符的关键字没有三元组，不做模糊匹配。
+pub(super) fn fuzzy_candidates(conn: &rusqlite::Connection, keyword: &str, max_distance: usize) -> Result<Vec<(i64, usize)>> {
+    let grams = trigrams(keyword);
+    if grams.is_empty() {
+        return Ok(Vec::new());
+    }
+    let max_distance = allowed_distance(keyword, max_distance);
+    let fts = grams.iter().map(|g| quote_fts(g)).collect::<Vec<_>>().join(" OR ");
+
+    let mut stmt = conn.prepare(
+        "SELECT file_id, file_name FROM file_name_trigrams
+         WHERE file_name_trigrams MATCH ?1
+         ORDER BY rank LIMIT ?2",
+    )?;
+    let mut rows = stmt.query(params![fts, CANDIDATE_LIMIT])?;
+
+    let mut candidates = Vec::new();
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(1)?;
+        if let Some(m) = approximate_match(keyword, &name, max_distance) {
+            candidates.push((row.get(0)?, m.distance));
+        }
+    }
+    Ok(candidates)
+}
+
+/// 计算各关键字在文件名中的匹配区间，重叠的区间只保留靠前的一个
+pub(super) fn highlights(name: &str, terms: &[String], max_distance: usize) -> Vec<MatchHighlight> {
+    let mut found: Vec<MatchHighlight> = terms
+        .iter()
+        .filter_map(|term| approximate_match(term, name, allowed_distance(term, max_distance)))
+        .collect();
+    found.sort_by_key(|h| (h.start, h.distance));
+
+    let mut merged: Vec<MatchHighlight> = Vec::new();
+    for highlight in found {
+        if merged.last().is_none_or(|last| highlight.start >= last.end) {
+            merged.push(highlight);
+        }
+    }
+    merged
+}
+
+/// 关键字实际允许的编辑距离：每 3 个字符最多 1 处差异，避免短关键字匹配到任意文件名
+fn allowed_distance(keyword: &str, max_distance: usize) -> usize {
+    max_distance.min(keyword.chars().count() / 3)
+}
+
+/// 关键字的三元组（小写，去重）
+fn trigrams(keyword: &str) -> Vec<String> {
+    let chars = lowercase_chars(keyword.trim());
+    let mut grams: Vec<String> = chars.windows(3).map(|w| w.iter().collect()).collect();
+    grams.sort();
+    grams.dedup();
+    grams
+}
+
+/// 逐字符转小写，保持字符下标不变
+fn lowercase_chars(text: &str) -> Vec<char> {
+    text.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect()
+}
+
+/// 在 `text` 中找与 `pattern` 编辑距离最小的一段（不区分大小写）
+///
+/// 使用 Sellers 算法：`text` 中任意位置开始的匹配代价为 0，返回距离不超过 `max_distance`
+/// 的最佳匹配，距离相同时取最靠前的一段。
+pub(super) fn approximate_match(pattern: &str, text: &str, max_distance: usize) -> Option<MatchHighlight> {
+    let pattern = lowercase_chars(pattern.trim());
+    let text = lowercase_chars(text);
+    if pattern.is_empty() {
+        return None;
+    }
+
+    // previous[i]：pattern 前 i 个字符与以上一位置结尾的某段文本的最小距离，starts 记录该段的起点
+    let mut previous: Vec<usize> = (0..=pattern.len()).collect();
+    let mut previous_starts = vec![0; pattern.len() + 1];
+    let mut current = vec![0; pattern.len() + 1];
+    let mut current_starts = vec![0; pattern.len() + 1];
+    let mut best: Option<MatchHighlight> = None;
+
+    for (j, &c) in text.iter().enumerate() {
+        current[0] = 0;
+        current_starts[0] = j + 1;
+        for i in 1..=pattern.len() {
+            let substitute = previous[i - 1] + usize::from(pattern[i - 1] != c);
+            let skip_pattern = current[i - 1] + 1;
+            let skip_text = previous[i] + 1;
+
+            (current[i], current_starts[i]) = if substitute <= skip_pattern && substitute <= skip_text {
+                (substitute, previous_starts[i - 1])
+            } else if skip_pattern <= skip_text {
+                (skip_pattern, current_starts[i - 1])
+            } else {
+                (skip_text, previous_starts[i])
+            };
+        }
+
+        let distance = current[pattern.len()];
+        let start = current_starts[pattern.len()];
+        if distance <= max_distance && start <= j && best.is_none_or(|b| distance < b.distance) {
+            best = Some(MatchHighlight { start, end: j + 1, distance });
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+        std::mem::swap(&mut previous_starts, &mut current_starts);
+    }
+
+    best
+}
@@ -0,0 +1,257 @@
+use super::models::*;
+use super::tags::tag_name_and_parent;
+use super::Database;
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+
+impl Database {
+    /// 获取所有标签分组
+    pub fn get_tag_groups(&self) -> Result<Vec<TagGroup>> {
+        let conn = self.conn.lock();
+
+        let mut stmt = conn.prepare(
+            "SELECT id, name, exclusive, created_at FROM tag_groups ORDER BY name",
+        )?;
+
+        let mut groups = Vec::new();
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            groups.push(row_to_group(row)?);
+        }
+
+        Ok(groups)
+    }
+
+    /// 创建标签分组
+    ///
+    /// 已存在的 `分组名:值` 形式的标签（如 `status:done`）自动归入新分组。
+    pub fn create_tag_group(&self, name: &str, exclusive: bool) -> Result<i64> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let name = validate_group_name(&tx, name, None)?;
+        tx.execute(
+            "INSERT INTO tag_groups (name, exclusive, created_at) VALUES (?1, ?2, ?3)",
+            params![name, exclusive as i32, Utc::now().timestamp()],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        tx.execute(
+            "UPDATE tags SET group_id = ?1
+             WHERE group_id IS NULL AND lower(substr(name, 1, length(?2) + 1)) = lower(?2 || ':')",
+            params![id, name],
+        )?;
+
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    /// 修改分组名称或互斥设置
+    ///
+    /// 改为互斥不会修改已有的文件标签，只约束之后添加的标签。
+    pub fn update_tag_group(&self, group_id: i64, name: &str, exclusive: bool) -> Result<()> {
+        let conn = self.conn.lock();
+
+        group_by_id(&conn, group_id)?;
+        let name = validate_group_name(&conn, name, Some(group_id))?;
+        conn.execute(
+            "UPDATE tag_groups SET name = ?1, exclusive = ?2 WHERE id = ?3",
+            params![name, exclusive as i32, group_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// 删除分组，分组内的标签保留并变为未分组
+    pub fn delete_tag_group(&self, group_id: i64) -> Result<()> {
+        let conn = self.conn.lock();
+
+        conn.execute("DELETE FROM tag_groups WHERE id = ?1", params![group_id])?;
+
+        Ok(())
+    }
+
+    /// 设置标签所属分组，`group_id` 为 None 时移出分组
+    pub fn set_tag_group(&self, tag_id: i64, group_id: Option<i64>) -> Result<()> {
+        let conn = self.conn.lock();
+
+        tag_name_and_parent(&conn, tag_id)?;
+        if let Some(group_id) = group_id {
+            group_by_id(&conn, group_id)?;
+        }
+        conn.execute(
+            "UPDATE tags SET group_id = ?1 WHERE id = ?2",
+            params![group_id, tag_id],
+        )?;
+
+        Ok(())
+    }
+
+    /// 按分组获取所有标签（供标签面板使用）
+    ///
+    /// 分组按名称排序，未分组的标签排在最后；组内标签按使用次数降序。
+    pub fn get_grouped_tags(&self) -> Result<Vec<GroupedTags>> {
+        let groups = self.get_tag_groups()?;
+        let tags = self.get_all_tags()?;
+
+        let memberships: HashMap<i64, i64> = {
+            let conn = self.conn.lock();
+            let mut stmt = conn.prepare("SELECT id, group_id FROM tags WHERE group_id IS NOT NULL")?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<std::result::Result<HashMap<_, _>, _>>()?;
+            rows
+        };
+        let group_of = |tag: &Tag| tag.id.and_then(|id| memberships.get(&id).copied());
+
+        let mut grouped: Vec<GroupedTags> = groups
+            .into_iter()
+            .map(|group| GroupedTags { group: Some(group), tags: Vec::new() })
+            .collect();
+        let mut ungrouped = Vec::new();
+
+        for tag in tags {
+            let slot = group_of(&tag).and_then(|group_id| {
+                grouped
+                    .iter_mut()
+                    .find(|g| g.group.as_ref().and_then(|g| g.id) == Some(group_id))
+            });
+            match slot {
+                Some(slot) => slot.tags.push(tag),
+                None => ungrouped.push(tag),
+            }
+        }
+
+        if !ungrouped.is_empty() {
+            grouped.push(GroupedTags { group: None, tags: ungrouped });
+        }
+
+        Ok(grouped)
+    }
+}
+
+/// 直接添加标签后执行分组互斥：标签属于互斥分组时，移除文件上同组的其他直接标签（供事务内使用）
+///
+/// 推导和继承的标签不在此移除，重新计算时它们让位于直接标签（见 `blocked_by_direct_tag`），
+/// 否则来源仍在时下一次重新计算又会把它们加回来。
+pub(super) fn enforce_exclusive_group(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<()> {
+    remove_conflicting_tags(conn, file_id, tag_id, None)
+}
+
+/// 添加自动标签后执行分组互斥：只移除同组的其他自动标签，手动标签保持不变（供事务内使用）
+pub(super) fn enforce_exclusive_auto_group(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<()> {
+    remove_conflicting_tags(conn, file_id, tag_id, Some(true))
+}
+
+/// 文件上是否已直接添加了与该标签同属互斥分组的其他标签（供事务内使用）
+///
+/// 直接标签优先：此时该标签不再作为推导或继承标签添加到文件上。
+pub(super) fn blocked_by_direct_tag(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<bool> {
+    Ok(!conflicting_tags(conn, file_id, tag_id, None)?.is_empty())
+}
+
+/// 文件上是否已手动添加了与该标签同属互斥分组的其他标签（供事务内使用）
+///
+/// 手动标签优先：此时规则不再把该标签作为自动标签添加到文件上。
+pub(super) fn blocked_by_manual_tag(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<bool> {
+    Ok(!conflicting_tags(conn, file_id, tag_id, Some(false))?.is_empty())
+}
+
+/// 移除文件上与该标签同属互斥分组的其他直接标签，`is_auto` 为 Some 时只移除对应来源的标签
+fn remove_conflicting_tags(
+    conn: &rusqlite::Connection,
+    file_id: i64,
+    tag_id: i64,
+    is_auto: Option<bool>,
+) -> Result<()> {
+    for other in conflicting_tags(conn, file_id, tag_id, is_auto)? {
+        conn.execute(
+            "DELETE FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
+            params![file_id, other],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// 文件上与该标签同属互斥分组的其他直接标签，`is_auto` 为 Some 时只包含对应来源的标签
+fn conflicting_tags(
+    conn: &rusqlite::Connection,
+    file_id: i64,
+    tag_id: i64,
+    is_auto: Option<bool>,
+) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT ft.tag_id FROM file_tags ft
+         JOIN tags t ON ft.tag_id = t.id
+         JOIN tags added ON added.id = ?2
+         JOIN tag_groups g ON added.group_id = g.id
+         WHERE ft.file_id = ?1 AND ft.tag_id != ?2 AND t.group_id = g.id AND g.exclusive = 1
+           AND ft.implied_by IS NULL AND ft.inherited_from IS NULL
+           AND (?3 IS NULL OR ft.is_auto = ?3)",
+    )?;
+    let ids = stmt
+        .query_map(params![file_id, tag_id, is_auto], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<i64>, _>>()?;
+
+    Ok(ids)
+}
+
+/// `分组名:值` 形式的标签名所属的分组
+pub(super) fn group_for_tag_name(conn: &rusqlite::Connection, name: &str) -> Result<Option<i64>> {
+    let Some((prefix, _)) = name.split_once(':') else {
+        return Ok(None);
+    };
+
+    Ok(conn.query_row(
+        "SELECT id FROM tag_groups WHERE name = ?1",
+        params![prefix.trim()],
+        |row| row.get(0),
+    ).optional()?)
+}
+
+/// 校验分组名：非空、不含 `:` 且不与其他分组重名，返回去除首尾空白后的名称
+fn validate_group_name(conn: &rusqlite::Connection, name: &str, current: Option<i64>) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(':') {
+        return Err(AppError::InvalidTag(format!("无效的分组名: '{}'", name)));
+    }
+
+    let existing: Option<i64> = conn.query_row(
+        "SELECT id FROM tag_groups WHERE name = ?1",
+        params![name],
+        |row| row.get(0),
+    ).optional()?;
+    if existing.is_some() && existing != current {
+        return Err(AppError::TagConflict(format!("分组 {}", name)));
+    }
+
+    Ok(name.to_string())
+}
+
+/// 按 ID 读取分组
+fn group_by_id(conn: &rusqlite::Connection, group_id: i64) -> Result<TagGroup> {
+    conn.query_row(
+        "SELECT id, name, exclusive, created_at FROM tag_groups WHERE id = ?1",
+        params![group_id],
+        |row| Ok(row_to_group(row)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::InvalidTag(format!("标签分组不存在: {}", group_id)))?
+}
+
+/// 将数据库行转换为 TagGroup
+fn row_to_group(row: &rusqlite::Row) -> Result<TagGroup> {
+    let created_at_ts: i64 = row.get(3)?;
+
+    Ok(TagGroup {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        exclusive: row.get::<_, i32>(2)? != 0,
+        created_at: DateTime::from_timestamp(created_at_ts, 0)
+            .ok_or_else(|| AppError::Unknown(format!("无效的时间戳: {}", created_at_ts)))?,
+    })
+}
@@ -0,0 +1,219 @@
+use super::groups::blocked_by_direct_tag;
+use super::models::*;
+use super::tags::tag_name_and_parent;
+use super::Database;
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::params;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+impl Database {
+    /// 添加推导规则：带有 `tag_id` 的文件自动获得 `implied_tag_id`
+    ///
+    /// 规则不能形成循环（包括经由其他规则间接形成的循环）。添加后立即应用到已有该标签的文件。
+    pub fn add_tag_implication(&self, tag_id: i64, implied_tag_id: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let (name, _) = tag_name_and_parent(&tx, tag_id)?;
+        tag_name_and_parent(&tx, implied_tag_id)?;
+
+        if tag_id == implied_tag_id {
+            return Err(AppError::InvalidTag(format!("标签 '{}' 不能推导自身", name)));
+        }
+
+        // implied 已能推导出 tag 时，新规则会形成循环
+        if let Some(path) = implication_path(&tx, implied_tag_id, tag_id)? {
+            let mut cycle = vec![name];
+            for id in path {
+                cycle.push(tag_name_and_parent(&tx, id)?.0);
+            }
+            return Err(AppError::InvalidTag(format!("推导规则形成循环: {}", cycle.join(" → "))));
+        }
+
+        tx.execute(
+            "INSERT OR IGNORE INTO tag_implications (tag_id, implied_tag_id, created_at) VALUES (?1, ?2, ?3)",
+            params![tag_id, implied_tag_id, Utc::now().timestamp()],
+        )?;
+
+        let files = files_with_tag(&tx, tag_id)?;
+        sync_implied_tags(&tx, &files)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 删除推导规则，并移除仅由该规则推导出的标签
+    pub fn remove_tag_implication(&self, tag_id: i64, implied_tag_id: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM tag_implications WHERE tag_id = ?1 AND implied_tag_id = ?2",
+            params![tag_id, implied_tag_id],
+        )?;
+
+        let files = files_with_tag(&tx, implied_tag_id)?;
+        sync_implied_tags(&tx, &files)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 获取推导规则，指定 tag_id 时只返回以该标签为来源的规则
+    pub fn get_tag_implications(&self, tag_id: Option<i64>) -> Result<Vec<TagImplication>> {
+        let conn = self.conn.lock();
+
+        let mut stmt = conn.prepare(
+            "SELECT i.tag_id, t.name, i.implied_tag_id, it.name, i.created_at
+             FROM tag_implications i
+             JOIN tags t ON i.tag_id = t.id
+             JOIN tags it ON i.implied_tag_id = it.id
+             WHERE ?1 IS NULL OR i.tag_id = ?1
+             ORDER BY t.name, it.name",
+        )?;
+
+        let mut implications = Vec::new();
+        let mut rows = stmt.query(params![tag_id])?;
+        while let Some(row) = rows.next()? {
+            let created_at_ts: i64 = row.get(4)?;
+            implications.push(TagImplication {
+                tag_id: row.get(0)?,
+                tag_name: row.get(1)?,
+                implied_tag_id: row.get(2)?,
+                implied_tag_name: row.get(3)?,
+                created_at: DateTime::from_timestamp(created_at_ts, 0)
+                    .ok_or_else(|| AppError::Unknown(format!("无效的时间戳: {}", created_at_ts)))?,
+            });
+        }
+
+        Ok(implications)
+    }
+}
+
+/// 按推导规则重新计算文件的推导标签（供事务内使用）
+///
+/// 从文件的非推导标签（手动或自动）出发沿推导规则求闭包：缺少的标签以推导标签（`implied_by`
+/// 记录直接来源标签）加入，闭包外的推导标签移除。已直接存在的标签保持不变；与直接标签同属
+/// 互斥分组的标签不会被推导出来，也不再由它继续推导。
+pub(super) fn sync_implied_tags(conn: &rusqlite::Connection, file_ids: &[i64]) -> Result<()> {
+    for &file_id in file_ids {
+        let explicit: Vec<i64> = {
+            let mut stmt = conn.prepare(
+                "SELECT tag_id FROM file_tags WHERE file_id = ?1 AND implied_by IS NULL",
+            )?;
+            let ids = stmt
+                .query_map(params![file_id], |row| row.get(0))?
+                .collect::<std::result::Result<Vec<i64>, _>>()?;
+            ids
+        };
+        let current: HashMap<i64, i64> = {
+            let mut stmt = conn.prepare(
+                "SELECT tag_id, implied_by FROM file_tags WHERE file_id = ?1 AND implied_by IS NOT NULL",
+            )?;
+            let rows = stmt
+                .query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<std::result::Result<HashMap<i64, i64>, _>>()?;
+            rows
+        };
+
+        let implied = implied_closure(conn, file_id, &explicit)?;
+
+        for (&tag_id, _) in current.iter().filter(|(id, _)| !implied.contains_key(id)) {
+            conn.execute(
+                "DELETE FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
+                params![file_id, tag_id],
+            )?;
+        }
+
+        for (&tag_id, &source_id) in &implied {
+            match current.get(&tag_id) {
+                Some(&existing) if existing == source_id => {}
+                Some(_) => {
+                    conn.execute(
+                        "UPDATE file_tags SET implied_by = ?3 WHERE file_id = ?1 AND tag_id = ?2",
+                        params![file_id, tag_id, source_id],
+                    )?;
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO file_tags (file_id, tag_id, is_auto, created_at, implied_by)
+                         VALUES (?1, ?2, 1, ?3, ?4)",
+                        params![file_id, tag_id, Utc::now().timestamp(), source_id],
+                    )?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 关联了该标签的文件
+pub(super) fn files_with_tag(conn: &rusqlite::Connection, tag_id: i64) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT file_id FROM file_tags WHERE tag_id = ?1")?;
+    let ids = stmt
+        .query_map(params![tag_id], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<i64>, _>>()?;
+    Ok(ids)
+}
+
+/// 从文件的 `sources` 出发可推导出的标签（不含 `sources` 本身），值为推导出该标签的直接来源
+fn implied_closure(conn: &rusqlite::Connection, file_id: i64, sources: &[i64]) -> Result<HashMap<i64, i64>> {
+    let explicit: HashSet<i64> = sources.iter().copied().collect();
+    let mut implied = HashMap::new();
+    let mut queue: VecDeque<i64> = sources.iter().copied().collect();
+
+    let mut stmt = conn.prepare("SELECT implied_tag_id FROM tag_implications WHERE tag_id = ?1")?;
+    while let Some(tag_id) = queue.pop_front() {
+        let targets = stmt
+            .query_map(params![tag_id], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        for target in targets {
+            if explicit.contains(&target)
+                || implied.contains_key(&target)
+                || blocked_by_direct_tag(conn, file_id, target)?
+            {
+                continue;
+            }
+            implied.insert(target, tag_id);
+            queue.push_back(target);
+        }
+    }
+
+    Ok(implied)
+}
+
+/// 查找从 `from` 沿推导规则到达 `to` 的路径（含两端），不可达时返回 None
+fn implication_path(conn: &rusqlite::Connection, from: i64, to: i64) -> Result<Option<Vec<i64>>> {
+    let mut previous: HashMap<i64, i64> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+
+    let mut stmt = conn.prepare("SELECT implied_tag_id FROM tag_implications WHERE tag_id = ?1")?;
+    while let Some(tag_id) = queue.pop_front() {
+        if tag_id == to {
+            let mut path = vec![to];
+            let mut current = to;
+            while let Some(&prev) = previous.get(&current) {
+                path.push(prev);
+                current = prev;
+            }
+            path.reverse();
+            return Ok(Some(path));
+        }
+
+        let targets = stmt
+            .query_map(params![tag_id], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+        for target in targets {
+            if target != from && !previous.contains_key(&target) {
+                previous.insert(target, tag_id);
+                queue.push_back(target);
+            }
+        }
+    }
+
+    Ok(None)
+}
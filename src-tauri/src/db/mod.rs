@@ -1,5 +1,16 @@
+mod attributes;
+mod directory_tags;
+mod fuzzy;
+mod groups;
+mod implications;
 mod models;
 mod queries;
+mod query_parser;
+mod search;
+mod sql;
+mod suggestions;
+mod tags;
+mod tokenizer;
 
 #[cfg(test)]
 mod tests;
@@ -35,9 +46,17 @@ impl Database {
         // 启用 WAL 模式以提高并发性能（在 execute_batch 之前）
         let _ = conn.execute("PRAGMA journal_mode = WAL;", []);
 
+        // FTS 表使用自定义分词器，建表和读写前都需要先注册
+        tokenizer::register_cjk_tokenizer(&conn)?;
+
         // 使用 execute_batch 执行所有 SQL 语句
         conn.execute_batch(SCHEMA_SQL)?;
 
+        // 为旧版本数据库补充新增的列
+        migrate_columns(&conn)?;
+        migrate_tag_types(&conn)?;
+        migrate_tag_counts(&conn)?;
+
         // 检查是否需要重建 FTS 表以支持中文分词
         // 检查 FTS 表的分词器配置（通过尝试查询表结构判断）
         // 如果需要更新分词器配置，重建 FTS 表
@@ -46,7 +65,7 @@ impl Database {
             [],
             |row| {
                 let sql: String = row.get(0)?;
-                Ok(!sql.contains("tokenize=\"unicode61\""))
+                Ok(!sql.contains("tokenize=\"cjk\""))
             },
         ).unwrap_or(true);
 
@@ -71,7 +90,7 @@ impl Database {
                     file_name,
                     file_path,
                     tag_names,
-                    tokenize="unicode61"
+                    tokenize="cjk"
                 );
                 "#,
             )?;
@@ -167,6 +186,30 @@ impl Database {
             )?;
         }
 
+        // 旧版本数据库没有文件名三元组索引，建表后补充现有文件
+        let trigram_count: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM file_name_trigrams",
+            [],
+            |row| row.get(0),
+        ).unwrap_or(0);
+
+        if file_count > 0 && trigram_count == 0 {
+            conn.execute("INSERT INTO file_name_trigrams(file_id, file_name) SELECT id, name FROM files", [])?;
+        }
+
+        Ok(Database {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// 创建内存数据库（仅用于测试）
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        tokenizer::register_cjk_tokenizer(&conn)?;
+        conn.execute_batch(SCHEMA_SQL)?;
+        migrate_columns(&conn)?;
+
         Ok(Database {
             conn: Mutex::new(conn),
         })
@@ -182,3 +225,124 @@ impl Database {
         Ok(app_dir.join("file_tags.db"))
     }
 }
+
+/// 列是否存在
+fn has_columns(conn: &Connection, table: &str, columns: &str) -> bool {
+    conn.prepare(&format!("SELECT {} FROM {} LIMIT 0", columns, table)).is_ok()
+}
+
+/// 迁移：为旧版本数据库补充后续新增的列及其索引
+///
+/// `CREATE TABLE IF NOT EXISTS` 不会修改已存在的表，新增列需要在这里逐一补充；
+/// 依赖新增列的索引也在这里创建，以保证旧数据库执行 schema.sql 时不会失败。
+fn migrate_columns(conn: &Connection) -> Result<()> {
+    // 自动标签的来源规则
+    if !has_columns(conn, "file_tags", "rule_name, rule_detail") {
+        conn.execute_batch(
+            r#"
+            ALTER TABLE file_tags ADD COLUMN rule_name TEXT;
+            ALTER TABLE file_tags ADD COLUMN rule_detail TEXT;
+            "#,
+        )?;
+    }
+
+    // 按目录启用标签提供者
+    if !has_columns(conn, "watched_directories", "tag_providers") {
+        conn.execute("ALTER TABLE watched_directories ADD COLUMN tag_providers TEXT", [])?;
+    }
+
+    // 层级标签
+    if !has_columns(conn, "tags", "parent_id") {
+        conn.execute(
+            "ALTER TABLE tags ADD COLUMN parent_id INTEGER REFERENCES tags(id) ON DELETE SET NULL",
+            [],
+        )?;
+    }
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_tags_parent_id ON tags(parent_id)", [])?;
+
+    // 推导标签的来源标签
+    if !has_columns(conn, "file_tags", "implied_by") {
+        conn.execute("ALTER TABLE file_tags ADD COLUMN implied_by INTEGER", [])?;
+    }
+
+    // 继承自目录标签的来源目录
+    if !has_columns(conn, "file_tags", "inherited_from") {
+        conn.execute("ALTER TABLE file_tags ADD COLUMN inherited_from TEXT", [])?;
+    }
+
+    // 标签分组
+    if !has_columns(conn, "tags", "group_id") {
+        conn.execute(
+            "ALTER TABLE tags ADD COLUMN group_id INTEGER REFERENCES tag_groups(id) ON DELETE SET NULL",
+            [],
+        )?;
+    }
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_tags_group_id ON tags(group_id)", [])?;
+
+    // 键值标签
+    if !has_columns(conn, "file_tags", "value, value_type") {
+        conn.execute_batch(
+            r#"
+            ALTER TABLE file_tags ADD COLUMN value TEXT;
+            ALTER TABLE file_tags ADD COLUMN value_type TEXT;
+            "#,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// 旧版本手动维护的 use_count 可能已有偏差：交由触发器接管前按实际关联校正一次，
+/// 完成后在 settings 中记录。
+fn migrate_tag_counts(conn: &Connection) -> Result<()> {
+    const MIGRATED_KEY: &str = "tag_counts_recounted";
+
+    let migrated: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM settings WHERE key = ?1)",
+        [MIGRATED_KEY],
+        |row| row.get(0),
+    )?;
+    if migrated {
+        return Ok(());
+    }
+
+    queries::recount_tag_usage(conn)?;
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at) VALUES (?1, 'true', strftime('%s', 'now'))",
+        [MIGRATED_KEY],
+    )?;
+
+    Ok(())
+}
+
+/// 旧版本手动添加标签时会误建为系统标签：将从未被自动添加过、且不是保留名称的系统标签改为
+/// 自定义标签。只执行一次，完成后在 settings 中记录。
+fn migrate_tag_types(conn: &Connection) -> Result<()> {
+    const MIGRATED_KEY: &str = "custom_tag_types_migrated";
+
+    let migrated: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM settings WHERE key = ?1)",
+        [MIGRATED_KEY],
+        |row| row.get(0),
+    )?;
+    if migrated {
+        return Ok(());
+    }
+
+    let placeholders = RESERVED_TAG_NAMES.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    conn.execute(
+        &format!(
+            "UPDATE tags SET tag_type = 'custom'
+             WHERE tag_type = 'system' AND name NOT IN ({})
+               AND NOT EXISTS (SELECT 1 FROM file_tags WHERE tag_id = tags.id AND is_auto = 1)",
+            placeholders
+        ),
+        rusqlite::params_from_iter(RESERVED_TAG_NAMES),
+    )?;
+    conn.execute(
+        "INSERT INTO settings (key, value, updated_at) VALUES (?1, 'true', strftime('%s', 'now'))",
+        [MIGRATED_KEY],
+    )?;
+
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 
@@ -39,8 +39,7 @@ impl FileType {
         }
     }
 
-    /// 获取文件类型的中文名称（预留功能）
-    #[allow(dead_code)]
+    /// 获取文件类型的中文名称
     pub fn display_name(&self) -> &'static str {
         match self {
             FileType::Image => "图片",
@@ -136,6 +135,21 @@ impl TagType {
     }
 }
 
+/// 系统保留的标签名（内置自动标签规则生成的标签），自定义标签不能使用
+///
+/// 须与 `tagger::default_rules` 生成的标签名保持一致（见 tagger 中的测试）。
+pub const RESERVED_TAG_NAMES: &[&str] = &[
+    "图片", "音频", "视频", "文本", "二进制",
+    "小文件", "大文件",
+    "今日文件", "本周文件", "本月文件",
+    "下载", "文档", "桌面", "图片文件夹", "音乐", "视频文件夹",
+];
+
+/// 是否为系统保留的标签名
+pub fn is_reserved_tag_name(name: &str) -> bool {
+    RESERVED_TAG_NAMES.contains(&name)
+}
+
 /// 标签信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -148,6 +162,161 @@ pub struct Tag {
     pub icon: Option<String>,
     pub use_count: i64,
     pub created_at: DateTime<Utc>,
+    /// 父标签 ID，层级标签的 name 为完整路径（如 `work/clientA/invoices`）
+    #[serde(default)]
+    pub parent_id: Option<i64>,
+    /// 键值标签在该文件上的值（仅在获取文件的标签时填充）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<TagValue>,
+    /// 继承自目录标签时为来源目录（仅在获取文件的标签时填充），直接添加的标签为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inherited_from: Option<String>,
+    /// 由推导规则添加时为来源标签 ID（仅在获取文件的标签时填充），直接添加的标签为 None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub implied_by: Option<i64>,
+}
+
+/// 目录标签，目录下的文件继承该标签
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DirectoryTag {
+    pub path: String,
+    pub tag: Tag,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 标签建议
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagSuggestion {
+    pub tag: Tag,
+    /// 综合得分，范围 0..=1
+    pub score: f64,
+    pub reasons: Vec<SuggestionReason>,
+}
+
+/// 推荐标签的依据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SuggestionReason {
+    /// 常与文件已有的标签一起出现：带有 `tag_name` 的文件中有 `ratio` 比例也带有该标签
+    #[serde(rename_all = "camelCase")]
+    CoOccurrence { tag_name: String, shared_files: i64, ratio: f64 },
+    /// 同目录下 `total` 个文件中有 `matched` 个带有该标签
+    #[serde(rename_all = "camelCase")]
+    SiblingFiles { matched: i64, total: i64 },
+    /// 名称相似的 `total` 个文件中有 `matched` 个带有该标签，`examples` 为部分文件名
+    #[serde(rename_all = "camelCase")]
+    SimilarNames { matched: i64, total: i64, examples: Vec<String> },
+}
+
+/// 键值标签的值，如 rating=4、status=review、due=2024-06-30
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum TagValue {
+    String(String),
+    Number(f64),
+    Date(NaiveDate),
+}
+
+impl TagValue {
+    /// 存入 file_tags.value_type 的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TagValue::String(_) => "string",
+            TagValue::Number(_) => "number",
+            TagValue::Date(_) => "date",
+        }
+    }
+
+    /// 存入 file_tags.value 的文本，日期为 `YYYY-MM-DD` 以便按文本比较
+    pub fn to_db_string(&self) -> String {
+        match self {
+            TagValue::String(s) => s.clone(),
+            TagValue::Number(n) => n.to_string(),
+            TagValue::Date(d) => d.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// 从数据库中的类型名和文本还原，无法解析时返回 None
+    pub fn from_db(value_type: &str, value: &str) -> Option<Self> {
+        match value_type {
+            "string" => Some(TagValue::String(value.to_string())),
+            "number" => value.parse().ok().map(TagValue::Number),
+            "date" => NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().map(TagValue::Date),
+            _ => None,
+        }
+    }
+}
+
+/// 键值标签的某个取值及使用该值的文件数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagValueCount {
+    pub value: TagValue,
+    pub count: i64,
+}
+
+/// 标签树节点
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagNode {
+    #[serde(flatten)]
+    pub tag: Tag,
+    pub children: Vec<TagNode>,
+}
+
+/// 标签分组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagGroup {
+    pub id: Option<i64>,
+    pub name: String,
+    /// 互斥分组内每个文件最多保留一个标签，添加新标签时移除同组的其他标签
+    pub exclusive: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 按分组归类的标签，group 为 None 表示未分组
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupedTags {
+    pub group: Option<TagGroup>,
+    pub tags: Vec<Tag>,
+}
+
+/// 标签改名结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum RenameTagResult {
+    /// 已改名
+    Renamed { tag: Tag },
+    /// 新名称已被占用，未做修改（可选择合并）
+    Conflict { existing: Tag },
+    /// 已合并到同名标签
+    Merged { tag: Tag },
+}
+
+/// 标签别名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagAlias {
+    pub alias: String,
+    pub tag_id: i64,
+    /// 规范标签名
+    pub tag_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 标签推导规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagImplication {
+    pub tag_id: i64,
+    pub tag_name: String,
+    pub implied_tag_id: i64,
+    pub implied_tag_name: String,
+    pub created_at: DateTime<Utc>,
 }
 
 /// 文件与标签的关联（预留功能）
@@ -160,6 +329,34 @@ pub struct FileTag {
     pub created_at: DateTime<Utc>,
 }
 
+/// 自动生成的标签及其来源规则
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoTag {
+    /// 标签名
+    pub name: String,
+    /// 产生该标签的规则名称
+    pub rule_name: String,
+    /// 规则条件的描述（如 "大小 ≥ 100MB"）
+    pub rule_detail: String,
+}
+
+/// 标签来源说明
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagExplanation {
+    pub file_id: i64,
+    pub tag_id: i64,
+    pub tag_name: String,
+    pub is_auto: bool,
+    pub rule_name: Option<String>,
+    pub rule_detail: Option<String>,
+    /// 推导出该标签的来源标签名，直接添加的标签为 None
+    pub implied_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// 面向用户的说明文字
+    pub message: String,
+}
+
 /// 监控目录信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -171,6 +368,8 @@ pub struct WatchedDirectory {
     pub enabled: bool,
     pub created_at: DateTime<Utc>,
     pub last_scanned_at: Option<DateTime<Utc>>,
+    /// 启用的标签提供者，None 表示启用全部
+    pub tag_providers: Option<Vec<String>>,
 }
 
 /// 目录过滤器
@@ -186,40 +385,174 @@ pub struct SearchResult {
     pub file: File,
     pub tags: Vec<Tag>,
     pub relevance: f32,
+    /// 关键字在文件名中的匹配区间
+    #[serde(default)]
+    pub highlights: Vec<MatchHighlight>,
 }
 
-/// 搜索查询参数
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 文件名中的匹配区间，位置为字符下标（从 0 开始，`end` 不含）
+///
+/// 字符指 Unicode 码点，与 `QueryParseError` 相同，前端需换算为 UTF-16 下标后再截取字符串。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MatchHighlight {
+    pub start: usize,
+    pub end: usize,
+    /// 与关键字的编辑距离，精确匹配为 0
+    pub distance: usize,
+}
+
+/// 模糊搜索选项，未指定的字段取默认值
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct FuzzyOptions {
+    /// 关键字与文件名之间允许的最大编辑距离
+    pub max_distance: usize,
+}
+
+impl Default for FuzzyOptions {
+    fn default() -> Self {
+        FuzzyOptions { max_distance: 2 }
+    }
+}
+
+/// 搜索查询：由搜索语句解析得到的表达式及分页参数
+///
+/// 语句语法见 `SearchQuery::parse`，`expr` 为 None 表示匹配全部文件。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct SearchQuery {
-    pub keywords: Vec<String>,
-    pub operator: SearchOperator,
-    pub file_type_filter: Option<FileType>,
-    pub tags: Option<Vec<String>>,
+    pub expr: Option<QueryExpr>,
     pub limit: usize,
     pub offset: usize,
+    /// 不为 None 时关键字同时按文件名模糊匹配，容忍拼写错误
+    #[serde(default)]
+    pub fuzzy: Option<FuzzyOptions>,
+}
+
+/// 搜索表达式
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum QueryExpr {
+    /// 所有子表达式都匹配（相邻的条件默认为 AND）
+    And(Vec<QueryExpr>),
+    /// 任一子表达式匹配
+    Or(Vec<QueryExpr>),
+    /// 不匹配子表达式（`-` 前缀）
+    Not(Box<QueryExpr>),
+    Term(QueryTerm),
+}
+
+/// 搜索条件
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum QueryTerm {
+    /// 关键字，匹配文件名、路径和标签名（标签别名同时匹配规范标签名）
+    Keyword(String),
+    /// 带引号的短语，按整体匹配
+    Phrase(String),
+    /// `tag:invoice`，包含子标签
+    Tag(String),
+    /// `type:image`
+    Type(FileType),
+    /// `ext:pdf`，不区分大小写，不含前导点
+    Extension(String),
+    /// `size:>10MB`，单位按 1024 进制换算为字节
+    Size { op: CompareOp, bytes: i64 },
+    /// `modified:2024-01..2024-06`，`from` 含当天、`until` 不含当天，省略表示不限
+    Modified { from: Option<NaiveDate>, until: Option<NaiveDate> },
+    /// `path:~/work`，绝对路径（含 `~`）按目录前缀匹配，否则按路径片段匹配
+    Path(String),
+    /// 键值标签条件，如 `rating>=4`
+    Attribute(AttributeFilter),
+}
+
+/// 搜索语句解析错误，位置为字符下标（从 0 开始，`end` 不含）
+///
+/// 字符指 Unicode 码点，既不是 UTF-8 字节也不是 UTF-16 码元：`"😀 )"` 中的 `)` 位于 2，
+/// 而在 JavaScript 字符串中位于 3。前端用 `toUtf16Range` 换算。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryParseError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
 }
 
-/// 搜索运算符
+impl std::fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "搜索语句第 {} 个字符处{}", self.start + 1, self.message)
+    }
+}
+
+impl std::error::Error for QueryParseError {}
+
+/// 键值标签比较运算符
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
-pub enum SearchOperator {
-    /// AND 运算：所有关键字都必须匹配
-    And,
-    /// OR 运算：任一关键字匹配即可
-    Or,
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
 }
 
-impl SearchOperator {
-    /// 获取运算符字符串（预留功能）
-    #[allow(dead_code)]
-    pub fn as_str(&self) -> &'static str {
+impl CompareOp {
+    /// 对应的 SQL 运算符
+    pub fn as_sql(&self) -> &'static str {
         match self {
-            SearchOperator::And => "AND",
-            SearchOperator::Or => "OR",
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
         }
     }
 }
 
+/// 键值标签过滤条件
+///
+/// 值为数字时只匹配数字类型的值，为 `YYYY-MM-DD` 时只匹配日期，否则按字符串（不区分大小写）比较。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributeFilter {
+    pub key: String,
+    pub op: CompareOp,
+    pub value: String,
+}
+
+impl AttributeFilter {
+    /// 解析 `rating>=4`、`status=review`、`status!=done` 形式的搜索词，不是键值条件时返回 None
+    pub fn parse(term: &str) -> Option<Self> {
+        let start = term.find(['=', '!', '<', '>'])?;
+        let (key, rest) = term.split_at(start);
+
+        let (op, len) = match rest.as_bytes() {
+            [b'>', b'=', ..] => (CompareOp::Ge, 2),
+            [b'<', b'=', ..] => (CompareOp::Le, 2),
+            [b'!', b'=', ..] => (CompareOp::Ne, 2),
+            [b'>', ..] => (CompareOp::Gt, 1),
+            [b'<', ..] => (CompareOp::Lt, 1),
+            [b'=', ..] => (CompareOp::Eq, 1),
+            _ => return None,
+        };
+
+        let key = key.trim();
+        let value = rest[len..].trim();
+        if key.is_empty() || value.is_empty() || key.contains(char::is_whitespace) {
+            return None;
+        }
+
+        Some(AttributeFilter {
+            key: key.to_string(),
+            op,
+            value: value.to_string(),
+        })
+    }
+}
+
 /// 搜索结果响应
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResultResponse {
@@ -1,16 +1,22 @@
+use super::directory_tags::sync_derived_tags;
+use super::groups::{blocked_by_manual_tag, enforce_exclusive_auto_group, enforce_exclusive_group};
 use super::models::*;
+use super::sql::SqlBuilder;
+use super::tags::{get_or_create_tag_path, insert_tag, resolve_tag_name, resolve_tag_names, tag_subtree_sql};
 use super::Database;
 use crate::error::{AppError, Result};
 use chrono::{DateTime, Utc};
 use rusqlite::{params, OptionalExtension};
+use std::collections::{HashMap, HashSet};
 
 impl Database {
-    /// 创建文件
+    /// 创建文件，并应用所在目录的目录标签
     pub fn create_file(&self, file: &File) -> Result<i64> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
         let now = Utc::now().timestamp();
 
-        conn.execute(
+        tx.execute(
             "INSERT INTO files (path, name, extension, size, file_type, created_at, modified_at, accessed_at, status, indexed_at)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
             params![
@@ -26,8 +32,46 @@ impl Database {
                 now,
             ],
         )?;
+        let id = tx.last_insert_rowid();
 
-        Ok(conn.last_insert_rowid())
+        sync_derived_tags(&tx, &[id])?;
+        tx.commit()?;
+
+        Ok(id)
+    }
+
+    /// 更新已索引文件的属性（重新索引时使用，保留文件 ID 及其标签）
+    ///
+    /// 路径变化（文件移动）时按新位置重新计算继承的目录标签。
+    pub fn update_file(&self, file: &File) -> Result<i64> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let id = file.id.ok_or_else(|| AppError::FileNotFound(file.path.clone()))?;
+        let now = Utc::now().timestamp();
+
+        tx.execute(
+            "UPDATE files SET path = ?1, name = ?2, extension = ?3, size = ?4, file_type = ?5, created_at = ?6,
+                    modified_at = ?7, accessed_at = ?8, status = ?9, indexed_at = ?10
+             WHERE id = ?11",
+            params![
+                file.path,
+                file.name,
+                file.extension,
+                file.size,
+                file.file_type.to_string(),
+                file.created_at.timestamp(),
+                file.modified_at.timestamp(),
+                file.accessed_at.timestamp(),
+                file.status.as_str(),
+                now,
+                id,
+            ],
+        )?;
+
+        sync_derived_tags(&tx, &[id])?;
+        tx.commit()?;
+
+        Ok(id)
     }
 
     /// 根据路径获取文件
@@ -37,7 +81,8 @@ impl Database {
         // 使用 JOIN 查询一次性获取文件及其标签
         let mut stmt = conn.prepare(
             "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata,
-                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at
+                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at, t.parent_id as tag_parent_id,
+                    ft.value_type as tag_value_type, ft.value as tag_value, ft.inherited_from as tag_inherited_from, ft.implied_by as tag_implied_by
              FROM files f
              LEFT JOIN file_tags ft ON f.id = ft.file_id
              LEFT JOIN tags t ON ft.tag_id = t.id
@@ -64,7 +109,8 @@ impl Database {
         // 使用 JOIN 查询一次性获取文件及其标签
         let mut stmt = conn.prepare(
             "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata,
-                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at
+                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at, t.parent_id as tag_parent_id,
+                    ft.value_type as tag_value_type, ft.value as tag_value, ft.inherited_from as tag_inherited_from, ft.implied_by as tag_implied_by
              FROM files f
              LEFT JOIN file_tags ft ON f.id = ft.file_id
              LEFT JOIN tags t ON ft.tag_id = t.id
@@ -113,7 +159,8 @@ impl Database {
         // 使用 JOIN 查询一次性获取文件及其标签
         let sql = if limit.is_some() || offset.is_some() {
             "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata,
-                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at
+                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at, t.parent_id as tag_parent_id,
+                    ft.value_type as tag_value_type, ft.value as tag_value, ft.inherited_from as tag_inherited_from, ft.implied_by as tag_implied_by
              FROM files f
              LEFT JOIN file_tags ft ON f.id = ft.file_id
              LEFT JOIN tags t ON ft.tag_id = t.id
@@ -121,7 +168,8 @@ impl Database {
              ORDER BY f.created_at DESC LIMIT ?1 OFFSET ?2"
         } else {
             "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata,
-                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at
+                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at, t.parent_id as tag_parent_id,
+                    ft.value_type as tag_value_type, ft.value as tag_value, ft.inherited_from as tag_inherited_from, ft.implied_by as tag_implied_by
              FROM files f
              LEFT JOIN file_tags ft ON f.id = ft.file_id
              LEFT JOIN tags t ON ft.tag_id = t.id
@@ -186,27 +234,17 @@ impl Database {
     }
 
     /// 创建标签
+    ///
+    /// 名称为路径（如 `work/clientA`）时自动创建缺失的上级标签并设置 parent_id。
     pub fn create_tag(&self, tag: &Tag) -> Result<i64> {
-        let conn = self.conn.lock();
-        let now = Utc::now().timestamp();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
 
-        let icon_value: Option<&str> = tag.icon.as_ref().map(|s| s.as_str());
+        let id = insert_tag(&tx, tag)?;
 
-        conn.execute(
-            "INSERT INTO tags (name, display_name, tag_type, color, icon, use_count, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                tag.name,
-                tag.display_name,
-                tag.tag_type.as_str(),
-                tag.color,
-                if let Some(icon) = icon_value { icon } else { "" },
-                tag.use_count,
-                now,
-            ],
-        )?;
+        tx.commit()?;
 
-        Ok(conn.last_insert_rowid())
+        Ok(id)
     }
 
     /// 获取所有标签
@@ -214,7 +252,7 @@ impl Database {
         let conn = self.conn.lock();
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at
+            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at, parent_id
              FROM tags ORDER BY use_count DESC, created_at DESC"
         )?;
 
@@ -227,12 +265,13 @@ impl Database {
         Ok(tags)
     }
 
-    /// 根据名称获取标签
+    /// 根据名称获取标签（预留功能）
+    #[allow(dead_code)]
     pub fn get_tag_by_name(&self, name: &str) -> Result<Option<Tag>> {
         let conn = self.conn.lock();
 
         let mut stmt = conn.prepare(
-            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at
+            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at, parent_id
              FROM tags WHERE name = ?1"
         )?;
 
@@ -250,7 +289,7 @@ impl Database {
         let conn = self.conn.lock();
 
         let mut stmt = conn.prepare(
-            "SELECT t.id, t.name, t.display_name, t.tag_type, t.color, t.icon, t.use_count, t.created_at
+            "SELECT t.id, t.name, t.display_name, t.tag_type, t.color, t.icon, t.use_count, t.created_at, t.parent_id, ft.value_type, ft.value, ft.inherited_from, ft.implied_by
              FROM tags t
              JOIN file_tags ft ON t.id = ft.tag_id
              WHERE ft.file_id = ?1
@@ -260,21 +299,27 @@ impl Database {
         let mut tags = Vec::new();
         let mut rows = stmt.query(params![file_id])?;
         while let Some(row) = rows.next()? {
-            tags.push(self.row_to_tag(row)?);
+            tags.push(Tag {
+                value: tag_value(row, 9)?,
+                inherited_from: row.get(11)?,
+                implied_by: row.get(12)?,
+                ..self.row_to_tag(row)?
+            });
         }
 
         Ok(tags)
     }
 
-    /// 添加标签到文件
+    /// 添加标签到文件（连同推导出的标签）
     pub fn add_tag_to_file(&self, file_id: i64, tag_id: i64, is_auto: bool) -> Result<()> {
-        let conn = self.conn.lock();
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
         let now = Utc::now().timestamp();
 
         println!("[DEBUG] add_tag_to_file: file_id={}, tag_id={}, is_auto={}", file_id, tag_id, is_auto);
 
-        // 使用 INSERT OR IGNORE 避免重复添加时重复递增计数
-        let rows_affected = conn.execute(
+        // use_count 由 file_tags 上的触发器维护
+        let rows_affected = tx.execute(
             "INSERT OR IGNORE INTO file_tags (file_id, tag_id, is_auto, created_at)
              VALUES (?1, ?2, ?3, ?4)",
             params![file_id, tag_id, is_auto as i32, now],
@@ -282,128 +327,417 @@ impl Database {
 
         println!("[DEBUG] INSERT OR IGNORE rows_affected: {}", rows_affected);
 
-        // 仅当成功插入新记录时才更新标签使用计数
-        if rows_affected > 0 {
-            conn.execute(
-                "UPDATE tags SET use_count = use_count + 1 WHERE id = ?1",
-                params![tag_id],
-            )?;
-            println!("[DEBUG] Updated use_count for tag_id: {}", tag_id);
-        } else {
-            println!("[DEBUG] Skipping use_count update (duplicate)");
+        if rows_affected == 0 {
+            promote_derived_tag(&tx, file_id, tag_id, is_auto)?;
         }
 
+        enforce_exclusive_group(&tx, file_id, tag_id)?;
+        sync_derived_tags(&tx, &[file_id])?;
+        tx.commit()?;
+
         Ok(())
     }
 
-    /// 根据标签名称添加到文件
+    /// 根据标签名称添加到文件（别名解析为规范标签）
+    ///
+    /// 标签不存在时自动创建：手动添加创建自定义标签，自动添加或使用保留名称时创建系统标签。
     pub fn add_tag_to_file_by_name(&self, file_id: i64, tag_name: &str, is_auto: bool) -> Result<()> {
-        // 获取或创建标签
-        let tag = match self.get_tag_by_name(tag_name)? {
-            Some(t) => t,
-            None => {
-                // 创建系统标签
-                let new_tag = Tag {
-                    id: None,
-                    name: tag_name.to_string(),
-                    display_name: tag_name.to_string(),
-                    tag_type: TagType::System,
-                    color: "#007ACC".to_string(),
-                    icon: None,
-                    use_count: 0,
-                    created_at: Utc::now(),
-                };
-                let tag_id = self.create_tag(&new_tag)?;
-                Tag {
-                    id: Some(tag_id),
-                    ..new_tag
+        let tag_type = if is_auto { TagType::System } else { TagType::Custom };
+        let tag_id = get_or_create_tag_path(&self.conn.lock(), tag_name, &tag_type, "#007ACC")?;
+
+        self.add_tag_to_file(file_id, tag_id, is_auto)
+    }
+
+    /// 从文件移除标签，由该标签推导出的标签一并移除
+    ///
+    /// 推导出的标签不能单独移除，需要移除其来源标签或删除推导规则；继承的目录标签需要在目录上移除。
+    pub fn remove_tag_from_file(&self, file_id: i64, tag_id: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let implied_by: Option<String> = tx.query_row(
+            "SELECT t.name FROM file_tags ft JOIN tags t ON ft.implied_by = t.id
+             WHERE ft.file_id = ?1 AND ft.tag_id = ?2",
+            params![file_id, tag_id],
+            |row| row.get(0),
+        ).optional()?;
+        if let Some(source) = implied_by {
+            return Err(AppError::InvalidTag(format!(
+                "该标签由标签 '{}' 推导得出，请先移除 '{}'",
+                source, source
+            )));
+        }
+
+        let inherited_from: Option<String> = tx.query_row(
+            "SELECT inherited_from FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
+            params![file_id, tag_id],
+            |row| row.get(0),
+        ).optional()?.flatten();
+        if let Some(dir) = inherited_from {
+            return Err(AppError::InvalidTag(format!("该标签继承自目录 '{}'，请在目录上移除", dir)));
+        }
+
+        remove_file_tag(&tx, file_id, tag_id)?;
+        sync_derived_tags(&tx, &[file_id])?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 同步文件的自动标签
+    ///
+    /// 添加 `tags` 中尚未关联的标签并记录来源规则，不在 `tags` 中的自动标签（is_auto = 1）予以移除。
+    /// `failed_rules` 中的规则本次执行失败，其以前产生的标签保持不变。
+    /// 手动标签不受影响，推导标签随来源标签重新计算。
+    pub fn sync_auto_tags(&self, file_id: i64, tags: &[AutoTag], failed_rules: &[String]) -> Result<()> {
+        self.sync_auto_tags_where(file_id, tags, failed_rules, &SqlBuilder::default())
+    }
+
+    /// 只同步 `rule_names` 中的规则产生的自动标签，其他规则和提供者的自动标签保持不变
+    ///
+    /// 用于定期重新评估时间相关规则：`tags` 只需包含这些规则的结果。
+    pub fn sync_rule_tags(
+        &self,
+        file_id: i64,
+        rule_names: &[String],
+        tags: &[AutoTag],
+        failed_rules: &[String],
+    ) -> Result<()> {
+        let mut filter = SqlBuilder::new(" AND ft.rule_name IN (SELECT value FROM json_each(");
+        filter.bind(serde_json::to_string(rule_names).unwrap()).push("))");
+        self.sync_auto_tags_where(file_id, tags, failed_rules, &filter)
+    }
+
+    /// 同步自动标签，`filter` 限定可被移除的自动标签（追加到 `WHERE` 之后的条件）
+    fn sync_auto_tags_where(
+        &self,
+        file_id: i64,
+        tags: &[AutoTag],
+        failed_rules: &[String],
+        filter: &SqlBuilder,
+    ) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        // 生成的标签名可能是别名，按规范名比较，避免每次同步都删除后重新插入
+        let produced: HashSet<String> = tags
+            .iter()
+            .map(|t| resolve_tag_name(&tx, &t.name))
+            .collect::<Result<_>>()?;
+
+        // 找出范围内已不再匹配的自动标签
+        let stale: Vec<i64> = {
+            let mut sql = SqlBuilder::new(
+                "SELECT t.id, t.name FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+                 WHERE ft.is_auto = 1 AND ft.implied_by IS NULL AND ft.inherited_from IS NULL AND ft.file_id = ",
+            );
+            sql.bind(file_id).append(filter);
+            if !failed_rules.is_empty() {
+                sql.push(" AND (ft.rule_name IS NULL OR ft.rule_name NOT IN (SELECT value FROM json_each(")
+                    .bind(serde_json::to_string(failed_rules).unwrap())
+                    .push(")))");
+            }
+            let mut stmt = tx.prepare(sql.sql())?;
+            let rows = stmt.query_map(sql.params(), |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+
+            let mut stale = Vec::new();
+            for row in rows {
+                let (tag_id, name) = row?;
+                if !produced.contains(&name) {
+                    stale.push(tag_id);
                 }
             }
+            stale
         };
 
-        if let Some(tag_id) = tag.id {
-            self.add_tag_to_file(file_id, tag_id, is_auto)?;
+        for tag_id in stale {
+            remove_file_tag(&tx, file_id, tag_id)?;
         }
 
+        for tag in tags {
+            insert_auto_tag(&tx, file_id, tag)?;
+        }
+
+        sync_derived_tags(&tx, &[file_id])?;
+        tx.commit()?;
+
         Ok(())
     }
 
-    /// 从文件移除标签
-    pub fn remove_tag_from_file(&self, file_id: i64, tag_id: i64) -> Result<()> {
+    /// 获取指定规则产生的所有自动标签，返回 (file_id, 标签名)
+    pub fn get_auto_tags_by_rule(&self, rule_name: &str) -> Result<Vec<(i64, String)>> {
         let conn = self.conn.lock();
 
-        // 更新标签使用计数
-        conn.execute(
-            "UPDATE tags SET use_count = use_count - 1 WHERE id = ?1 AND use_count > 0",
-            params![tag_id],
+        let mut stmt = conn.prepare(
+            "SELECT ft.file_id, t.name FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+             WHERE ft.is_auto = 1 AND ft.rule_name = ?1"
         )?;
+        let rows = stmt.query_map(params![rule_name], |row| Ok((row.get(0)?, row.get(1)?)))?;
 
-        conn.execute(
-            "DELETE FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
-            params![file_id, tag_id],
-        )?;
+        let mut tags = Vec::new();
+        for row in rows {
+            tags.push(row?);
+        }
+
+        Ok(tags)
+    }
+
+    /// 在同一事务中应用规则并保存配置
+    ///
+    /// 写入 `assignments` 中各文件的自动标签，移除该规则以前产生、现在不再产生的标签，
+    /// 最后写入配置项 `setting`（键, 值）。任一步失败则整体回滚。
+    pub fn apply_rule_tags(
+        &self,
+        rule_name: &str,
+        assignments: &[(i64, Vec<AutoTag>)],
+        setting: (&str, &str),
+    ) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        // 各文件新产生的标签（按规范名），用于判断已有标签是否仍被产生
+        let mut produced: HashMap<i64, HashSet<String>> = HashMap::new();
+        for (file_id, tags) in assignments {
+            let names = produced.entry(*file_id).or_default();
+            for tag in tags {
+                names.insert(resolve_tag_name(&tx, &tag.name)?);
+            }
+        }
+
+        let stale: Vec<(i64, i64)> = {
+            let mut stmt = tx.prepare(
+                "SELECT ft.file_id, ft.tag_id, t.name FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+                 WHERE ft.is_auto = 1 AND ft.rule_name = ?1"
+            )?;
+            let rows = stmt.query_map(params![rule_name], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, String>(2)?))
+            })?;
+
+            let mut stale = Vec::new();
+            for row in rows {
+                let (file_id, tag_id, name) = row?;
+                let still_produced = produced
+                    .get(&file_id)
+                    .is_some_and(|names| names.contains(&name));
+                if !still_produced {
+                    stale.push((file_id, tag_id));
+                }
+            }
+            stale
+        };
+
+        let mut touched: Vec<i64> = stale.iter().map(|(file_id, _)| *file_id).collect();
+        for (file_id, tag_id) in stale {
+            remove_file_tag(&tx, file_id, tag_id)?;
+        }
+
+        for (file_id, tags) in assignments {
+            for tag in tags {
+                insert_auto_tag(&tx, *file_id, tag)?;
+            }
+            touched.push(*file_id);
+        }
+
+        touched.sort_unstable();
+        touched.dedup();
+        sync_derived_tags(&tx, &touched)?;
+
+        set_setting(&tx, setting.0, setting.1)?;
+
+        tx.commit()?;
 
         Ok(())
     }
 
-    /// 批量添加标签到文件
+    /// 读取配置项
+    pub fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock();
+
+        let value = conn.query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![key],
+            |row| row.get::<_, String>(0),
+        ).optional()?;
+
+        Ok(value)
+    }
+
+    /// 写入配置项
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        set_setting(&conn, key, value)
+    }
+
+    /// 获取文件标签的来源说明
+    pub fn get_tag_explanation(&self, file_id: i64, tag_id: i64) -> Result<TagExplanation> {
+        let conn = self.conn.lock();
+
+        let row = conn.query_row(
+            "SELECT t.name, ft.is_auto, ft.rule_name, ft.rule_detail, ft.created_at, src.name
+             FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+             LEFT JOIN tags src ON ft.implied_by = src.id
+             WHERE ft.file_id = ?1 AND ft.tag_id = ?2",
+            params![file_id, tag_id],
+            |row| Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i32>(1)? != 0,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, i64>(4)?,
+                row.get::<_, Option<String>>(5)?,
+            )),
+        ).optional()?;
+
+        let (tag_name, is_auto, rule_name, rule_detail, created_at_ts, implied_by) = row
+            .ok_or_else(|| AppError::TagNotFound(format!("文件 {} 未关联标签 {}", file_id, tag_id)))?;
+        let created_at = DateTime::from_timestamp(created_at_ts, 0)
+            .ok_or_else(|| AppError::Unknown(format!("无效的 created_at 时间戳: {}", created_at_ts)))?;
+        let date = created_at.with_timezone(&chrono::Local).format("%Y-%m-%d");
+
+        let message = match (is_auto, &rule_name, &rule_detail) {
+            _ if implied_by.is_some() => format!(
+                "由标签 '{}' 推导得出（{}）",
+                implied_by.as_deref().unwrap_or_default(),
+                date
+            ),
+            (false, _, _) => format!("手动添加于 {}", date),
+            (true, Some(rule), Some(detail)) => format!("由规则 '{}'（{}）于 {} 自动添加", rule, detail, date),
+            (true, Some(rule), None) => format!("由规则 '{}' 于 {} 自动添加", rule, date),
+            (true, None, _) => format!("于 {} 自动添加（未记录来源规则）", date),
+        };
+
+        Ok(TagExplanation {
+            file_id,
+            tag_id,
+            tag_name,
+            is_auto,
+            rule_name,
+            rule_detail,
+            implied_by,
+            created_at,
+            message,
+        })
+    }
+
+    /// 批量添加标签到文件（别名解析为规范标签，不存在的标签忽略）
     pub fn batch_add_tags(&self, file_ids: &[i64], tag_names: &[String]) -> Result<()> {
         let mut conn = self.conn.lock();
 
         let tx = conn.transaction()?;
+        let tag_ids = existing_tag_ids(&tx, tag_names)?;
 
         for &file_id in file_ids {
-            for tag_name in tag_names {
-                if let Some(tag) = tx.query_row(
-                    "SELECT id FROM tags WHERE name = ?1",
-                    params![tag_name],
-                    |row| row.get::<_, i64>(0),
-                ).optional()? {
-                    let now = Utc::now().timestamp();
-                    // 使用 INSERT OR IGNORE 避免重复添加时重复递增计数
-                    let rows_affected = tx.execute(
-                        "INSERT OR IGNORE INTO file_tags (file_id, tag_id, is_auto, created_at)
-                         VALUES (?1, ?2, 0, ?3)",
-                        params![file_id, tag, now],
-                    )?;
-
-                    println!("[DEBUG] batch_add_tags: file_id={}, tag={}, rows_affected={}", file_id, tag_name, rows_affected);
-
-                    // 仅当成功插入新记录时才更新标签使用计数
-                    if rows_affected > 0 {
-                        tx.execute(
-                            "UPDATE tags SET use_count = use_count + 1 WHERE id = ?1",
-                            params![tag],
-                        )?;
-                        println!("[DEBUG] Updated use_count for tag_id: {}", tag);
-                    } else {
-                        println!("[DEBUG] Skipping use_count update (duplicate)");
-                    }
-                }
+            for &tag_id in &tag_ids {
+                add_manual_tag(&tx, file_id, tag_id)?;
             }
         }
 
+        sync_derived_tags(&tx, file_ids)?;
+
         tx.commit()?;
 
         Ok(())
     }
 
-    /// 根据标签获取文件
+    /// 批量从文件移除标签（别名解析为规范标签，不存在的标签忽略）
+    ///
+    /// 只移除直接添加的标签（手动或自动）；推导和继承的标签随其来源变化，由被移除标签推导出的标签一并移除。
+    pub fn batch_remove_tags(&self, file_ids: &[i64], tag_names: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock();
+
+        let tx = conn.transaction()?;
+        let tag_ids = existing_tag_ids(&tx, tag_names)?;
+
+        remove_direct_tags(&tx, file_ids, &tag_ids)?;
+        sync_derived_tags(&tx, file_ids)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 批量设置文件的手动标签（别名解析为规范标签，不存在的标签忽略）
+    ///
+    /// 移除不在 `tag_names` 中的手动标签并添加缺少的标签；自动、推导和继承的标签不受影响。
+    pub fn batch_set_tags(&self, file_ids: &[i64], tag_names: &[String]) -> Result<()> {
+        let mut conn = self.conn.lock();
+
+        let tx = conn.transaction()?;
+        let tag_ids = existing_tag_ids(&tx, tag_names)?;
+
+        for &file_id in file_ids {
+            let manual: Vec<i64> = {
+                let mut stmt = tx.prepare(
+                    "SELECT tag_id FROM file_tags
+                     WHERE file_id = ?1 AND is_auto = 0 AND implied_by IS NULL AND inherited_from IS NULL",
+                )?;
+                let ids = stmt
+                    .query_map(params![file_id], |row| row.get(0))?
+                    .collect::<std::result::Result<Vec<i64>, _>>()?;
+                ids
+            };
+
+            for tag_id in manual.into_iter().filter(|id| !tag_ids.contains(id)) {
+                remove_file_tag(&tx, file_id, tag_id)?;
+            }
+            for &tag_id in &tag_ids {
+                add_manual_tag(&tx, file_id, tag_id)?;
+            }
+        }
+
+        sync_derived_tags(&tx, file_ids)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 对搜索结果中的所有文件添加和移除标签，返回受影响的文件数
+    ///
+    /// 忽略 `query` 的 limit 与 offset，在一个事务中处理整个结果集，前端无需传递大量文件 ID。
+    /// 添加与移除的规则同 `batch_add_tags` 与 `batch_remove_tags`。
+    pub fn retag_by_query(&self, query: &SearchQuery, add: &[String], remove: &[String]) -> Result<usize> {
+        let file_ids = self.search_file_ids(query)?;
+
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let remove_ids = existing_tag_ids(&tx, remove)?;
+        let add_ids = existing_tag_ids(&tx, add)?;
+
+        remove_direct_tags(&tx, &file_ids, &remove_ids)?;
+        for &file_id in &file_ids {
+            for &tag_id in &add_ids {
+                add_manual_tag(&tx, file_id, tag_id)?;
+            }
+        }
+        sync_derived_tags(&tx, &file_ids)?;
+
+        tx.commit()?;
+
+        Ok(file_ids.len())
+    }
+
+    /// 根据标签获取文件（包含子标签下的文件，别名解析为规范标签）
     pub fn get_files_by_tags(&self, tag_names: &[String]) -> Result<Vec<File>> {
         let conn = self.conn.lock();
+        let tag_names = resolve_tag_names(&conn, tag_names)?;
 
         let placeholders = tag_names.iter().map(|_| "?").collect::<Vec<_>>().join(",");
         let sql = format!(
             "SELECT DISTINCT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata,
-                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at
+                    t.id as tag_id, t.name as tag_name, t.display_name as tag_display_name, t.tag_type as tag_type, t.color as tag_color, t.icon as tag_icon, t.use_count as tag_use_count, t.created_at as tag_created_at, t.parent_id as tag_parent_id,
+                    ft.value_type as tag_value_type, ft.value as tag_value, ft.inherited_from as tag_inherited_from, ft.implied_by as tag_implied_by
              FROM files f
              JOIN file_tags ft ON f.id = ft.file_id
              JOIN tags t ON ft.tag_id = t.id
              LEFT JOIN file_tags ft2 ON f.id = ft2.file_id
              LEFT JOIN tags t2 ON ft2.tag_id = t2.id
-             WHERE t.name IN ({}) AND f.status = 'active'
+             WHERE t.id IN ({}) AND f.status = 'active'
              ORDER BY f.created_at DESC",
-            placeholders
+            tag_subtree_sql(&placeholders)
         );
 
         let mut stmt = conn.prepare(&sql)?;
@@ -430,16 +764,18 @@ impl Database {
         let now = Utc::now().timestamp();
 
         let filters_json = dir.filters.as_ref().map(|f| serde_json::to_string(f).unwrap());
+        let providers_json = dir.tag_providers.as_ref().map(|p| serde_json::to_string(p).unwrap());
 
         conn.execute(
-            "INSERT INTO watched_directories (path, recursive, filters, enabled, created_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO watched_directories (path, recursive, filters, enabled, created_at, tag_providers)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
             params![
                 dir.path,
                 dir.recursive as i32,
                 filters_json,
                 dir.enabled as i32,
                 now,
+                providers_json,
             ],
         )?;
 
@@ -451,7 +787,7 @@ impl Database {
         let conn = self.conn.lock();
 
         let mut stmt = conn.prepare(
-            "SELECT id, path, recursive, filters, enabled, created_at, last_scanned_at
+            "SELECT id, path, recursive, filters, enabled, created_at, last_scanned_at, tag_providers
              FROM watched_directories
              ORDER BY created_at DESC"
         )?;
@@ -470,6 +806,10 @@ impl Database {
                 .get::<_, Option<i64>>(6)?
                 .and_then(|t| DateTime::from_timestamp(t, 0));
 
+            let tag_providers: Option<Vec<String>> = row
+                .get::<_, Option<String>>(7)?
+                .and_then(|p| serde_json::from_str(&p).ok());
+
             dirs.push(WatchedDirectory {
                 id: Some(row.get(0)?),
                 path: row.get(1)?,
@@ -478,12 +818,30 @@ impl Database {
                 enabled: row.get::<_, i32>(4)? != 0,
                 created_at,
                 last_scanned_at,
+                tag_providers,
             });
         }
 
         Ok(dirs)
     }
 
+    /// 设置监控目录启用的标签提供者，None 表示启用全部
+    pub fn set_directory_tag_providers(&self, id: i64, providers: Option<&[String]>) -> Result<()> {
+        let conn = self.conn.lock();
+        let providers_json = providers.map(|p| serde_json::to_string(p).unwrap());
+
+        let updated = conn.execute(
+            "UPDATE watched_directories SET tag_providers = ?1 WHERE id = ?2",
+            params![providers_json, id],
+        )?;
+
+        if updated == 0 {
+            return Err(AppError::ConfigNotFound(format!("监控目录 {}", id)));
+        }
+
+        Ok(())
+    }
+
     /// 删除监控目录
     pub fn delete_watched_directory(&self, id: i64) -> Result<()> {
         let conn = self.conn.lock();
@@ -569,218 +927,12 @@ impl Database {
         })
     }
 
-    /// 搜索文件 - 支持混合搜索策略（中文用LIKE，英文数字用FTS）
-    pub fn search_files(&self, query: &SearchQuery) -> Result<SearchResultResponse> {
-        let conn = self.conn.lock();
-
-        // 检测关键字是否包含中文
-        let has_chinese = query.keywords.iter().any(|k| self.contains_chinese(k));
-
-        // 构建标签过滤子句
-        let tag_filter = if let Some(ref tags) = query.tags {
-            if !tags.is_empty() {
-                let placeholders = tags.iter().map(|_| "?").collect::<Vec<_>>().join(",");
-                Some(format!("EXISTS (SELECT 1 FROM file_tags ft JOIN tags t ON ft.tag_id = t.id WHERE ft.file_id = f.id AND t.name IN ({}))", placeholders))
-            } else {
-                None
-            }
-        } else {
-            None
-        };
-
-        let mut results = Vec::new();
-        let total: i64;
-
-        if has_chinese {
-            // 中文搜索：使用 LIKE 进行模糊匹配
-            let like_conditions: Vec<String> = query.keywords
-                .iter()
-                .map(|k| format!("(f.name LIKE '%{}%' OR f.path LIKE '%{}%' OR f.path LIKE '%{}%')",
-                    self.escape_like(k), self.escape_like(k), self.escape_like(k)))
-                .collect();
-
-            let like_clause = match query.operator {
-                SearchOperator::And => like_conditions.join(" AND "),
-                SearchOperator::Or => like_conditions.join(" OR "),
-            };
-
-            let mut sql = format!(
-                "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata, 0.0 as relevance
-                 FROM files f
-                 WHERE ({}) AND f.status = 'active'",
-                like_clause
-            );
-
-            // 添加文件类型过滤
-            if let Some(file_type) = &query.file_type_filter {
-                sql.push_str(&format!(" AND f.file_type = '{}'", file_type.to_string()));
-            }
-
-            // 添加标签过滤
-            if let Some(ref tag_filter) = tag_filter {
-                sql.push_str(&format!(" AND {}", tag_filter));
-            }
-
-            sql.push_str(&format!(" ORDER BY f.created_at DESC LIMIT {} OFFSET {}",
-                query.limit, query.offset));
-
-            let mut stmt = conn.prepare(&sql)?;
-
-            // 准备查询参数
-            let mut query_params: Vec<&dyn rusqlite::ToSql> = Vec::new();
-            if let Some(ref tags) = query.tags {
-                for tag in tags {
-                    query_params.push(tag);
-                }
-            }
-            let mut rows = stmt.query(query_params.as_slice())?;
-
-            while let Some(row) = rows.next()? {
-                let file = self.row_to_file(row)?;
-                let relevance: f32 = row.get(12)?;
-
-                let tags = self.get_tags_for_file(&conn, file.id)?;
-
-                results.push(SearchResult {
-                    file,
-                    tags,
-                    relevance,
-                });
-            }
-
-            // 获取总数
-            let count_sql = format!(
-                "SELECT COUNT(DISTINCT f.id)
-                 FROM files f
-                 WHERE ({}) AND f.status = 'active'",
-                like_clause
-            );
-
-            // 在总数查询中也添加标签过滤
-            let mut count_sql_with_filters = count_sql;
-            if let Some(file_type) = &query.file_type_filter {
-                count_sql_with_filters.push_str(&format!(" AND f.file_type = '{}'", file_type.to_string()));
-            }
-            if let Some(ref tag_filter) = tag_filter {
-                count_sql_with_filters.push_str(&format!(" AND {}", tag_filter));
-            }
-
-            total = conn.query_row(&count_sql_with_filters, query_params.as_slice(), |row| row.get(0))?;
-
-        } else {
-            // 英文/数字搜索：使用 FTS 全文搜索
-            let fts_query = self.build_fts_query(query)?;
-
-            let mut sql = String::from(
-                "SELECT f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata, bm25(file_tags_content)
-                 FROM files f
-                 JOIN file_tags_content ON f.id = file_tags_content.file_id
-                 WHERE file_tags_content MATCH ?1 AND f.status = 'active'"
-            );
-
-            // 添加文件类型过滤
-            if let Some(file_type) = &query.file_type_filter {
-                sql.push_str(&format!(" AND f.file_type = '{}'", file_type.to_string()));
-            }
-
-            // 添加标签过滤
-            if let Some(ref tag_filter) = tag_filter {
-                sql.push_str(&format!(" AND {}", tag_filter));
-            }
-
-            sql.push_str(" ORDER BY bm25(file_tags_content) DESC, f.created_at DESC LIMIT ?2 OFFSET ?3");
-
-            let mut stmt = conn.prepare(&sql)?;
-
-            // 准备查询参数
-            let limit_i64 = query.limit as i64;
-            let offset_i64 = query.offset as i64;
-            let mut query_params: Vec<&dyn rusqlite::ToSql> = vec![&fts_query];
-            if let Some(ref tags) = query.tags {
-                for tag in tags {
-                    query_params.push(tag);
-                }
-            }
-            query_params.push(&limit_i64);
-            query_params.push(&offset_i64);
-
-            let mut rows = stmt.query(query_params.as_slice())?;
-
-            while let Some(row) = rows.next()? {
-                let file = self.row_to_file(row)?;
-                let relevance: f32 = row.get(12)?;
-
-                let tags = self.get_tags_for_file(&conn, file.id)?;
-
-                results.push(SearchResult {
-                    file,
-                    tags,
-                    relevance,
-                });
-            }
-
-            // 获取总数
-            let mut count_sql = String::from(
-                "SELECT COUNT(DISTINCT f.id)
-                 FROM files f
-                 JOIN file_tags_content ON f.id = file_tags_content.file_id
-                 WHERE file_tags_content MATCH ?1 AND f.status = 'active'"
-            );
-
-            // 在总数查询中也添加文件类型和标签过滤
-            if let Some(file_type) = &query.file_type_filter {
-                count_sql.push_str(&format!(" AND f.file_type = '{}'", file_type.to_string()));
-            }
-            if let Some(ref tag_filter) = tag_filter {
-                count_sql.push_str(&format!(" AND {}", tag_filter));
-            }
-
-            // 准备总数查询参数
-            let mut count_params: Vec<&dyn rusqlite::ToSql> = vec![&fts_query];
-            if let Some(ref tags) = query.tags {
-                for tag in tags {
-                    count_params.push(tag);
-                }
-            }
-
-            total = conn.query_row(&count_sql, count_params.as_slice(), |row| row.get(0))?;
-        }
-
-        Ok(SearchResultResponse {
-            results,
-            total,
-        })
-    }
-
-    /// 检测字符串是否包含中文字符
-    fn contains_chinese(&self, text: &str) -> bool {
-        text.chars().any(|c| {
-            let code = c as u32;
-            // 基本汉字范围：\u4e00-\u9fff
-            (0x4e00..=0x9fff).contains(&code) ||
-            // 扩展汉字范围A：\u3400-\u4dbf
-            (0x3400..=0x4dbf).contains(&code) ||
-            // 扩展汉字范围B：\u20000-\u2a6df
-            (0x20000..=0x2a6df).contains(&code) ||
-            // CJK 标点符号
-            (0x3000..=0x303f).contains(&code) ||
-            (0xff00..=0xffef).contains(&code)
-        })
-    }
-
-    /// 转义 LIKE 语句中的特殊字符
-    fn escape_like(&self, text: &str) -> String {
-        text.replace('\\', "\\\\")
-            .replace('%', "\\%")
-            .replace('_', "\\_")
-    }
-
     /// 获取文件的标签列表
-    fn get_tags_for_file(&self, conn: &rusqlite::Connection, file_id: Option<i64>) -> Result<Vec<Tag>> {
+    pub(super) fn get_tags_for_file(&self, conn: &rusqlite::Connection, file_id: Option<i64>) -> Result<Vec<Tag>> {
         if let Some(id) = file_id {
             let mut tags = Vec::new();
             let mut stmt = conn.prepare(
-                "SELECT t.id, t.name, t.display_name, t.tag_type, t.color, t.icon, t.use_count, t.created_at
+                "SELECT t.id, t.name, t.display_name, t.tag_type, t.color, t.icon, t.use_count, t.created_at, t.parent_id, ft.value_type, ft.value, ft.inherited_from, ft.implied_by
                  FROM tags t
                  JOIN file_tags ft ON t.id = ft.tag_id
                  WHERE ft.file_id = ?1"
@@ -802,6 +954,10 @@ impl Database {
                     icon: row.get(5)?,
                     use_count: row.get(6)?,
                     created_at,
+                    parent_id: row.get(8)?,
+                    value: tag_value(row, 9)?,
+                    inherited_from: row.get(11)?,
+                    implied_by: row.get(12)?,
                 });
             }
             Ok(tags)
@@ -810,30 +966,8 @@ impl Database {
         }
     }
 
-    /// 构建 FTS 查询
-    fn build_fts_query(&self, query: &SearchQuery) -> Result<String> {
-        match query.operator {
-            SearchOperator::And => {
-                // AND 逻辑: 所有关键字都必须出现
-                let terms: Vec<String> = query.keywords
-                    .iter()
-                    .map(|k| format!("\"{}\"", k.replace('"', "\"\"")))
-                    .collect();
-                Ok(terms.join(" "))
-            }
-            SearchOperator::Or => {
-                // OR 逻辑: 任一关键字出现即可
-                let terms: Vec<String> = query.keywords
-                    .iter()
-                    .map(|k| format!("\"{}\"", k.replace('"', "\"\"")))
-                    .collect();
-                Ok(terms.join(" OR "))
-            }
-        }
-    }
-
     /// 将数据库行转换为 File
-    fn row_to_file(&self, row: &rusqlite::Row) -> Result<File> {
+    pub(super) fn row_to_file(&self, row: &rusqlite::Row) -> Result<File> {
         // SQL列顺序: id(0), path(1), name(2), extension(3), size(4), file_type(5),
         //            created_at(6), modified_at(7), accessed_at(8), status(9), indexed_at(10), metadata(11)
         let file_type_str: String = row.get(5)?;
@@ -877,7 +1011,7 @@ impl Database {
     }
 
     /// 将数据库行转换为 Tag
-    fn row_to_tag(&self, row: &rusqlite::Row) -> Result<Tag> {
+    pub(super) fn row_to_tag(&self, row: &rusqlite::Row) -> Result<Tag> {
         let tag_type_str: String = row.get(3)?;
         let tag_type = TagType::from_str(&tag_type_str);
         let created_at_ts: i64 = row.get(7)?;
@@ -893,6 +1027,10 @@ impl Database {
             icon: row.get(5)?,
             use_count: row.get(6)?,
             created_at,
+            parent_id: row.get(8)?,
+            value: None,
+            inherited_from: None,
+            implied_by: None,
         })
     }
 
@@ -960,7 +1098,8 @@ impl Database {
 
             // 如果有标签，添加到标签列表
             if let Some(_tag_id) = tag_id_opt {
-                // 标签字段索引: tag_id(12), tag_name(13), tag_display_name(14), tag_type(15), tag_color(16), tag_icon(17), tag_use_count(18), tag_created_at(19)
+                // 标签字段索引: tag_id(12), tag_name(13), tag_display_name(14), tag_type(15), tag_color(16), tag_icon(17), tag_use_count(18), tag_created_at(19), tag_parent_id(20),
+                //              tag_value_type(21), tag_value(22), tag_inherited_from(23), tag_implied_by(24)
                 let tag_type_str: String = row.get(15)?;
                 let tag_type = TagType::from_str(&tag_type_str);
                 let tag_created_at_ts: i64 = row.get(19)?;
@@ -976,6 +1115,10 @@ impl Database {
                     icon: row.get(17)?,
                     use_count: row.get(18)?,
                     created_at: tag_created_at,
+                    parent_id: row.get(20)?,
+                    value: tag_value(row, 21)?,
+                    inherited_from: row.get(23)?,
+                    implied_by: row.get(24)?,
                 };
                 file_map.get_mut(&file_id).unwrap().1.push(tag);
             }
@@ -984,40 +1127,154 @@ impl Database {
     }
 
     /// 重新计算所有标签的使用计数
-    /// 基于 file_tags 表的实际数据修正 use_count
+    ///
+    /// use_count 由触发器维护，正常情况下无需调用；保留用于修复旧版本数据库中已经偏差的计数。
     pub fn recalculate_tag_counts(&self) -> Result<()> {
         let conn = self.conn.lock();
 
-        println!("[DEBUG] 开始重新计算标签使用计数...");
+        let updated = recount_tag_usage(&conn)?;
+        println!("[DEBUG] 重新计算完成，更新了 {} 个标签", updated);
 
-        // 先重置所有标签的 use_count 为 0
-        conn.execute("UPDATE tags SET use_count = 0", [])?;
+        Ok(())
+    }
+}
 
-        // 统计每个标签的实际使用次数
-        let mut stmt = conn.prepare(
-            "SELECT tag_id, COUNT(*) as count FROM file_tags GROUP BY tag_id"
+/// 读取从 `index` 开始的 value_type、value 两列为标签值
+fn tag_value(row: &rusqlite::Row, index: usize) -> Result<Option<TagValue>> {
+    let value_type: Option<String> = row.get(index)?;
+    let value: Option<String> = row.get(index + 1)?;
+
+    Ok(match (value_type, value) {
+        (Some(value_type), Some(value)) => TagValue::from_db(&value_type, &value),
+        _ => None,
+    })
+}
+
+/// 获取标签 ID，不存在时创建系统标签（连同缺失的上级标签）（供事务内使用）
+fn get_or_create_tag_id(conn: &rusqlite::Connection, name: &str) -> Result<i64> {
+    get_or_create_tag_path(conn, name, &TagType::System, "#007ACC")
+}
+
+/// 为文件添加自动标签并记录来源规则；已存在的自动标签只更新来源（供事务内使用）
+///
+/// 标签属于互斥分组时，同组已有手动标签则不添加，否则移除同组的其他自动标签。
+fn insert_auto_tag(conn: &rusqlite::Connection, file_id: i64, tag: &AutoTag) -> Result<()> {
+    let tag_id = get_or_create_tag_id(conn, &tag.name)?;
+    if blocked_by_manual_tag(conn, file_id, tag_id)? {
+        return Ok(());
+    }
+
+    let rows_affected = conn.execute(
+        "INSERT OR IGNORE INTO file_tags (file_id, tag_id, is_auto, created_at, rule_name, rule_detail)
+         VALUES (?1, ?2, 1, ?3, ?4, ?5)",
+        params![file_id, tag_id, Utc::now().timestamp(), tag.rule_name, tag.rule_detail],
+    )?;
+
+    if rows_affected == 0 {
+        // 已存在的自动标签可能改由其他规则产生，更新来源
+        conn.execute(
+            "UPDATE file_tags SET rule_name = ?3, rule_detail = ?4, implied_by = NULL, inherited_from = NULL
+             WHERE file_id = ?1 AND tag_id = ?2 AND is_auto = 1",
+            params![file_id, tag_id, tag.rule_name, tag.rule_detail],
         )?;
+    }
 
-        let mut rows = stmt.query([])?;
-        let mut tag_counts: Vec<(i64, i64)> = Vec::new();
+    enforce_exclusive_auto_group(conn, file_id, tag_id)?;
 
-        while let Some(row) = rows.next()? {
-            let tag_id: i64 = row.get(0)?;
-            let count: i64 = row.get(1)?;
-            tag_counts.push((tag_id, count));
+    Ok(())
+}
+
+/// 按名称查找已存在的标签 ID（别名解析为规范标签，不存在的名称忽略）
+fn existing_tag_ids(conn: &rusqlite::Connection, tag_names: &[String]) -> Result<Vec<i64>> {
+    let mut ids = Vec::new();
+    for name in resolve_tag_names(conn, tag_names)? {
+        let id: Option<i64> = conn.query_row(
+            "SELECT id FROM tags WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        ).optional()?;
+        if let Some(id) = id.filter(|id| !ids.contains(id)) {
+            ids.push(id);
         }
+    }
+
+    Ok(ids)
+}
+
+/// 为文件添加手动标签：已存在的推导或继承标签改为手动，并执行分组互斥（供事务内使用）
+fn add_manual_tag(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<()> {
+    let rows_affected = conn.execute(
+        "INSERT OR IGNORE INTO file_tags (file_id, tag_id, is_auto, created_at)
+         VALUES (?1, ?2, 0, ?3)",
+        params![file_id, tag_id, Utc::now().timestamp()],
+    )?;
+
+    if rows_affected == 0 {
+        promote_derived_tag(conn, file_id, tag_id, false)?;
+    }
+
+    enforce_exclusive_group(conn, file_id, tag_id)
+}
 
-        // 更新每个标签的 use_count
-        for i in 0..tag_counts.len() {
-            let (tag_id, count) = tag_counts[i];
+/// 移除文件上直接添加的标签，推导和继承的标签保留（供事务内使用）
+fn remove_direct_tags(conn: &rusqlite::Connection, file_ids: &[i64], tag_ids: &[i64]) -> Result<()> {
+    for &file_id in file_ids {
+        for &tag_id in tag_ids {
             conn.execute(
-                "UPDATE tags SET use_count = ?1 WHERE id = ?2",
-                params![count, tag_id],
+                "DELETE FROM file_tags
+                 WHERE file_id = ?1 AND tag_id = ?2 AND implied_by IS NULL AND inherited_from IS NULL",
+                params![file_id, tag_id],
             )?;
         }
+    }
 
-        println!("[DEBUG] 重新计算完成，更新了 {} 个标签", tag_counts.len());
+    Ok(())
+}
 
-        Ok(())
-    }
+/// 已作为推导或继承标签存在时改为直接添加的标签，来源移除后不再随之移除（供事务内使用）
+pub(super) fn promote_derived_tag(conn: &rusqlite::Connection, file_id: i64, tag_id: i64, is_auto: bool) -> Result<()> {
+    conn.execute(
+        "UPDATE file_tags SET is_auto = ?3, implied_by = NULL, inherited_from = NULL
+         WHERE file_id = ?1 AND tag_id = ?2 AND (implied_by IS NOT NULL OR inherited_from IS NOT NULL)",
+        params![file_id, tag_id, is_auto as i32],
+    )?;
+
+    Ok(())
+}
+
+/// 移除文件与标签的关联（供事务内使用）
+fn remove_file_tag(conn: &rusqlite::Connection, file_id: i64, tag_id: i64) -> Result<()> {
+    conn.execute(
+        "DELETE FROM file_tags WHERE file_id = ?1 AND tag_id = ?2",
+        params![file_id, tag_id],
+    )?;
+
+    Ok(())
+}
+
+/// 按活跃文件上的实际关联重新计算所有标签的 use_count，返回计数发生变化的标签数
+pub(super) fn recount_tag_usage(conn: &rusqlite::Connection) -> Result<usize> {
+    let updated = conn.execute(
+        "UPDATE tags SET use_count = (
+             SELECT COUNT(*) FROM file_tags ft JOIN files f ON ft.file_id = f.id
+             WHERE ft.tag_id = tags.id AND f.status = 'active'
+         )
+         WHERE use_count != (
+             SELECT COUNT(*) FROM file_tags ft JOIN files f ON ft.file_id = f.id
+             WHERE ft.tag_id = tags.id AND f.status = 'active'
+         )",
+        [],
+    )?;
+
+    Ok(updated)
+}
+
+/// 写入配置项（供事务内使用）
+fn set_setting(conn: &rusqlite::Connection, key: &str, value: &str) -> Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value, updated_at) VALUES (?1, ?2, ?3)",
+        params![key, value, Utc::now().timestamp()],
+    )?;
+
+    Ok(())
 }
@@ -0,0 +1,470 @@
+use super::models::*;
+use chrono::{Datelike, NaiveDate};
+
+/// 括号与 `-` 的最大嵌套层数，防止过深的递归耗尽栈空间
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// 支持的字段前缀
+const FIELDS: &[&str] = &["tag", "type", "size", "modified", "ext", "path"];
+
+type ParseResult<T> = std::result::Result<T, QueryParseError>;
+
+impl SearchQuery {
+    /// 解析搜索语句
+    ///
+    /// 语法：
+    /// - 空白分隔的条件默认为 AND，`OR` 连接可选条件（优先级低于 AND），括号用于分组；
+    /// - `-` 前缀表示排除，如 `-tag:draft`、`-(a OR b)`，括号与 `-` 最多嵌套 64 层；
+    /// - `"exact phrase"` 按短语整体匹配，引号内连续两个引号 `""` 表示一个引号字符；
+    /// - 字段条件：`tag:invoice`、`type:image`、`size:>10MB`、`size:1MB..10MB`、
+    ///   `modified:2024-01..2024-06`、`modified:>=2024-03-01`、`ext:pdf`、`path:~/work`，
+    ///   字段值可以加引号（`tag:"my tag"`），`type` 与 `ext` 可用逗号列出多个值；
+    /// - `rating>=4`、`tag:status=review` 形式为键值标签条件，其余词语作为关键字。
+    pub fn parse(input: &str, limit: usize, offset: usize) -> ParseResult<Self> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0, depth: 0, nesting: 0 };
+
+        let expr = if parser.tokens.is_empty() {
+            None
+        } else {
+            let expr = parser.parse_or()?;
+            if let Some(token) = parser.peek() {
+                return Err(error("多余的右括号", token.start, token.end));
+            }
+            Some(expr)
+        };
+
+        Ok(SearchQuery { expr, limit, offset, fuzzy: None })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    Or,
+    And,
+    Not,
+    /// 词语；带已知字段前缀时 `field` 为小写字段名，`value` 为去掉前缀与引号后的值
+    Word { field: Option<String>, value: String, value_start: usize, value_end: usize },
+    Phrase(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    start: usize,
+    end: usize,
+}
+
+fn error(message: impl Into<String>, start: usize, end: usize) -> QueryParseError {
+    QueryParseError { message: message.into(), start, end }
+}
+
+/// 将搜索语句拆分为词法单元，位置以字符（Unicode 码点）计
+fn tokenize(input: &str) -> ParseResult<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let kind = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+            '-' => {
+                i += 1;
+                TokenKind::Not
+            }
+            '"' => {
+                let (text, next) = read_quoted(&chars, i)?;
+                if text.trim().is_empty() {
+                    return Err(error("引号内缺少内容", start, next));
+                }
+                i = next;
+                TokenKind::Phrase(text)
+            }
+            _ => {
+                let mut word = String::new();
+                let mut quoted = None;
+                while i < chars.len() && !chars[i].is_whitespace() && !matches!(chars[i], '(' | ')' | '"') {
+                    word.push(chars[i]);
+                    i += 1;
+                }
+                // 字段值可以加引号，如 tag:"my tag"
+                if i < chars.len() && chars[i] == '"' && word.ends_with(':') && is_field(&word[..word.len() - 1]) {
+                    let (text, next) = read_quoted(&chars, i)?;
+                    quoted = Some((text, i + 1, next - 1));
+                    i = next;
+                }
+                classify_word(word, quoted, start, i)
+            }
+        };
+        tokens.push(Token { kind, start, end: i });
+    }
+
+    Ok(tokens)
+}
+
+/// 从 `start` 处的引号读到配对的引号，返回引号内的文本和引号之后的位置；`""` 转义为一个引号
+fn read_quoted(chars: &[char], start: usize) -> ParseResult<(String, usize)> {
+    let mut text = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        if chars[i] == '"' {
+            if chars.get(i + 1) != Some(&'"') {
+                return Ok((text, i + 1));
+            }
+            i += 1;
+        }
+        text.push(chars[i]);
+        i += 1;
+    }
+    Err(error("引号未闭合", start, chars.len()))
+}
+
+fn is_field(name: &str) -> bool {
+    FIELDS.contains(&name.to_ascii_lowercase().as_str())
+}
+
+fn classify_word(word: String, quoted: Option<(String, usize, usize)>, start: usize, end: usize) -> TokenKind {
+    if quoted.is_none() {
+        match word.as_str() {
+            "OR" => return TokenKind::Or,
+            "AND" => return TokenKind::And,
+            _ => {}
+        }
+    }
+
+    match word.split_once(':') {
+        Some((field, rest)) if is_field(field) => {
+            let field_len = field.chars().count() + 1;
+            let (value, value_start, value_end) = match quoted {
+                Some(quoted) => quoted,
+                None => (rest.to_string(), start + field_len, end),
+            };
+            TokenKind::Word { field: Some(field.to_ascii_lowercase()), value, value_start, value_end }
+        }
+        _ => TokenKind::Word { field: None, value: word, value_start: start, value_end: end },
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// 当前所在的括号层数
+    depth: usize,
+    /// 当前括号与 `-` 的总嵌套层数
+    nesting: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// 进入一层括号或 `-`，超过最大嵌套层数时在该词法单元处报错
+    fn enter(&mut self, token: &Token) -> ParseResult<()> {
+        if self.nesting >= MAX_NESTING_DEPTH {
+            return Err(error(
+                format!("嵌套层数超过 {} 层", MAX_NESTING_DEPTH),
+                token.start,
+                token.end,
+            ));
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
+    /// 下一个词法单元能否作为条件的开头
+    fn at_operand(&self) -> bool {
+        matches!(
+            self.peek().map(|t| &t.kind),
+            Some(TokenKind::LParen | TokenKind::Not | TokenKind::Word { .. } | TokenKind::Phrase(_))
+        )
+    }
+
+    /// or_expr := and_expr ("OR" and_expr)*
+    fn parse_or(&mut self) -> ParseResult<QueryExpr> {
+        let mut items = vec![self.parse_and()?];
+        while let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Or).cloned() {
+            self.pos += 1;
+            if !self.at_operand() {
+                return Err(error("OR 后缺少搜索条件", token.start, token.end));
+            }
+            items.push(self.parse_and()?);
+        }
+
+        Ok(if items.len() == 1 { items.remove(0) } else { QueryExpr::Or(items) })
+    }
+
+    /// and_expr := unary (["AND"] unary)*
+    fn parse_and(&mut self) -> ParseResult<QueryExpr> {
+        let mut items = Vec::new();
+        while let Some(token) = self.peek().cloned() {
+            match token.kind {
+                TokenKind::Or if items.is_empty() => {
+                    return Err(error("OR 前缺少搜索条件", token.start, token.end));
+                }
+                TokenKind::RParen if self.depth == 0 => {
+                    return Err(error("多余的右括号", token.start, token.end));
+                }
+                TokenKind::Or | TokenKind::RParen => break,
+                TokenKind::And => {
+                    self.pos += 1;
+                    if items.is_empty() {
+                        return Err(error("AND 前缺少搜索条件", token.start, token.end));
+                    }
+                    if !self.at_operand() {
+                        return Err(error("AND 后缺少搜索条件", token.start, token.end));
+                    }
+                }
+                _ => items.push(self.parse_unary()?),
+            }
+        }
+
+        Ok(if items.len() == 1 { items.remove(0) } else { QueryExpr::And(items) })
+    }
+
+    /// unary := "-" unary | primary
+    fn parse_unary(&mut self) -> ParseResult<QueryExpr> {
+        if let Some(token) = self.peek().filter(|t| t.kind == TokenKind::Not).cloned() {
+            self.pos += 1;
+            if !self.at_operand() {
+                return Err(error("'-' 后缺少搜索条件", token.start, token.end));
+            }
+            self.enter(&token)?;
+            let expr = self.parse_unary()?;
+            self.nesting -= 1;
+            return Ok(QueryExpr::Not(Box::new(expr)));
+        }
+
+        self.parse_primary()
+    }
+
+    /// primary := "(" or_expr ")" | term
+    fn parse_primary(&mut self) -> ParseResult<QueryExpr> {
+        let token = self.next().expect("调用方已确认存在条件");
+        match token.kind {
+            TokenKind::LParen => {
+                if let Some(close) = self.peek().filter(|t| t.kind == TokenKind::RParen) {
+                    return Err(error("括号内缺少搜索条件", token.start, close.end));
+                }
+                self.enter(&token)?;
+                self.depth += 1;
+                let expr = self.parse_or()?;
+                self.depth -= 1;
+                self.nesting -= 1;
+                match self.next() {
+                    Some(Token { kind: TokenKind::RParen, .. }) => Ok(expr),
+                    _ => Err(error("缺少右括号", token.start, token.end)),
+                }
+            }
+            TokenKind::Phrase(text) => Ok(QueryExpr::Term(QueryTerm::Phrase(text))),
+            TokenKind::Word { field, value, value_start, value_end } => {
+                parse_term(field.as_deref(), value, token.start, value_start, value_end)
+            }
+            _ => unreachable!("at_operand 只接受条件开头的词法单元"),
+        }
+    }
+}
+
+/// 解析单个条件；值无效时错误位置指向值本身
+fn parse_term(field: Option<&str>, value: String, start: usize, value_start: usize, value_end: usize) -> ParseResult<QueryExpr> {
+    let term = |t: QueryTerm| QueryExpr::Term(t);
+    let Some(field) = field else {
+        return Ok(term(match AttributeFilter::parse(&value) {
+            Some(filter) => QueryTerm::Attribute(filter),
+            None => QueryTerm::Keyword(value),
+        }));
+    };
+
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(error(format!("{}: 缺少值", field), start, value_end.max(value_start)));
+    }
+    let invalid = |message: String| error(message, value_start, value_end);
+
+    match field {
+        "tag" => Ok(term(match AttributeFilter::parse(value) {
+            Some(filter) => QueryTerm::Attribute(filter),
+            None => QueryTerm::Tag(value.to_string()),
+        })),
+        "type" => any_of(value.split(',').map(|name| {
+            parse_file_type(name.trim())
+                .map(|t| term(QueryTerm::Type(t)))
+                .ok_or_else(|| invalid(format!(
+                    "无法识别的文件类型 '{}'（可用：image、audio、video、text、binary、other）",
+                    name.trim()
+                )))
+        })),
+        "ext" => any_of(value.split(',').map(|ext| {
+            let ext = ext.trim().trim_start_matches('.').to_lowercase();
+            if ext.is_empty() {
+                Err(invalid("ext: 扩展名不能为空".to_string()))
+            } else {
+                Ok(term(QueryTerm::Extension(ext)))
+            }
+        })),
+        "size" => {
+            let bounds = parse_size(value)
+                .ok_or_else(|| invalid(format!("无法识别的文件大小 '{}'（如 >10MB、1MB..1GB）", value)))?;
+            let mut terms: Vec<QueryExpr> = bounds
+                .into_iter()
+                .map(|(op, bytes)| term(QueryTerm::Size { op, bytes }))
+                .collect();
+            Ok(if terms.len() == 1 { terms.remove(0) } else { QueryExpr::And(terms) })
+        }
+        "modified" => {
+            let (from, until) = parse_date_range(value)
+                .ok_or_else(|| invalid(format!("无法识别的日期 '{}'（如 2024-01、>=2024-03-01、2024-01..2024-06）", value)))?;
+            Ok(term(QueryTerm::Modified { from, until }))
+        }
+        "path" => Ok(term(QueryTerm::Path(value.to_string()))),
+        _ => unreachable!("FIELDS 中的字段均已处理"),
+    }
+}
+
+/// 多个值之间为 OR
+fn any_of(items: impl Iterator<Item = ParseResult<QueryExpr>>) -> ParseResult<QueryExpr> {
+    let mut items = items.collect::<ParseResult<Vec<_>>>()?;
+    Ok(if items.len() == 1 { items.remove(0) } else { QueryExpr::Or(items) })
+}
+
+/// 文件类型名称，支持英文名与中文名
+fn parse_file_type(name: &str) -> Option<FileType> {
+    match name.to_lowercase().as_str() {
+        "image" | "图片" => Some(FileType::Image),
+        "audio" | "音频" => Some(FileType::Audio),
+        "video" | "视频" => Some(FileType::Video),
+        "text" | "文本" => Some(FileType::Text),
+        "binary" | "二进制" => Some(FileType::Binary),
+        "other" | "其他" => Some(FileType::Other),
+        _ => None,
+    }
+}
+
+/// 拆分值前面的比较运算符，没有运算符时为等于
+fn split_compare_op(value: &str) -> (CompareOp, &str) {
+    for (prefix, op) in [
+        (">=", CompareOp::Ge),
+        ("<=", CompareOp::Le),
+        ("!=", CompareOp::Ne),
+        (">", CompareOp::Gt),
+        ("<", CompareOp::Lt),
+        ("=", CompareOp::Eq),
+    ] {
+        if let Some(rest) = value.strip_prefix(prefix) {
+            return (op, rest.trim());
+        }
+    }
+    (CompareOp::Eq, value)
+}
+
+/// 解析 `>10MB`、`<=1.5GB`、`1MB..10MB` 形式的文件大小条件
+fn parse_size(value: &str) -> Option<Vec<(CompareOp, i64)>> {
+    if let Some((low, high)) = value.split_once("..") {
+        let mut bounds = Vec::new();
+        if !low.trim().is_empty() {
+            bounds.push((CompareOp::Ge, parse_bytes(low.trim())?));
+        }
+        if !high.trim().is_empty() {
+            bounds.push((CompareOp::Le, parse_bytes(high.trim())?));
+        }
+        return (!bounds.is_empty()).then_some(bounds);
+    }
+
+    let (op, rest) = split_compare_op(value);
+    Some(vec![(op, parse_bytes(rest)?)])
+}
+
+/// 解析 `10MB`、`1.5g`、`512` 形式的大小（按 1024 进制，无单位时为字节）
+fn parse_bytes(value: &str) -> Option<i64> {
+    let split = value.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: f64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        "t" | "tb" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+
+    let bytes = (number * multiplier).round();
+    (bytes.is_finite() && bytes <= i64::MAX as f64).then_some(bytes as i64)
+}
+
+/// 解析日期条件为 [from, until) 区间
+///
+/// 日期可以是 `2024`、`2024-03` 或 `2024-03-15`，表示整个年、月或日；`a..b` 包含两端所在的整个时段。
+fn parse_date_range(value: &str) -> Option<(Option<NaiveDate>, Option<NaiveDate>)> {
+    if let Some((low, high)) = value.split_once("..") {
+        let from = match low.trim() {
+            "" => None,
+            low => Some(parse_period(low)?.0),
+        };
+        let until = match high.trim() {
+            "" => None,
+            high => Some(parse_period(high)?.1),
+        };
+        return (from.is_some() || until.is_some()).then_some((from, until));
+    }
+
+    let (op, rest) = split_compare_op(value);
+    let (start, next) = parse_period(rest)?;
+    match op {
+        CompareOp::Eq => Some((Some(start), Some(next))),
+        CompareOp::Gt => Some((Some(next), None)),
+        CompareOp::Ge => Some((Some(start), None)),
+        CompareOp::Lt => Some((None, Some(start))),
+        CompareOp::Le => Some((None, Some(next))),
+        CompareOp::Ne => None,
+    }
+}
+
+/// 解析年、月或日，返回该时段的第一天和下一时段的第一天
+fn parse_period(value: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+
+    let year: i32 = parts[0].parse().ok()?;
+    match parts[..] {
+        [_] => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?)),
+        [_, month] => {
+            let start = NaiveDate::from_ymd_opt(year, month.parse().ok()?, 1)?;
+            let next = if start.month() == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, start.month() + 1, 1)?
+            };
+            Some((start, next))
+        }
+        [_, month, day] => {
+            let start = NaiveDate::from_ymd_opt(year, month.parse().ok()?, day.parse().ok()?)?;
+            Some((start, start.succ_opt()?))
+        }
+        _ => None,
+    }
+}
@@ -33,7 +33,9 @@ CREATE TABLE IF NOT EXISTS tags (
     color TEXT NOT NULL DEFAULT '#007ACC',
     icon TEXT,
     use_count INTEGER NOT NULL DEFAULT 0,
-    created_at INTEGER NOT NULL
+    created_at INTEGER NOT NULL,
+    parent_id INTEGER REFERENCES tags(id) ON DELETE SET NULL,  -- 父标签，name 为完整路径（如 work/clientA）
+    group_id INTEGER REFERENCES tag_groups(id) ON DELETE SET NULL  -- 所属分组
 );
 
 CREATE INDEX IF NOT EXISTS idx_tags_type ON tags(tag_type);
@@ -48,6 +50,15 @@ CREATE TABLE IF NOT EXISTS file_tags (
     tag_id INTEGER NOT NULL,
     is_auto INTEGER NOT NULL DEFAULT 0,
     created_at INTEGER NOT NULL,
+    rule_name TEXT,
+    rule_detail TEXT,
+    -- 推导标签的直接来源标签，NULL 表示直接添加的标签
+    implied_by INTEGER,
+    -- 键值标签的值（如 rating=4），value_type 为 string / number / date
+    value TEXT,
+    value_type TEXT,
+    -- 继承自目录标签时为来源目录，NULL 表示直接添加或推导的标签
+    inherited_from TEXT,
     PRIMARY KEY (file_id, tag_id),
     FOREIGN KEY (file_id) REFERENCES files(id) ON DELETE CASCADE,
     FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
@@ -58,6 +69,48 @@ CREATE INDEX IF NOT EXISTS idx_file_tags_tag_id ON file_tags(tag_id);
 CREATE INDEX IF NOT EXISTS idx_file_tags_is_auto ON file_tags(is_auto);
 
 
+-- 标签别名：别名（不区分大小写）在打标签和搜索时解析为规范标签
+CREATE TABLE IF NOT EXISTS tag_aliases (
+    alias TEXT PRIMARY KEY COLLATE NOCASE,
+    tag_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_aliases_tag_id ON tag_aliases(tag_id);
+
+-- 标签推导规则：带有 tag_id 的文件自动获得 implied_tag_id（如 invoice → finance）
+CREATE TABLE IF NOT EXISTS tag_implications (
+    tag_id INTEGER NOT NULL,
+    implied_tag_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (tag_id, implied_tag_id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE,
+    FOREIGN KEY (implied_tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_tag_implications_implied ON tag_implications(implied_tag_id);
+
+-- 目录标签：目录下所有已索引文件（包括子目录中的）继承这些标签
+CREATE TABLE IF NOT EXISTS directory_tags (
+    path TEXT NOT NULL,
+    tag_id INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (path, tag_id),
+    FOREIGN KEY (tag_id) REFERENCES tags(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_directory_tags_tag_id ON directory_tags(tag_id);
+
+-- 标签分组（如 Status、Client、Priority），互斥分组内每个文件最多保留一个标签
+CREATE TABLE IF NOT EXISTS tag_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    exclusive INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+
 -- =====================================================
 -- 4. 监控目录表 (watched_directories)
 -- =====================================================
@@ -68,7 +121,8 @@ CREATE TABLE IF NOT EXISTS watched_directories (
     filters TEXT,
     enabled INTEGER NOT NULL DEFAULT 1,
     created_at INTEGER NOT NULL,
-    last_scanned_at INTEGER
+    last_scanned_at INTEGER,
+    tag_providers TEXT                 -- 启用的标签提供者 (JSON 数组)，NULL 表示全部
 );
 
 CREATE INDEX IF NOT EXISTS idx_watched_enabled ON watched_directories(enabled);
@@ -82,7 +136,7 @@ CREATE VIRTUAL TABLE IF NOT EXISTS file_tags_content USING fts5(
     file_name,
     file_path,
     tag_names,
-    tokenize="unicode61"
+    tokenize="cjk"
 );
 
 -- 触发器：同步数据到 FTS 索引
@@ -125,6 +179,67 @@ BEGIN
     WHERE file_id = OLD.file_id;
 END;
 
+-- 文件名三元组索引，为模糊搜索生成候选文件
+CREATE VIRTUAL TABLE IF NOT EXISTS file_name_trigrams USING fts5(
+    file_id UNINDEXED,
+    file_name,
+    tokenize="trigram"
+);
+
+CREATE TRIGGER IF NOT EXISTS trigram_file_insert AFTER INSERT ON files
+BEGIN
+    INSERT INTO file_name_trigrams(file_id, file_name) VALUES (NEW.id, NEW.name);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigram_file_delete AFTER DELETE ON files
+BEGIN
+    DELETE FROM file_name_trigrams WHERE file_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS trigram_file_rename AFTER UPDATE OF name ON files
+BEGIN
+    UPDATE file_name_trigrams SET file_name = NEW.name WHERE file_id = NEW.id;
+END;
+
+-- 触发器：维护标签使用计数（只统计活跃文件上的关联）
+CREATE TRIGGER IF NOT EXISTS tag_count_insert AFTER INSERT ON file_tags
+WHEN EXISTS (SELECT 1 FROM files WHERE id = NEW.file_id AND status = 'active')
+BEGIN
+    UPDATE tags SET use_count = use_count + 1 WHERE id = NEW.tag_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_count_delete AFTER DELETE ON file_tags
+WHEN EXISTS (SELECT 1 FROM files WHERE id = OLD.file_id AND status = 'active')
+BEGIN
+    UPDATE tags SET use_count = use_count - 1 WHERE id = OLD.tag_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_count_update AFTER UPDATE OF file_id, tag_id ON file_tags
+BEGIN
+    UPDATE tags SET use_count = use_count - 1
+    WHERE id = OLD.tag_id
+      AND EXISTS (SELECT 1 FROM files WHERE id = OLD.file_id AND status = 'active');
+    UPDATE tags SET use_count = use_count + 1
+    WHERE id = NEW.tag_id
+      AND EXISTS (SELECT 1 FROM files WHERE id = NEW.file_id AND status = 'active');
+END;
+
+CREATE TRIGGER IF NOT EXISTS tag_count_file_status AFTER UPDATE OF status ON files
+WHEN (OLD.status = 'active') != (NEW.status = 'active')
+BEGIN
+    UPDATE tags
+    SET use_count = use_count + (CASE WHEN NEW.status = 'active' THEN 1 ELSE -1 END)
+    WHERE id IN (SELECT tag_id FROM file_tags WHERE file_id = NEW.id);
+END;
+
+-- 级联删除 file_tags 时文件行已不存在，因此在删除文件之前扣减计数
+CREATE TRIGGER IF NOT EXISTS tag_count_file_delete BEFORE DELETE ON files
+WHEN OLD.status = 'active'
+BEGIN
+    UPDATE tags SET use_count = use_count - 1
+    WHERE id IN (SELECT tag_id FROM file_tags WHERE file_id = OLD.id);
+END;
+
 
 -- =====================================================
 -- 6. 系统配置表 (settings)
@@ -141,3 +256,7 @@ VALUES ('max_index_files', '1000000', strftime('%s', 'now'));
 
 INSERT OR IGNORE INTO settings (key, value, updated_at)
 VALUES ('search_result_limit', '100', strftime('%s', 'now'));
+
+-- 是否允许删除、改名、移动或合并系统标签
+INSERT OR IGNORE INTO settings (key, value, updated_at)
+VALUES ('allow_system_tag_changes', 'false', strftime('%s', 'now'));
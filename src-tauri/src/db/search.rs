@@ -0,0 +1,309 @@
+use super::attributes::attribute_filter_sql;
+use super::fuzzy::{fuzzy_candidates, highlights};
+use super::models::*;
+use super::sql::SqlBuilder;
+use super::tags::{resolve_tag_name, tag_subtree_sql};
+use super::Database;
+use crate::error::Result;
+use chrono::{Local, NaiveDate, TimeZone};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// 文件列，顺序与 `row_to_file` 一致
+const FILE_COLUMNS: &str = "f.id, f.path, f.name, f.extension, f.size, f.file_type, f.created_at, f.modified_at, f.accessed_at, f.status, f.indexed_at, f.metadata";
+
+impl Database {
+    /// 搜索文件
+    ///
+    /// 查询语句编译为参数化的 SQL：关键字（包括中文，由 `cjk` 分词器切分）走 FTS 全文索引并按 bm25 排序，
+    /// 字段条件直接比较文件列。开启模糊搜索时关键字还会匹配编辑距离足够小的文件名，
+    /// 结果按编辑距离优先排序。每个结果附带关键字在文件名中的匹配区间。
+    pub fn search_files(&self, query: &SearchQuery) -> Result<SearchResultResponse> {
+        let conn = self.conn.lock();
+        let compiled = compile_query(&conn, query)?;
+        let max_distance = query.fuzzy.map_or(0, |fuzzy| fuzzy.max_distance);
+
+        let mut sql = SqlBuilder::new("SELECT ");
+        sql.push(FILE_COLUMNS);
+        // 只用非排除的全文关键字计算相关度，没有时按创建时间排序
+        match &compiled.rank_query {
+            Some(rank_query) => {
+                sql.push(", COALESCE(r.rank, 0.0) AS relevance FROM files f
+                          LEFT JOIN (SELECT file_id, bm25(file_tags_content) AS rank FROM file_tags_content
+                                     WHERE file_tags_content MATCH ")
+                    .bind(rank_query.clone())
+                    .push(") r ON r.file_id = f.id");
+            }
+            None => {
+                sql.push(", 0.0 AS relevance FROM files f");
+            }
+        }
+        // 模糊匹配的编辑距离以 {文件 ID: 距离} 的 JSON 对象传入
+        let order = if compiled.distances.is_empty() {
+            "relevance"
+        } else {
+            sql.push(" LEFT JOIN (SELECT CAST(key AS INTEGER) AS file_id, value AS distance FROM json_each(")
+                .bind(serde_json::to_string(&compiled.distances).unwrap())
+                .push(")) d ON d.file_id = f.id");
+            "COALESCE(d.distance, 0), relevance"
+        };
+        sql.push(" WHERE f.status = 'active' AND ")
+            .append(&compiled.condition)
+            .push(" ORDER BY ")
+            .push(order)
+            .push(", f.created_at DESC LIMIT ")
+            .bind(query.limit as i64)
+            .push(" OFFSET ")
+            .bind(query.offset as i64);
+
+        let mut results = Vec::new();
+        let mut stmt = conn.prepare(sql.sql())?;
+        let mut rows = stmt.query(sql.params())?;
+        while let Some(row) = rows.next()? {
+            let file = self.row_to_file(row)?;
+            let relevance: f64 = row.get(12)?;
+            let tags = self.get_tags_for_file(&conn, file.id)?;
+            let highlights = highlights(&file.name, &compiled.highlight_terms, max_distance);
+
+            results.push(SearchResult {
+                file,
+                tags,
+                relevance: relevance as f32,
+                highlights,
+            });
+        }
+
+        let mut count = SqlBuilder::new("SELECT COUNT(*) FROM files f WHERE f.status = 'active' AND ");
+        count.append(&compiled.condition);
+        let total = conn.query_row(count.sql(), count.params(), |row| row.get(0))?;
+
+        Ok(SearchResultResponse {
+            results,
+            total,
+        })
+    }
+
+    /// 搜索结果中所有文件的 ID（忽略分页）
+    pub(super) fn search_file_ids(&self, query: &SearchQuery) -> Result<Vec<i64>> {
+        let conn = self.conn.lock();
+        let compiled = compile_query(&conn, query)?;
+
+        let mut sql = SqlBuilder::new("SELECT f.id FROM files f WHERE f.status = 'active' AND ");
+        sql.append(&compiled.condition).push(" ORDER BY f.id");
+
+        let mut stmt = conn.prepare(sql.sql())?;
+        let ids = stmt
+            .query_map(sql.params(), |row| row.get(0))?
+            .collect::<std::result::Result<Vec<i64>, _>>()?;
+
+        Ok(ids)
+    }
+}
+
+/// 编译后的搜索条件
+struct CompiledQuery {
+    /// 针对 `files f` 的 WHERE 条件（已加括号）
+    condition: SqlBuilder,
+    /// 用于 bm25 排序的 FTS 查询
+    rank_query: Option<String>,
+    /// 模糊匹配到的文件及其编辑距离（多个关键字时取最小值）
+    distances: HashMap<i64, usize>,
+    /// 需要在文件名中标出的关键字与短语
+    highlight_terms: Vec<String>,
+}
+
+fn compile_query(conn: &rusqlite::Connection, query: &SearchQuery) -> Result<CompiledQuery> {
+    let mut compiler = Compiler {
+        conn,
+        fuzzy: query.fuzzy,
+        rank_terms: Vec::new(),
+        distances: HashMap::new(),
+        highlight_terms: Vec::new(),
+    };
+    let condition = match &query.expr {
+        Some(expr) => compiler.expr(expr, false)?,
+        None => SqlBuilder::new("1"),
+    };
+
+    let rank_query = (!compiler.rank_terms.is_empty()).then(|| compiler.rank_terms.join(" OR "));
+
+    Ok(CompiledQuery {
+        condition: SqlBuilder::join(&[condition], ""),
+        rank_query,
+        distances: compiler.distances,
+        highlight_terms: compiler.highlight_terms,
+    })
+}
+
+struct Compiler<'a> {
+    conn: &'a rusqlite::Connection,
+    fuzzy: Option<FuzzyOptions>,
+    rank_terms: Vec<String>,
+    distances: HashMap<i64, usize>,
+    highlight_terms: Vec<String>,
+}
+
+impl Compiler<'_> {
+    /// 编译表达式；`negated` 表示位于奇数层排除之内，此时关键字不参与排序
+    fn expr(&mut self, expr: &QueryExpr, negated: bool) -> Result<SqlBuilder> {
+        match expr {
+            QueryExpr::And(items) if items.is_empty() => Ok(SqlBuilder::new("1")),
+            QueryExpr::Or(items) if items.is_empty() => Ok(SqlBuilder::new("0")),
+            QueryExpr::And(items) => self.join(items, " AND ", negated),
+            QueryExpr::Or(items) => self.join(items, " OR ", negated),
+            QueryExpr::Not(inner) => {
+                let mut sql = SqlBuilder::new("NOT ");
+                sql.append(&SqlBuilder::join(&[self.expr(inner, !negated)?], ""));
+                Ok(sql)
+            }
+            QueryExpr::Term(term) => self.term(term, negated),
+        }
+    }
+
+    fn join(&mut self, items: &[QueryExpr], separator: &str, negated: bool) -> Result<SqlBuilder> {
+        let parts = items
+            .iter()
+            .map(|item| self.expr(item, negated))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SqlBuilder::join(&parts, separator))
+    }
+
+    fn term(&mut self, term: &QueryTerm, negated: bool) -> Result<SqlBuilder> {
+        let mut sql = SqlBuilder::default();
+        match term {
+            QueryTerm::Keyword(keyword) => {
+                // 关键字是标签别名时同时匹配规范标签名，如 `pics` 展开为 `("pics" OR "图片")`
+                let canonical = resolve_tag_name(self.conn, keyword)?;
+                let fts = if canonical.eq_ignore_ascii_case(keyword.trim()) {
+                    quote_fts(keyword)
+                } else {
+                    format!("({} OR {})", quote_fts(keyword), quote_fts(&canonical))
+                };
+                let exact = self.fts(fts, negated);
+                if negated {
+                    return Ok(exact);
+                }
+                self.highlight_terms.push(keyword.clone());
+
+                // 排除条件不做模糊匹配，以免误排除拼写相近的文件
+                let Some(fuzzy) = self.fuzzy else { return Ok(exact) };
+                let candidates = fuzzy_candidates(self.conn, keyword, fuzzy.max_distance)?;
+                if candidates.is_empty() {
+                    return Ok(exact);
+                }
+                let ids: Vec<i64> = candidates.iter().map(|&(id, _)| id).collect();
+                for (id, distance) in candidates {
+                    let best = self.distances.entry(id).or_insert(distance);
+                    *best = (*best).min(distance);
+                }
+                let mut matched = SqlBuilder::new("f.id IN (SELECT value FROM json_each(");
+                matched.bind(serde_json::to_string(&ids).unwrap()).push("))");
+                return Ok(SqlBuilder::join(&[exact, matched], " OR "));
+            }
+            QueryTerm::Phrase(phrase) => {
+                if !negated {
+                    self.highlight_terms.push(phrase.clone());
+                }
+                return Ok(self.fts(quote_fts(phrase), negated));
+            }
+            QueryTerm::Tag(name) => {
+                // 按父标签过滤时包含其所有子标签
+                sql.push("EXISTS (SELECT 1 FROM file_tags ft WHERE ft.file_id = f.id AND ft.tag_id IN (")
+                    .append(&SqlBuilder::fragment(&tag_subtree_sql("?"), [resolve_tag_name(self.conn, name)?.into()]))
+                    .push("))");
+            }
+            QueryTerm::Type(file_type) => {
+                sql.push("f.file_type = ").bind(file_type.to_string());
+            }
+            QueryTerm::Extension(ext) => {
+                sql.push("f.extension = ").bind(ext.trim_start_matches('.').to_string()).push(" COLLATE NOCASE");
+            }
+            QueryTerm::Size { op, bytes } => {
+                sql.push("f.size ").push(op.as_sql()).push(" ").bind(*bytes);
+            }
+            QueryTerm::Modified { from, until } => {
+                sql.push("(1");
+                if let Some(from) = from {
+                    sql.push(" AND f.modified_at >= ").bind(local_midnight(*from));
+                }
+                if let Some(until) = until {
+                    sql.push(" AND f.modified_at < ").bind(local_midnight(*until));
+                }
+                sql.push(")");
+            }
+            QueryTerm::Path(path) => return Ok(path_condition(path)),
+            QueryTerm::Attribute(filter) => {
+                match attribute_filter_sql(self.conn, std::slice::from_ref(filter))? {
+                    Some((clause, values)) => return Ok(SqlBuilder::fragment(&clause, values)),
+                    None => {
+                        sql.push("1");
+                    }
+                }
+            }
+        }
+        Ok(sql)
+    }
+
+    /// 全文索引条件；未被排除的关键字同时用于相关度排序
+    fn fts(&mut self, fts: String, negated: bool) -> SqlBuilder {
+        if !negated {
+            self.rank_terms.push(fts.clone());
+        }
+        let mut sql = SqlBuilder::new("f.id IN (SELECT file_id FROM file_tags_content WHERE file_tags_content MATCH ");
+        sql.bind(fts).push(")");
+        sql
+    }
+}
+
+/// 路径条件：绝对路径（`~` 展开为主目录）匹配该目录及其下所有文件，其他值匹配路径片段
+fn path_condition(path: &str) -> SqlBuilder {
+    let expanded = match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => dirs::home_dir()
+            .map(|home| format!("{}{}", home.to_string_lossy(), rest))
+            .unwrap_or_else(|| path.to_string()),
+        _ => path.to_string(),
+    };
+
+    let mut sql = SqlBuilder::default();
+    if Path::new(&expanded).is_absolute() {
+        let trimmed = expanded.trim_end_matches(['/', '\\']);
+        let dir = if trimmed.is_empty() { expanded.as_str() } else { trimmed };
+        sql.push("(f.path = ").bind(dir.to_string())
+            .push(" OR substr(f.path, 1, length(").bind(dir.to_string())
+            .push(") + 1) IN (").bind(format!("{}/", dir))
+            .push(", ").bind(format!("{}\\", dir))
+            .push("))");
+    } else {
+        sql.push("f.path LIKE ").bind(format!("%{}%", escape_like(&expanded))).push(" ESCAPE '\\'");
+    }
+    sql
+}
+
+/// 将文本包装为 FTS 短语，内部引号加倍转义
+///
+/// FTS5 按 C 字符串解析查询，NUL 之后的内容会被截断而导致引号不配对，因此替换为空格。
+pub(super) fn quote_fts(text: &str) -> String {
+    format!("\"{}\"", text.replace('\0', " ").replace('"', "\"\""))
+}
+
+/// 本地时区某日零点的时间戳
+fn local_midnight(date: NaiveDate) -> i64 {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("零点总是有效时间");
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| midnight.and_utc().with_timezone(&Local))
+        .timestamp()
+}
+
+/// 转义 LIKE 模式中的通配符，配合 `ESCAPE '\'` 使用
+pub(super) fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,65 @@
+use rusqlite::types::Value;
+
+/// 参数化 SQL 构建器
+///
+/// SQL 文本只能由代码中的常量片段拼成，所有来自用户的值都通过 `bind` 以 `?` 占位符加入，
+/// 参数顺序与占位符出现的顺序始终一致。
+#[derive(Debug, Clone, Default)]
+pub(super) struct SqlBuilder {
+    sql: String,
+    params: Vec<Value>,
+}
+
+impl SqlBuilder {
+    pub fn new(sql: &str) -> Self {
+        SqlBuilder { sql: sql.to_string(), params: Vec::new() }
+    }
+
+    /// 由已含占位符的片段和对应参数构建，如 `attribute_filter_sql` 的结果
+    pub fn fragment(sql: &str, params: impl IntoIterator<Item = Value>) -> Self {
+        let params: Vec<Value> = params.into_iter().collect();
+        debug_assert_eq!(sql.matches('?').count(), params.len(), "占位符与参数数量不一致: {}", sql);
+        SqlBuilder { sql: sql.to_string(), params }
+    }
+
+    /// 追加 SQL 文本（不得包含用户输入）
+    pub fn push(&mut self, sql: &str) -> &mut Self {
+        self.sql.push_str(sql);
+        self
+    }
+
+    /// 追加一个占位符并绑定参数
+    pub fn bind(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.sql.push('?');
+        self.params.push(value.into());
+        self
+    }
+
+    /// 追加另一个构建器的 SQL 与参数
+    pub fn append(&mut self, other: &SqlBuilder) -> &mut Self {
+        self.sql.push_str(&other.sql);
+        self.params.extend(other.params.iter().cloned());
+        self
+    }
+
+    /// 用 `separator` 连接多个片段，整体加括号
+    pub fn join(parts: &[SqlBuilder], separator: &str) -> Self {
+        let mut joined = SqlBuilder::new("(");
+        for (i, part) in parts.iter().enumerate() {
+            if i > 0 {
+                joined.push(separator);
+            }
+            joined.append(part);
+        }
+        joined.push(")");
+        joined
+    }
+
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn params(&self) -> rusqlite::ParamsFromIter<&Vec<Value>> {
+        rusqlite::params_from_iter(&self.params)
+    }
+}
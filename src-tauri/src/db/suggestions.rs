@@ -0,0 +1,288 @@
+use super::models::*;
+use super::search::escape_like;
+use super::Database;
+use crate::error::{AppError, Result};
+use rusqlite::{params, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+
+/// 三类依据的权重，各项得分均在 0..=1 之间，加权后综合得分同样在 0..=1 之间
+const CO_OCCURRENCE_WEIGHT: f64 = 0.5;
+const SIBLING_WEIGHT: f64 = 0.3;
+const SIMILAR_NAME_WEIGHT: f64 = 0.2;
+
+/// 文件名词元的 Jaccard 相似度达到该值时视为名称相似
+const SIMILAR_NAME_THRESHOLD: f64 = 0.5;
+/// 参与查找相似文件的词元数量，以及每个词元最多取出的候选文件数
+const SIMILAR_NAME_TOKENS: usize = 3;
+const SIMILAR_NAME_CANDIDATES: i64 = 200;
+/// 相似文件名依据中最多列出的示例文件数
+const SIMILAR_NAME_EXAMPLES: usize = 3;
+
+/// 与文件 ?1 位于同一目录 ?2 下的其他活跃文件（不含子目录）
+const SIBLING_CONDITION: &str = "f.status = 'active' AND f.id != ?1
+     AND substr(f.path, 1, length(?2) + 1) IN (?2 || '/', ?2 || '\\')
+     AND instr(substr(f.path, length(?2) + 2), '/') = 0
+     AND instr(substr(f.path, length(?2) + 2), '\\') = 0";
+
+/// 单个候选标签的累计得分
+#[derive(Default)]
+struct Candidate {
+    score: f64,
+    reasons: Vec<SuggestionReason>,
+}
+
+impl Database {
+    /// 为文件推荐标签
+    ///
+    /// 候选标签来自三类依据：与文件已有标签的共现统计、同目录文件的标签、名称相似文件的标签。
+    /// 只统计其他活跃文件上手动添加的标签，文件已有的标签不会被推荐。结果按得分降序排列，
+    /// 每条建议附带得分来源。
+    pub fn suggest_tags(&self, file_id: i64, limit: usize) -> Result<Vec<TagSuggestion>> {
+        let conn = self.conn.lock();
+
+        let (path, name): (String, String) = conn
+            .query_row(
+                "SELECT path, name FROM files WHERE id = ?1",
+                params![file_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+            .ok_or_else(|| AppError::FileNotFound(file_id.to_string()))?;
+
+        let existing: HashSet<i64> = {
+            let mut stmt = conn.prepare("SELECT tag_id FROM file_tags WHERE file_id = ?1")?;
+            let ids = stmt
+                .query_map(params![file_id], |row| row.get(0))?
+                .collect::<std::result::Result<_, _>>()?;
+            ids
+        };
+
+        let mut candidates: HashMap<i64, Candidate> = HashMap::new();
+        co_occurrence(&conn, file_id, &mut candidates)?;
+        if let Some(dir) = parent_directory(&path) {
+            sibling_files(&conn, file_id, dir, &mut candidates)?;
+        }
+        similar_names(&conn, file_id, &name, &mut candidates)?;
+
+        let mut tag_stmt = conn.prepare(
+            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at, parent_id
+             FROM tags WHERE id = ?1",
+        )?;
+        let mut suggestions = Vec::new();
+        for (tag_id, candidate) in candidates {
+            if existing.contains(&tag_id) {
+                continue;
+            }
+            let mut rows = tag_stmt.query(params![tag_id])?;
+            if let Some(row) = rows.next()? {
+                suggestions.push(TagSuggestion {
+                    tag: self.row_to_tag(row)?,
+                    score: candidate.score,
+                    reasons: candidate.reasons,
+                });
+            }
+        }
+
+        suggestions.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then(b.tag.use_count.cmp(&a.tag.use_count))
+                .then_with(|| a.tag.name.cmp(&b.tag.name))
+        });
+        suggestions.truncate(limit);
+
+        Ok(suggestions)
+    }
+}
+
+/// 共现统计：对文件已有的每个标签，统计带有它的其他文件中同时带有候选标签的比例，取比例最高的一项
+fn co_occurrence(conn: &rusqlite::Connection, file_id: i64, candidates: &mut HashMap<i64, Candidate>) -> Result<()> {
+    let totals: HashMap<i64, i64> = {
+        let mut stmt = conn.prepare(
+            "SELECT ft.tag_id, COUNT(*) FROM file_tags ft JOIN files f ON ft.file_id = f.id
+             WHERE ft.tag_id IN (SELECT tag_id FROM file_tags WHERE file_id = ?1)
+               AND ft.file_id != ?1 AND f.status = 'active'
+             GROUP BY ft.tag_id",
+        )?;
+        let totals = stmt
+            .query_map(params![file_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        totals
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT src.tag_id, t.name, cand.tag_id, COUNT(*)
+         FROM file_tags src
+         JOIN files f ON src.file_id = f.id
+         JOIN file_tags cand ON cand.file_id = src.file_id AND cand.tag_id != src.tag_id AND cand.is_auto = 0
+         JOIN tags t ON src.tag_id = t.id
+         WHERE src.tag_id IN (SELECT tag_id FROM file_tags WHERE file_id = ?1)
+           AND src.file_id != ?1 AND f.status = 'active'
+         GROUP BY src.tag_id, cand.tag_id",
+    )?;
+    let rows = stmt
+        .query_map(params![file_id], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, i64>(2)?, row.get::<_, i64>(3)?))
+        })?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    // 每个候选标签只保留比例最高的来源标签
+    let mut best: HashMap<i64, (String, i64, f64)> = HashMap::new();
+    for (source_id, source_name, tag_id, shared) in rows {
+        let Some(&total) = totals.get(&source_id) else { continue };
+        let ratio = shared as f64 / total as f64;
+        let replace = best
+            .get(&tag_id)
+            .is_none_or(|&(_, best_shared, best_ratio)| (ratio, shared) > (best_ratio, best_shared));
+        if replace {
+            best.insert(tag_id, (source_name, shared, ratio));
+        }
+    }
+
+    for (tag_id, (tag_name, shared_files, ratio)) in best {
+        let candidate = candidates.entry(tag_id).or_default();
+        candidate.score += CO_OCCURRENCE_WEIGHT * ratio;
+        candidate.reasons.push(SuggestionReason::CoOccurrence { tag_name, shared_files, ratio });
+    }
+
+    Ok(())
+}
+
+/// 同目录文件：候选标签在同目录其他文件中出现的比例
+fn sibling_files(conn: &rusqlite::Connection, file_id: i64, dir: &str, candidates: &mut HashMap<i64, Candidate>) -> Result<()> {
+    let total: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM files f WHERE {}", SIBLING_CONDITION),
+        params![file_id, dir],
+        |row| row.get(0),
+    )?;
+    if total == 0 {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT ft.tag_id, COUNT(*) FROM file_tags ft JOIN files f ON ft.file_id = f.id
+         WHERE ft.is_auto = 0 AND {}
+         GROUP BY ft.tag_id",
+        SIBLING_CONDITION
+    ))?;
+    let rows = stmt
+        .query_map(params![file_id, dir], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    for (tag_id, matched) in rows {
+        let candidate = candidates.entry(tag_id).or_default();
+        candidate.score += SIBLING_WEIGHT * matched as f64 / total as f64;
+        candidate.reasons.push(SuggestionReason::SiblingFiles { matched, total });
+    }
+
+    Ok(())
+}
+
+/// 相似文件名：按文件名词元的 Jaccard 相似度找出相似文件，统计候选标签在其中出现的比例
+fn similar_names(conn: &rusqlite::Connection, file_id: i64, name: &str, candidates: &mut HashMap<i64, Candidate>) -> Result<()> {
+    let tokens = name_tokens(name);
+    if tokens.is_empty() {
+        return Ok(());
+    }
+
+    // 用最长的几个词元粗筛候选文件，再计算相似度
+    let mut probes: Vec<&String> = tokens.iter().collect();
+    probes.sort_by(|a, b| b.chars().count().cmp(&a.chars().count()).then(a.cmp(b)));
+    probes.truncate(SIMILAR_NAME_TOKENS);
+
+    let mut similar: Vec<(i64, String)> = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stmt = conn.prepare(
+        "SELECT id, name FROM files
+         WHERE status = 'active' AND id != ?1 AND name LIKE ?2 ESCAPE '\\'
+         ORDER BY id LIMIT ?3",
+    )?;
+    for probe in probes {
+        let pattern = format!("%{}%", escape_like(probe));
+        let rows = stmt
+            .query_map(params![file_id, pattern, SIMILAR_NAME_CANDIDATES], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        for (id, other) in rows {
+            if seen.insert(id) && jaccard(&tokens, &name_tokens(&other)) >= SIMILAR_NAME_THRESHOLD {
+                similar.push((id, other));
+            }
+        }
+    }
+    if similar.is_empty() {
+        return Ok(());
+    }
+
+    let mut matches: HashMap<i64, Vec<&str>> = HashMap::new();
+    let mut tag_stmt = conn.prepare("SELECT tag_id FROM file_tags WHERE file_id = ?1 AND is_auto = 0")?;
+    for (id, other) in &similar {
+        let tag_ids = tag_stmt
+            .query_map(params![id], |row| row.get::<_, i64>(0))?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        for tag_id in tag_ids {
+            matches.entry(tag_id).or_default().push(other);
+        }
+    }
+
+    let total = similar.len() as i64;
+    for (tag_id, names) in matches {
+        let matched = names.len() as i64;
+        let candidate = candidates.entry(tag_id).or_default();
+        candidate.score += SIMILAR_NAME_WEIGHT * matched as f64 / total as f64;
+        candidate.reasons.push(SuggestionReason::SimilarNames {
+            matched,
+            total,
+            examples: names.into_iter().take(SIMILAR_NAME_EXAMPLES).map(str::to_string).collect(),
+        });
+    }
+
+    Ok(())
+}
+
+/// 文件所在目录，没有目录部分时返回 None
+fn parent_directory(path: &str) -> Option<&str> {
+    path.rfind(['/', '\\']).map(|index| &path[..index])
+}
+
+/// 将文件名（不含扩展名）拆分为小写词元
+///
+/// 以非字母数字字符以及字母与数字的交界处分隔，纯数字（日期、序号等）和单个字符的词元被忽略，
+/// 如 `Invoice_2024-03_acme.pdf` 得到 `invoice`、`acme`。
+fn name_tokens(name: &str) -> HashSet<String> {
+    let stem = match name.rfind('.') {
+        Some(index) if index > 0 => &name[..index],
+        _ => name,
+    };
+
+    let mut tokens = HashSet::new();
+    let mut current = String::new();
+    let mut current_is_digit = false;
+    for c in stem.chars().flat_map(char::to_lowercase) {
+        if !c.is_alphanumeric() || (!current.is_empty() && c.is_ascii_digit() != current_is_digit) {
+            push_token(&mut tokens, std::mem::take(&mut current));
+        }
+        if c.is_alphanumeric() {
+            current_is_digit = c.is_ascii_digit();
+            current.push(c);
+        }
+    }
+    push_token(&mut tokens, current);
+
+    tokens
+}
+
+fn push_token(tokens: &mut HashSet<String>, token: String) {
+    if token.chars().count() >= 2 && !token.chars().all(|c| c.is_ascii_digit()) {
+        tokens.insert(token);
+    }
+}
+
+/// 两个词元集合的 Jaccard 相似度
+fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
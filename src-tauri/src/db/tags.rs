@@ -0,0 +1,663 @@
+use super::directory_tags::sync_derived_tags;
+use super::groups::group_for_tag_name;
+use super::implications::{files_with_tag, sync_implied_tags};
+use super::models::*;
+use super::Database;
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, OptionalExtension};
+use std::collections::HashMap;
+
+/// 配置项：为 true 时允许删除、改名、移动或合并系统标签
+const ALLOW_SYSTEM_TAG_CHANGES_KEY: &str = "allow_system_tag_changes";
+
+impl Database {
+    /// 获取标签树（同级标签按使用次数排序）
+    pub fn get_tag_tree(&self) -> Result<Vec<TagNode>> {
+        let tags = self.get_all_tags()?;
+        let ids: Vec<i64> = tags.iter().filter_map(|t| t.id).collect();
+
+        let mut children: HashMap<Option<i64>, Vec<Tag>> = HashMap::new();
+        for tag in tags {
+            // 父标签不存在时按根标签处理
+            let parent = tag.parent_id.filter(|p| ids.contains(p));
+            children.entry(parent).or_default().push(tag);
+        }
+
+        Ok(build_tree(None, &mut children))
+    }
+
+    /// 将标签（连同其子树）移动到新的父标签下，None 表示移动到根
+    ///
+    /// 子树中所有标签的路径名随之更新，新路径与已有标签冲突时不做任何修改。
+    pub fn move_tag(&self, tag_id: i64, new_parent_id: Option<i64>) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        move_subtree(&tx, tag_id, new_parent_id)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 删除标签
+    ///
+    /// `reassign_children` 为 true 时子标签上移到被删除标签的父标签下，否则连同整个子树一起删除。
+    pub fn delete_tag(&self, tag_id: i64, reassign_children: bool) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let (name, parent_id) = tag_name_and_parent(&tx, tag_id)?;
+
+        ensure_editable(&tx, &[tag_id])?;
+
+        let mut doomed = vec![tag_id];
+        if reassign_children {
+            for child in child_ids(&tx, tag_id)? {
+                move_subtree(&tx, child, parent_id)?;
+            }
+        } else {
+            doomed.extend(
+                subtree(&tx, &name)?
+                    .into_iter()
+                    .map(|(id, _)| id)
+                    .filter(|id| *id != tag_id),
+            );
+            ensure_editable(&tx, &doomed)?;
+        }
+
+        // 先删除关联，由触发器更新各文件的 FTS tag_names
+        let mut affected = Vec::new();
+        for id in &doomed {
+            affected.extend(files_with_tag(&tx, *id)?);
+            tx.execute("DELETE FROM file_tags WHERE tag_id = ?1", params![id])?;
+        }
+        for id in doomed.iter().rev() {
+            tx.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+        }
+
+        // 由被删除标签推导出的标签随之移除，被它压制的继承标签随之恢复
+        affected.sort_unstable();
+        affected.dedup();
+        sync_derived_tags(&tx, &affected)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 将多个标签合并到目标标签
+    ///
+    /// 在一个事务中：文件关联改指向目标标签（同一文件已有目标标签时去重，任一方为手动标签则保留为手动），
+    /// 子标签移到目标标签下，源标签名及其别名成为目标标签的别名，重建 FTS 中的标签名，
+    /// 最后删除源标签。
+    pub fn merge_tags(&self, source_ids: &[i64], target_id: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        merge_into(&tx, source_ids, target_id)?;
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// 修改标签名称（search 与 FTS 使用的 name）
+    ///
+    /// 新名称为路径时自动创建上级标签；子标签的路径名和所有相关文件在 FTS 中的标签名一并更新。
+    /// 新名称已被其他标签（或其别名）占用时，`merge_on_conflict` 为 false 返回冲突供界面提示合并，
+    /// 为 true 则将该标签合并到已有标签。
+    pub fn rename_tag(&self, tag_id: i64, new_name: &str, merge_on_conflict: bool) -> Result<RenameTagResult> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+
+        let new_name = normalize_tag_name(new_name);
+        if new_name.is_empty() {
+            return Err(AppError::InvalidTag("标签名不能为空".to_string()));
+        }
+
+        let (name, _) = tag_name_and_parent(&tx, tag_id)?;
+        if new_name != name {
+            ensure_editable(&tx, &[tag_id])?;
+        }
+        if new_name.starts_with(&format!("{}/", name)) {
+            return Err(AppError::InvalidTag(format!(
+                "不能将标签 '{}' 改名为其子路径 '{}'",
+                name, new_name
+            )));
+        }
+
+        // 新名称是其他标签本身或其别名时视为冲突
+        let canonical = resolve_tag_name(&tx, &new_name)?;
+        let conflict = find_tag_id(&tx, &canonical)?.filter(|id| *id != tag_id);
+
+        if let Some(existing_id) = conflict {
+            if !merge_on_conflict {
+                let existing = self.get_tag_in(&tx, existing_id)?;
+                return Ok(RenameTagResult::Conflict { existing });
+            }
+
+            merge_into(&tx, &[tag_id], existing_id)?;
+            let tag = self.get_tag_in(&tx, existing_id)?;
+            tx.commit()?;
+            return Ok(RenameTagResult::Merged { tag });
+        }
+
+        if new_name != name {
+            // 改为自身别名时，该别名不再需要
+            tx.execute(
+                "DELETE FROM tag_aliases WHERE alias = ?1 AND tag_id = ?2",
+                params![new_name, tag_id],
+            )?;
+
+            let (tag_type, color): (String, String) = tx.query_row(
+                "SELECT tag_type, color FROM tags WHERE id = ?1",
+                params![tag_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let new_parent_id = match parent_path(&new_name) {
+                Some(parent) => Some(get_or_create_tag_path(&tx, parent, &TagType::from_str(&tag_type), &color)?),
+                None => None,
+            };
+
+            rename_subtree(&tx, tag_id, &name, &new_name, new_parent_id)?;
+
+            // 显示名称沿用旧名称时随之更新
+            tx.execute(
+                "UPDATE tags SET display_name = ?1 WHERE id = ?2 AND display_name IN (?3, ?4)",
+                params![leaf_name(&new_name), tag_id, name, leaf_name(&name)],
+            )?;
+        }
+
+        let tag = self.get_tag_in(&tx, tag_id)?;
+        tx.commit()?;
+
+        Ok(RenameTagResult::Renamed { tag })
+    }
+
+    /// 在给定连接上按 ID 读取标签
+    fn get_tag_in(&self, conn: &rusqlite::Connection, tag_id: i64) -> Result<Tag> {
+        conn.query_row(
+            "SELECT id, name, display_name, tag_type, color, icon, use_count, created_at, parent_id
+             FROM tags WHERE id = ?1",
+            params![tag_id],
+            |row| Ok(self.row_to_tag(row)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::TagNotFound(tag_id.to_string()))?
+    }
+
+    /// 为标签添加别名
+    ///
+    /// 别名不能与已有标签重名，也不能已指向其他标签。
+    pub fn add_tag_alias(&self, tag_id: i64, alias: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        let alias = normalize_tag_name(alias);
+        if alias.is_empty() {
+            return Err(AppError::InvalidTag("别名不能为空".to_string()));
+        }
+
+        tag_name_and_parent(&conn, tag_id)?;
+
+        let tag_exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM tags WHERE name = ?1 COLLATE NOCASE)",
+            params![alias],
+            |row| row.get(0),
+        )?;
+        if tag_exists {
+            return Err(AppError::TagConflict(alias));
+        }
+
+        let existing: Option<i64> = conn.query_row(
+            "SELECT tag_id FROM tag_aliases WHERE alias = ?1",
+            params![alias],
+            |row| row.get(0),
+        ).optional()?;
+        match existing {
+            Some(id) if id == tag_id => return Ok(()),
+            Some(_) => return Err(AppError::TagConflict(alias)),
+            None => {}
+        }
+
+        conn.execute(
+            "INSERT INTO tag_aliases (alias, tag_id, created_at) VALUES (?1, ?2, ?3)",
+            params![alias, tag_id, Utc::now().timestamp()],
+        )?;
+
+        Ok(())
+    }
+
+    /// 删除别名
+    pub fn remove_tag_alias(&self, alias: &str) -> Result<()> {
+        let conn = self.conn.lock();
+
+        conn.execute(
+            "DELETE FROM tag_aliases WHERE alias = ?1",
+            params![normalize_tag_name(alias)],
+        )?;
+
+        Ok(())
+    }
+
+    /// 获取别名列表，指定 tag_id 时只返回该标签的别名
+    pub fn get_tag_aliases(&self, tag_id: Option<i64>) -> Result<Vec<TagAlias>> {
+        let conn = self.conn.lock();
+
+        let mut stmt = conn.prepare(
+            "SELECT a.alias, a.tag_id, t.name, a.created_at
+             FROM tag_aliases a JOIN tags t ON a.tag_id = t.id
+             WHERE ?1 IS NULL OR a.tag_id = ?1
+             ORDER BY t.name, a.alias",
+        )?;
+
+        let mut aliases = Vec::new();
+        let mut rows = stmt.query(params![tag_id])?;
+        while let Some(row) = rows.next()? {
+            let created_at_ts: i64 = row.get(3)?;
+            aliases.push(TagAlias {
+                alias: row.get(0)?,
+                tag_id: row.get(1)?,
+                tag_name: row.get(2)?,
+                created_at: DateTime::from_timestamp(created_at_ts, 0)
+                    .ok_or_else(|| AppError::Unknown(format!("无效的时间戳: {}", created_at_ts)))?,
+            });
+        }
+
+        Ok(aliases)
+    }
+}
+
+/// 合并标签（供事务内使用）
+fn merge_into(conn: &rusqlite::Connection, source_ids: &[i64], target_id: i64) -> Result<()> {
+    let (target_name, _) = tag_name_and_parent(conn, target_id)?;
+
+    for &source_id in source_ids.iter().filter(|id| **id != target_id) {
+        let (source_name, _) = tag_name_and_parent(conn, source_id)?;
+        ensure_editable(conn, &[source_id])?;
+        if target_name.starts_with(&format!("{}/", source_name)) {
+            return Err(AppError::InvalidTag(format!(
+                "不能将标签 '{}' 合并到其子标签 '{}'",
+                source_name, target_name
+            )));
+        }
+
+        // 同一文件已有目标标签：任一方为手动标签时保留为手动
+        conn.execute(
+            "UPDATE file_tags SET is_auto = 0, rule_name = NULL, rule_detail = NULL, implied_by = NULL
+             WHERE tag_id = ?2 AND is_auto = 1
+               AND file_id IN (SELECT file_id FROM file_tags WHERE tag_id = ?1 AND is_auto = 0)",
+            params![source_id, target_id],
+        )?;
+
+        // 其余关联直接改指向目标标签，与目标标签重复的关联删除
+        conn.execute(
+            "UPDATE file_tags SET tag_id = ?2
+             WHERE tag_id = ?1 AND file_id NOT IN (SELECT file_id FROM file_tags WHERE tag_id = ?2)",
+            params![source_id, target_id],
+        )?;
+        conn.execute("DELETE FROM file_tags WHERE tag_id = ?1", params![source_id])?;
+
+        for child in child_ids(conn, source_id)? {
+            move_subtree(conn, child, Some(target_id))?;
+        }
+
+        // 推导规则改到目标标签上，指向自身的规则丢弃
+        conn.execute(
+            "UPDATE OR IGNORE tag_implications SET tag_id = ?2 WHERE tag_id = ?1",
+            params![source_id, target_id],
+        )?;
+        conn.execute(
+            "UPDATE OR IGNORE tag_implications SET implied_tag_id = ?2 WHERE implied_tag_id = ?1",
+            params![source_id, target_id],
+        )?;
+        conn.execute(
+            "DELETE FROM tag_implications WHERE tag_id = implied_tag_id OR ?1 IN (tag_id, implied_tag_id)",
+            params![source_id],
+        )?;
+
+        // 目录标签改到目标标签上
+        conn.execute(
+            "UPDATE OR IGNORE directory_tags SET tag_id = ?2 WHERE tag_id = ?1",
+            params![source_id, target_id],
+        )?;
+
+        // 源标签名和别名今后都解析到目标标签
+        conn.execute(
+            "UPDATE OR IGNORE tag_aliases SET tag_id = ?2 WHERE tag_id = ?1",
+            params![source_id, target_id],
+        )?;
+        conn.execute("DELETE FROM tags WHERE id = ?1", params![source_id])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO tag_aliases (alias, tag_id, created_at)
+             SELECT ?1, ?2, ?3 WHERE NOT EXISTS (SELECT 1 FROM tags WHERE name = ?1 COLLATE NOCASE)",
+            params![source_name, target_id, Utc::now().timestamp()],
+        )?;
+    }
+
+    // 来源标签变化后重新计算推导标签
+    sync_implied_tags(conn, &files_with_tag(conn, target_id)?)?;
+
+    // 改指向的关联不会触发 FTS 触发器，手动重建
+    refresh_tag_names(conn, &[target_id])
+}
+
+/// 将别名解析为规范标签名；名称本身就是标签或没有对应别名时原样返回
+pub(super) fn resolve_tag_name(conn: &rusqlite::Connection, name: &str) -> Result<String> {
+    let name = normalize_tag_name(name);
+
+    let resolved: Option<String> = conn.query_row(
+        "SELECT t.name FROM tag_aliases a JOIN tags t ON a.tag_id = t.id
+         WHERE a.alias = ?1 AND NOT EXISTS (SELECT 1 FROM tags WHERE name = ?1)",
+        params![name],
+        |row| row.get(0),
+    ).optional()?;
+
+    Ok(resolved.unwrap_or(name))
+}
+
+/// 批量解析别名
+pub(super) fn resolve_tag_names(conn: &rusqlite::Connection, names: &[String]) -> Result<Vec<String>> {
+    names.iter().map(|n| resolve_tag_name(conn, n)).collect()
+}
+
+/// 递归构建 `parent` 的子节点
+fn build_tree(parent: Option<i64>, children: &mut HashMap<Option<i64>, Vec<Tag>>) -> Vec<TagNode> {
+    children
+        .remove(&parent)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tag| {
+            let nodes = build_tree(tag.id, children);
+            TagNode { tag, children: nodes }
+        })
+        .collect()
+}
+
+/// 规范化层级标签名：去除各级首尾空白和空层级
+pub(super) fn normalize_tag_name(name: &str) -> String {
+    name.split('/')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// 层级标签名的最后一级
+fn leaf_name(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// 层级标签名的上级路径
+fn parent_path(name: &str) -> Option<&str> {
+    name.rsplit_once('/').map(|(parent, _)| parent)
+}
+
+/// 查询名称在 `names` 中的标签及其所有子孙标签 ID 的 SQL（`placeholders` 为 `?,?` 形式）
+pub(super) fn tag_subtree_sql(placeholders: &str) -> String {
+    format!(
+        "WITH RECURSIVE matched(id) AS (
+             SELECT id FROM tags WHERE name IN ({})
+             UNION
+             SELECT t.id FROM tags t JOIN matched m ON t.parent_id = m.id
+         )
+         SELECT id FROM matched",
+        placeholders
+    )
+}
+
+/// 插入标签，名称为路径时先创建缺失的上级标签（供事务内使用）
+pub(super) fn insert_tag(conn: &rusqlite::Connection, tag: &Tag) -> Result<i64> {
+    let name = normalize_tag_name(&tag.name);
+    if name.is_empty() {
+        return Err(AppError::InvalidTag("标签名不能为空".to_string()));
+    }
+
+    // 已是其他标签的别名时不再创建近似重复的标签
+    let alias_of: Option<String> = conn.query_row(
+        "SELECT t.name FROM tag_aliases a JOIN tags t ON a.tag_id = t.id WHERE a.alias = ?1",
+        params![name],
+        |row| row.get(0),
+    ).optional()?;
+    if let Some(canonical) = alias_of {
+        return Err(AppError::TagConflict(format!("{}（已是标签 '{}' 的别名）", name, canonical)));
+    }
+
+    // 自定义标签不能占用保留名称或已有系统标签的名称
+    if tag.tag_type == TagType::Custom {
+        let system_exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM tags WHERE name = ?1 AND tag_type = 'system')",
+            params![name],
+            |row| row.get(0),
+        )?;
+        if system_exists || is_reserved_tag_name(&name) {
+            return Err(AppError::ReservedTagName(name));
+        }
+    }
+
+    let parent_id = match (tag.parent_id, parent_path(&name)) {
+        (Some(id), _) => Some(id),
+        (None, Some(parent)) => Some(get_or_create_tag_path(conn, parent, &tag.tag_type, &tag.color)?),
+        (None, None) => None,
+    };
+
+    // `分组名:值` 形式的标签自动归入对应分组
+    let group_id = group_for_tag_name(conn, &name)?;
+
+    conn.execute(
+        "INSERT INTO tags (name, display_name, tag_type, color, icon, use_count, created_at, parent_id, group_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            name,
+            tag.display_name,
+            tag.tag_type.as_str(),
+            tag.color,
+            tag.icon.as_deref().unwrap_or(""),
+            tag.use_count,
+            Utc::now().timestamp(),
+            parent_id,
+            group_id,
+        ],
+    )?;
+
+    Ok(conn.last_insert_rowid())
+}
+
+/// 按路径获取标签 ID，不存在时逐级创建（供事务内使用）
+pub(super) fn get_or_create_tag_path(
+    conn: &rusqlite::Connection,
+    name: &str,
+    tag_type: &TagType,
+    color: &str,
+) -> Result<i64> {
+    let name = resolve_tag_name(conn, name)?;
+
+    if let Some(id) = conn.query_row(
+        "SELECT id FROM tags WHERE name = ?1",
+        params![name],
+        |row| row.get::<_, i64>(0),
+    ).optional()? {
+        return Ok(id);
+    }
+
+    // 保留名称的标签总是系统标签
+    let tag_type = if is_reserved_tag_name(&name) { TagType::System } else { tag_type.clone() };
+
+    insert_tag(conn, &Tag {
+        id: None,
+        name: name.clone(),
+        display_name: leaf_name(&name).to_string(),
+        tag_type,
+        color: color.to_string(),
+        icon: None,
+        use_count: 0,
+        created_at: Utc::now(),
+        parent_id: None,
+        value: None,
+        inherited_from: None,
+        implied_by: None,
+    })
+}
+
+/// 标签名称与父标签 ID
+pub(super) fn tag_name_and_parent(conn: &rusqlite::Connection, tag_id: i64) -> Result<(String, Option<i64>)> {
+    conn.query_row(
+        "SELECT name, parent_id FROM tags WHERE id = ?1",
+        params![tag_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()?
+    .ok_or_else(|| AppError::TagNotFound(tag_id.to_string()))
+}
+
+/// 直接子标签的 ID
+fn child_ids(conn: &rusqlite::Connection, tag_id: i64) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare("SELECT id FROM tags WHERE parent_id = ?1")?;
+    let ids = stmt
+        .query_map(params![tag_id], |row| row.get(0))?
+        .collect::<std::result::Result<Vec<i64>, _>>()?;
+    Ok(ids)
+}
+
+/// 名称为 `name` 或以 `name/` 开头的标签（即该标签及其子孙）
+fn subtree(conn: &rusqlite::Connection, name: &str) -> Result<Vec<(i64, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name FROM tags
+         WHERE name = ?1 OR substr(name, 1, length(?1) + 1) = ?1 || '/'
+         ORDER BY length(name)",
+    )?;
+    let tags = stmt
+        .query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<std::result::Result<Vec<(i64, String)>, _>>()?;
+    Ok(tags)
+}
+
+/// 移动标签子树并更新路径名（供事务内使用）
+fn move_subtree(conn: &rusqlite::Connection, tag_id: i64, new_parent_id: Option<i64>) -> Result<()> {
+    let (name, _) = tag_name_and_parent(conn, tag_id)?;
+
+    let new_name = match new_parent_id {
+        Some(parent_id) => {
+            let (parent_name, _) = tag_name_and_parent(conn, parent_id)?;
+            if parent_id == tag_id || parent_name.starts_with(&format!("{}/", name)) {
+                return Err(AppError::InvalidTag(format!(
+                    "不能将标签 '{}' 移动到自身或其子标签下",
+                    name
+                )));
+            }
+            format!("{}/{}", parent_name, leaf_name(&name))
+        }
+        None => leaf_name(&name).to_string(),
+    };
+
+    if let Some(existing) = find_tag_id(conn, &new_name)? {
+        if existing != tag_id {
+            return Err(AppError::TagConflict(new_name));
+        }
+    }
+
+    rename_subtree(conn, tag_id, &name, &new_name, new_parent_id)
+}
+
+/// 按名称查找标签 ID
+fn find_tag_id(conn: &rusqlite::Connection, name: &str) -> Result<Option<i64>> {
+    Ok(conn.query_row(
+        "SELECT id FROM tags WHERE name = ?1",
+        params![name],
+        |row| row.get(0),
+    ).optional()?)
+}
+
+/// 将标签 `name` 改名为 `new_name` 并挂到 `new_parent_id` 下，子孙标签的路径名随之更新（供事务内使用）
+///
+/// 子孙标签的新路径与子树之外的标签重名时返回 TagConflict，子树中有系统标签（且未允许修改）时返回
+/// SystemTagProtected，新路径为保留名称时返回 ReservedTagName，调用方负责回滚事务。
+fn rename_subtree(
+    conn: &rusqlite::Connection,
+    tag_id: i64,
+    name: &str,
+    new_name: &str,
+    new_parent_id: Option<i64>,
+) -> Result<()> {
+    let members = subtree(conn, name)?;
+    let renames: Vec<(i64, String)> = members
+        .iter()
+        .map(|(id, old)| (*id, format!("{}{}", new_name, &old[name.len()..])))
+        .collect();
+
+    if name != new_name {
+        ensure_editable(conn, &renames.iter().map(|(id, _)| *id).collect::<Vec<_>>())?;
+    }
+    for ((_, old), (_, renamed)) in members.iter().zip(&renames) {
+        if renamed != old && is_reserved_tag_name(renamed) {
+            return Err(AppError::ReservedTagName(renamed.clone()));
+        }
+    }
+
+    // 新路径不能与子树之外的标签重名
+    for (_, renamed) in &renames {
+        let existing = find_tag_id(conn, renamed)?;
+        if existing.is_some_and(|id| !members.iter().any(|(m, _)| *m == id)) {
+            return Err(AppError::TagConflict(renamed.clone()));
+        }
+    }
+
+    // 先改为临时名称（以 / 开头，规范化后的标签名不会出现），避免子树内部互相改名时触发唯一约束
+    for (id, _) in &renames {
+        conn.execute("UPDATE tags SET name = '/moving/' || id WHERE id = ?1", params![id])?;
+    }
+    for (id, renamed) in &renames {
+        conn.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![renamed, id])?;
+    }
+    conn.execute(
+        "UPDATE tags SET parent_id = ?1 WHERE id = ?2",
+        params![new_parent_id, tag_id],
+    )?;
+
+    let ids: Vec<i64> = renames.iter().map(|(id, _)| *id).collect();
+    refresh_tag_names(conn, &ids)
+}
+
+/// 重建关联了这些标签的文件在 FTS 中的 tag_names（标签改名后使用）
+pub(super) fn refresh_tag_names(conn: &rusqlite::Connection, tag_ids: &[i64]) -> Result<()> {
+    if tag_ids.is_empty() {
+        return Ok(());
+    }
+
+    let placeholders = tag_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let sql = format!(
+        "UPDATE file_tags_content
+         SET tag_names = (
+             SELECT group_concat(t.name, ',')
+             FROM file_tags ft JOIN tags t ON ft.tag_id = t.id
+             WHERE ft.file_id = file_tags_content.file_id
+         )
+         WHERE file_id IN (SELECT DISTINCT file_id FROM file_tags WHERE tag_id IN ({}))",
+        placeholders
+    );
+
+    conn.execute(&sql, rusqlite::params_from_iter(tag_ids))?;
+
+    Ok(())
+}
+
+/// 检查标签是否允许删除、改名、移动或合并：系统标签默认受保护，配置项允许时放行（供事务内使用）
+fn ensure_editable(conn: &rusqlite::Connection, tag_ids: &[i64]) -> Result<()> {
+    let allowed: Option<String> = conn.query_row(
+        "SELECT value FROM settings WHERE key = ?1",
+        params![ALLOW_SYSTEM_TAG_CHANGES_KEY],
+        |row| row.get(0),
+    ).optional()?;
+    if allowed.as_deref() == Some("true") {
+        return Ok(());
+    }
+
+    let mut stmt = conn.prepare("SELECT name FROM tags WHERE id = ?1 AND tag_type = 'system'")?;
+    for id in tag_ids {
+        if let Some(name) = stmt.query_row(params![id], |row| row.get::<_, String>(0)).optional()? {
+            return Err(AppError::SystemTagProtected(name));
+        }
+    }
+
+    Ok(())
+}
@@ -1,22 +1,17 @@
-use crate::db::{Database, File, Tag, FileType, FileStatus, TagType};
-use chrono::Utc;
-use rusqlite::{Connection, Result};
+use crate::db::{AttributeFilter, AutoTag, CompareOp, Database, File, FuzzyOptions, MatchHighlight, RenameTagResult, Tag, TagValue, FileType, FileStatus, TagType, QueryExpr, QueryParseError, QueryTerm, SearchQuery, SuggestionReason};
+use crate::error::AppError;
+use chrono::{Local, NaiveDate, TimeZone, Utc};
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::fuzzy::approximate_match;
+    use crate::db::tokenizer::{tokenize, Token};
+    use proptest::prelude::*;
 
     /// 创建内存测试数据库
     fn create_test_db() -> Database {
-        let conn = Connection::open_in_memory().unwrap();
-        // 执行 schema
-        let schema = include_str!("schema.sql");
-        conn.execute_batch(schema).unwrap();
-
-        // 将连接包装成 Database（需要手动实现）
-        Database {
-            conn: std::sync::Arc::new(std::sync::Mutex::new(conn)),
-        }
+        Database::in_memory().unwrap()
     }
 
     #[test]
@@ -36,6 +31,7 @@ mod tests {
             status: FileStatus::Active,
             indexed_at: Utc::now(),
             metadata: None,
+            tags: None,
         };
 
         let file_id = db.create_file(&file).unwrap();
@@ -59,6 +55,10 @@ mod tests {
             icon: None,
             use_count: 0,
             created_at: Utc::now(),
+            parent_id: None,
+            value: None,
+            inherited_from: None,
+            implied_by: None,
         };
 
         let tag_id = db.create_tag(&tag).unwrap();
@@ -87,6 +87,7 @@ mod tests {
             status: FileStatus::Active,
             indexed_at: Utc::now(),
             metadata: None,
+            tags: None,
         };
 
         let file_id = db.create_file(&file).unwrap();
@@ -109,4 +110,1454 @@ mod tests {
         assert_eq!(FileType::from_extension("exe"), FileType::Binary);
         assert_eq!(FileType::from_extension("unknown"), FileType::Other);
     }
+
+    /// 创建测试文件记录
+    fn insert_test_file(db: &Database, path: &str) -> i64 {
+        let file = File {
+            id: None,
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            extension: "txt".to_string(),
+            size: 100,
+            file_type: FileType::Text,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            accessed_at: Utc::now(),
+            status: FileStatus::Active,
+            indexed_at: Utc::now(),
+            metadata: None,
+            tags: None,
+        };
+        db.create_file(&file).unwrap()
+    }
+
+    /// 构造测试用自动标签
+    fn auto_tags(names: &[&str]) -> Vec<AutoTag> {
+        names
+            .iter()
+            .map(|name| AutoTag {
+                name: name.to_string(),
+                rule_name: name.to_string(),
+                rule_detail: "测试规则".to_string(),
+            })
+            .collect()
+    }
+
+    fn tag_names(db: &Database, file_id: i64) -> Vec<String> {
+        let mut names: Vec<String> = db.get_tags_by_file(file_id).unwrap().into_iter().map(|t| t.name).collect();
+        names.sort();
+        names
+    }
+
+    fn use_count(db: &Database, name: &str) -> i64 {
+        db.get_tag_by_name(name).unwrap().unwrap().use_count
+    }
+
+    #[test]
+    fn test_sync_auto_tags_removes_stale_auto_tags() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/big.bin");
+
+        db.sync_auto_tags(file_id, &auto_tags(&["大文件", "本月文件"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, file_id), vec!["大文件", "本月文件"]);
+        assert_eq!(use_count(&db, "大文件"), 1);
+
+        // 文件变小后重新索引
+        db.sync_auto_tags(file_id, &auto_tags(&["本月文件"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, file_id), vec!["本月文件"]);
+        assert_eq!(use_count(&db, "大文件"), 0);
+        assert_eq!(use_count(&db, "本月文件"), 1);
+    }
+
+    #[test]
+    fn test_sync_auto_tags_keeps_manual_tags() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/notes.txt");
+
+        db.add_tag_to_file_by_name(file_id, "工作", false).unwrap();
+        db.add_tag_to_file_by_name(file_id, "文本", false).unwrap();
+
+        // "文本" 已手动添加，自动同步不应重复计数，也不应在之后移除
+        db.sync_auto_tags(file_id, &auto_tags(&["文本"]), &[]).unwrap();
+        assert_eq!(use_count(&db, "文本"), 1);
+
+        db.sync_auto_tags(file_id, &auto_tags(&[]), &[]).unwrap();
+        assert_eq!(tag_names(&db, file_id), vec!["工作", "文本"]);
+        assert_eq!(use_count(&db, "工作"), 1);
+        assert_eq!(use_count(&db, "文本"), 1);
+    }
+
+    #[test]
+    fn test_sync_rule_tags_only_touches_given_rules() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/a.txt");
+
+        db.sync_auto_tags(file_id, &auto_tags(&["今日文件", "文本"]), &[]).unwrap();
+
+        // 只重新评估日期类规则，其他规则的标签保留
+        db.sync_rule_tags(file_id, &["今日文件".to_string()], &auto_tags(&[]), &[]).unwrap();
+        assert_eq!(tag_names(&db, file_id), vec!["文本"]);
+    }
+
+    #[test]
+    fn test_sync_auto_tags_keeps_tags_of_failed_rules() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/a.txt");
+
+        db.sync_auto_tags(file_id, &auto_tags(&["脚本", "文本"]), &[]).unwrap();
+
+        // "脚本" 规则本次执行失败（如超时），其标签不应被当作不再匹配而移除
+        db.sync_auto_tags(file_id, &auto_tags(&[]), &["脚本".to_string()]).unwrap();
+        assert_eq!(tag_names(&db, file_id), vec!["脚本"]);
+        assert_eq!(use_count(&db, "脚本"), 1);
+
+        db.sync_auto_tags(file_id, &auto_tags(&[]), &[]).unwrap();
+        assert!(tag_names(&db, file_id).is_empty());
+    }
+
+    #[test]
+    fn test_tag_explanation() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/movie.mkv");
+
+        db.sync_auto_tags(
+            file_id,
+            &[AutoTag {
+                name: "大文件".to_string(),
+                rule_name: "large files".to_string(),
+                rule_detail: "大小 ≥ 100MB".to_string(),
+            }],
+            &[],
+        ).unwrap();
+        db.add_tag_to_file_by_name(file_id, "电影", false).unwrap();
+
+        let auto_id = db.get_tag_by_name("大文件").unwrap().unwrap().id.unwrap();
+        let explanation = db.get_tag_explanation(file_id, auto_id).unwrap();
+        assert!(explanation.is_auto);
+        assert_eq!(explanation.rule_name.as_deref(), Some("large files"));
+        assert!(explanation.message.contains("由规则 'large files'（大小 ≥ 100MB）"));
+
+        let manual_id = db.get_tag_by_name("电影").unwrap().unwrap().id.unwrap();
+        let explanation = db.get_tag_explanation(file_id, manual_id).unwrap();
+        assert!(!explanation.is_auto);
+        assert!(explanation.message.starts_with("手动添加"));
+
+        assert!(db.get_tag_explanation(file_id, 9999).is_err());
+    }
+
+    fn tag_id(db: &Database, name: &str) -> i64 {
+        db.get_tag_by_name(name).unwrap().unwrap().id.unwrap()
+    }
+
+    fn file_paths(files: Vec<File>) -> Vec<String> {
+        let mut paths: Vec<String> = files.into_iter().map(|f| f.path).collect();
+        paths.sort();
+        paths
+    }
+
+    fn search_by_keyword(db: &Database, keyword: &str) -> Vec<String> {
+        let response = db.search_files(&SearchQuery::parse(keyword, 100, 0).unwrap()).unwrap();
+        file_paths(response.results.into_iter().map(|r| r.file).collect())
+    }
+
+    #[test]
+    fn test_hierarchical_tag_creates_parents() {
+        let db = create_test_db();
+        let file_id = insert_test_file(&db, "/test/a.pdf");
+
+        db.add_tag_to_file_by_name(file_id, "work/clientA/invoices", false).unwrap();
+
+        let work = tag_id(&db, "work");
+        let client = db.get_tag_by_name("work/clientA").unwrap().unwrap();
+        assert_eq!(client.parent_id, Some(work));
+        assert_eq!(client.display_name, "clientA");
+
+        let tree = db.get_tag_tree().unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].tag.name, "work");
+        assert_eq!(tree[0].children[0].children[0].tag.name, "work/clientA/invoices");
+    }
+
+    #[test]
+    fn test_parent_filter_includes_descendants() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.pdf");
+        let b = insert_test_file(&db, "/test/b.pdf");
+        let c = insert_test_file(&db, "/test/c.pdf");
+        db.add_tag_to_file_by_name(a, "work/clientA/invoices", false).unwrap();
+        db.add_tag_to_file_by_name(b, "work/clientB", false).unwrap();
+        db.add_tag_to_file_by_name(c, "home", false).unwrap();
+
+        let files = db.get_files_by_tags(&["work".to_string()]).unwrap();
+        assert_eq!(file_paths(files), vec!["/test/a.pdf", "/test/b.pdf"]);
+
+        let files = db.get_files_by_tags(&["work/clientA".to_string()]).unwrap();
+        assert_eq!(file_paths(files), vec!["/test/a.pdf"]);
+
+        let response = db.search_files(&SearchQuery::parse("pdf tag:work", 100, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 2);
+    }
+
+    #[test]
+    fn test_move_subtree_renames_and_refreshes_fts() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.pdf");
+        db.add_tag_to_file_by_name(a, "work/clientA/invoices", false).unwrap();
+        db.add_tag_to_file_by_name(a, "archive", false).unwrap();
+
+        db.move_tag(tag_id(&db, "work/clientA"), Some(tag_id(&db, "archive"))).unwrap();
+
+        assert!(db.get_tag_by_name("work/clientA").unwrap().is_none());
+        let moved = db.get_tag_by_name("archive/clientA/invoices").unwrap().unwrap();
+        assert_eq!(moved.parent_id, Some(tag_id(&db, "archive/clientA")));
+        assert_eq!(tag_names(&db, a), vec!["archive", "archive/clientA/invoices"]);
+        assert_eq!(search_by_keyword(&db, "clientA"), vec!["/test/a.pdf"]);
+
+        // 不能移动到自己的子标签下
+        let archive = tag_id(&db, "archive");
+        assert!(db.move_tag(archive, Some(moved.id.unwrap())).is_err());
+    }
+
+    #[test]
+    fn test_move_conflict_is_rejected() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.pdf");
+        db.add_tag_to_file_by_name(a, "work/invoices", false).unwrap();
+        db.add_tag_to_file_by_name(a, "invoices", false).unwrap();
+
+        let result = db.move_tag(tag_id(&db, "work/invoices"), None);
+        assert!(matches!(result, Err(crate::error::AppError::TagConflict(_))));
+        assert!(db.get_tag_by_name("work/invoices").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_delete_tag_with_and_without_reassign() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.pdf");
+        db.add_tag_to_file_by_name(a, "work/clientA/invoices", false).unwrap();
+        db.add_tag_to_file_by_name(a, "home/bills", false).unwrap();
+
+        db.delete_tag(tag_id(&db, "work"), true).unwrap();
+        assert!(db.get_tag_by_name("work").unwrap().is_none());
+        assert_eq!(db.get_tag_by_name("clientA").unwrap().unwrap().parent_id, None);
+        assert!(tag_names(&db, a).contains(&"clientA/invoices".to_string()));
+
+        db.delete_tag(tag_id(&db, "home"), false).unwrap();
+        assert!(db.get_tag_by_name("home/bills").unwrap().is_none());
+        assert_eq!(tag_names(&db, a), vec!["clientA/invoices"]);
+        assert!(search_by_keyword(&db, "bills").is_empty());
+    }
+
+    #[test]
+    fn test_aliases_resolve_when_tagging() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.jpg");
+        let b = insert_test_file(&db, "/test/b.jpg");
+        db.add_tag_to_file_by_name(a, "图片", false).unwrap();
+        let photos = tag_id(&db, "图片");
+        db.add_tag_alias(photos, "pics").unwrap();
+        db.add_tag_alias(photos, "Photos").unwrap();
+
+        db.add_tag_to_file_by_name(b, "PICS", false).unwrap();
+        db.batch_add_tags(&[a, b], &["photos".to_string()]).unwrap();
+
+        assert!(db.get_tag_by_name("pics").unwrap().is_none());
+        assert_eq!(tag_names(&db, b), vec!["图片"]);
+        assert_eq!(use_count(&db, "图片"), 2);
+        assert_eq!(db.get_tag_aliases(Some(photos)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_sync_auto_tags_with_alias_keeps_row() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.jpg");
+        db.sync_auto_tags(a, &auto_tags(&["图片"]), &[]).unwrap();
+        db.add_tag_alias(tag_id(&db, "图片"), "pics").unwrap();
+        db.conn.lock().execute("UPDATE file_tags SET created_at = 0", []).unwrap();
+
+        // 规则产生别名时对应规范标签，已有的关联不应被删除后重新插入
+        db.sync_auto_tags(a, &auto_tags(&["pics"]), &[]).unwrap();
+        let created_at: i64 = db
+            .conn
+            .lock()
+            .query_row("SELECT created_at FROM file_tags WHERE file_id = ?1", [a], |row| row.get(0))
+            .unwrap();
+        assert_eq!(created_at, 0);
+        assert_eq!(tag_names(&db, a), vec!["图片"]);
+        assert_eq!(use_count(&db, "图片"), 1);
+    }
+
+    #[test]
+    fn test_aliases_resolve_when_searching() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/holiday.jpg");
+        insert_test_file(&db, "/test/notes.txt");
+        db.add_tag_to_file_by_name(a, "photo", false).unwrap();
+        db.add_tag_alias(tag_id(&db, "photo"), "pics").unwrap();
+
+        let files = db.get_files_by_tags(&["pics".to_string()]).unwrap();
+        assert_eq!(file_paths(files), vec!["/test/holiday.jpg"]);
+
+        assert_eq!(search_by_keyword(&db, "pics"), vec!["/test/holiday.jpg"]);
+
+        let response = db.search_files(&SearchQuery::parse("holiday tag:pics", 100, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 1);
+    }
+
+    #[test]
+    fn test_alias_conflicts() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.jpg");
+        db.add_tag_to_file_by_name(a, "图片", false).unwrap();
+        db.add_tag_to_file_by_name(a, "视频", false).unwrap();
+        db.add_tag_alias(tag_id(&db, "图片"), "pics").unwrap();
+
+        // 别名不能是已有标签名，也不能指向两个标签
+        assert!(db.add_tag_alias(tag_id(&db, "图片"), "视频").is_err());
+        assert!(db.add_tag_alias(tag_id(&db, "视频"), "pics").is_err());
+
+        // 不能再创建与别名同名的标签
+        let tag = Tag {
+            id: None,
+            name: "pics".to_string(),
+            display_name: "pics".to_string(),
+            tag_type: TagType::Custom,
+            color: "#FF0000".to_string(),
+            icon: None,
+            use_count: 0,
+            created_at: Utc::now(),
+            parent_id: None,
+            value: None,
+            inherited_from: None,
+            implied_by: None,
+        };
+        assert!(db.create_tag(&tag).is_err());
+
+        db.remove_tag_alias("pics").unwrap();
+        assert!(db.create_tag(&tag).is_ok());
+    }
+
+    #[test]
+    fn test_merge_tags() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+        let c = insert_test_file(&db, "/test/c.txt");
+
+        db.add_tag_to_file_by_name(a, "report", false).unwrap();
+        db.add_tag_to_file_by_name(a, "reports", false).unwrap();
+        db.add_tag_to_file_by_name(b, "Report", false).unwrap();
+        db.sync_auto_tags(b, &auto_tags(&["reports"]), &[]).unwrap();
+        db.sync_auto_tags(c, &auto_tags(&["report"]), &[]).unwrap();
+        db.add_tag_to_file_by_name(c, "report/2024", false).unwrap();
+
+        let target = tag_id(&db, "reports");
+        db.merge_tags(&[tag_id(&db, "report"), tag_id(&db, "Report")], target).unwrap();
+
+        assert!(db.get_tag_by_name("report").unwrap().is_none());
+        assert!(db.get_tag_by_name("Report").unwrap().is_none());
+        assert_eq!(tag_names(&db, a), vec!["reports"]);
+        assert_eq!(tag_names(&db, b), vec!["reports"]);
+        assert_eq!(tag_names(&db, c), vec!["reports", "reports/2024"]);
+        assert_eq!(use_count(&db, "reports"), 3);
+
+        // b 上的手动标签与自动标签合并后保留为手动，c 上的自动标签仍为自动
+        assert!(!db.get_tag_explanation(b, target).unwrap().is_auto);
+        assert!(db.get_tag_explanation(c, target).unwrap().is_auto);
+
+        // 源标签名成为别名，FTS 中的标签名已更新
+        db.add_tag_to_file_by_name(a, "report", false).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["reports"]);
+        assert_eq!(search_by_keyword(&db, "reports").len(), 3);
+    }
+
+    #[test]
+    fn test_rename_tag_updates_subtree_and_fts() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+
+        db.add_tag_to_file_by_name(a, "draft", false).unwrap();
+        db.add_tag_to_file_by_name(b, "draft/old", false).unwrap();
+
+        let result = db.rename_tag(tag_id(&db, "draft"), "archive/drafts", false).unwrap();
+        match result {
+            RenameTagResult::Renamed { tag } => {
+                assert_eq!(tag.name, "archive/drafts");
+                assert_eq!(tag.display_name, "drafts");
+                assert_eq!(tag.parent_id, Some(tag_id(&db, "archive")));
+            }
+            other => panic!("应当改名成功: {:?}", other),
+        }
+
+        assert!(db.get_tag_by_name("draft").unwrap().is_none());
+        assert_eq!(tag_names(&db, a), vec!["archive/drafts"]);
+        assert_eq!(tag_names(&db, b), vec!["archive/drafts/old"]);
+        assert!(search_by_keyword(&db, "draft").is_empty());
+        assert_eq!(search_by_keyword(&db, "drafts").len(), 2);
+
+        // 不能改名为自身的子路径
+        assert!(db.rename_tag(tag_id(&db, "archive/drafts"), "archive/drafts/x", false).is_err());
+    }
+
+    #[test]
+    fn test_rename_tag_conflict_and_merge() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+
+        db.add_tag_to_file_by_name(a, "todo", false).unwrap();
+        db.add_tag_to_file_by_name(b, "tasks", false).unwrap();
+        db.add_tag_alias(tag_id(&db, "tasks"), "jobs").unwrap();
+
+        // 与已有标签或其别名重名时返回冲突且不做修改
+        for name in ["tasks", "jobs"] {
+            match db.rename_tag(tag_id(&db, "todo"), name, false).unwrap() {
+                RenameTagResult::Conflict { existing } => assert_eq!(existing.name, "tasks"),
+                other => panic!("应当返回冲突: {:?}", other),
+            }
+        }
+        assert_eq!(tag_names(&db, a), vec!["todo"]);
+
+        match db.rename_tag(tag_id(&db, "todo"), "tasks", true).unwrap() {
+            RenameTagResult::Merged { tag } => assert_eq!(tag.name, "tasks"),
+            other => panic!("应当合并: {:?}", other),
+        }
+        assert!(db.get_tag_by_name("todo").unwrap().is_none());
+        assert_eq!(tag_names(&db, a), vec!["tasks"]);
+        assert_eq!(use_count(&db, "tasks"), 2);
+        assert_eq!(search_by_keyword(&db, "tasks").len(), 2);
+    }
+
+    #[test]
+    fn test_implied_tags_follow_source_tag() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.pdf");
+        let b = insert_test_file(&db, "/test/b.pdf");
+
+        db.add_tag_to_file_by_name(a, "invoice", false).unwrap();
+        db.add_tag_to_file_by_name(b, "receipt", false).unwrap();
+        db.add_tag_to_file_by_name(b, "finance", false).unwrap();
+        db.add_tag_to_file_by_name(b, "archive", false).unwrap();
+        db.remove_tag_from_file(b, tag_id(&db, "archive")).unwrap();
+
+        // 添加规则后立即应用到已有来源标签的文件，推导可传递
+        db.add_tag_implication(tag_id(&db, "invoice"), tag_id(&db, "finance")).unwrap();
+        db.add_tag_implication(tag_id(&db, "finance"), tag_id(&db, "archive")).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["archive", "finance", "invoice"]);
+        assert_eq!(use_count(&db, "finance"), 2);
+
+        let explanation = db.get_tag_explanation(a, tag_id(&db, "finance")).unwrap();
+        assert_eq!(explanation.implied_by.as_deref(), Some("invoice"));
+        assert!(explanation.message.contains("invoice"));
+        assert!(db.get_tag_explanation(b, tag_id(&db, "finance")).unwrap().implied_by.is_none());
+
+        // 文件的标签列表标明推导来源
+        let implied_by = |file_id: i64, name: &str| {
+            db.get_tags_by_file(file_id).unwrap().into_iter().find(|t| t.name == name).unwrap().implied_by
+        };
+        assert_eq!(implied_by(a, "finance"), Some(tag_id(&db, "invoice")));
+        assert_eq!(implied_by(a, "archive"), Some(tag_id(&db, "finance")));
+        assert_eq!(implied_by(a, "invoice"), None);
+        assert_eq!(implied_by(b, "finance"), None);
+
+        // 推导标签不能单独移除
+        assert!(db.remove_tag_from_file(a, tag_id(&db, "finance")).is_err());
+
+        // 手动、批量与自动标签都会带上推导标签
+        let c = insert_test_file(&db, "/test/c.pdf");
+        let d = insert_test_file(&db, "/test/d.pdf");
+        let e = insert_test_file(&db, "/test/e.pdf");
+        db.add_tag_to_file(c, tag_id(&db, "invoice"), false).unwrap();
+        db.batch_add_tags(&[d], &["invoice".to_string()]).unwrap();
+        db.sync_auto_tags(e, &auto_tags(&["invoice"]), &[]).unwrap();
+        for file in [c, d, e] {
+            assert_eq!(tag_names(&db, file), vec!["archive", "finance", "invoice"]);
+        }
+
+        // 自动标签同步时不会把推导标签当作过期的自动标签
+        db.sync_auto_tags(e, &auto_tags(&["invoice"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, e), vec!["archive", "finance", "invoice"]);
+
+        // 移除来源标签时推导标签随之移除，直接添加的标签保留
+        db.remove_tag_from_file(a, tag_id(&db, "invoice")).unwrap();
+        db.sync_auto_tags(e, &[], &[]).unwrap();
+        assert!(tag_names(&db, a).is_empty());
+        assert!(tag_names(&db, e).is_empty());
+        assert_eq!(tag_names(&db, b), vec!["archive", "finance", "receipt"]);
+        assert_eq!(use_count(&db, "finance"), 3);
+        assert!(search_by_keyword(&db, "archive").len() == 3);
+
+        // 删除规则后仅由该规则推导出的标签移除
+        db.remove_tag_implication(tag_id(&db, "finance"), tag_id(&db, "archive")).unwrap();
+        assert_eq!(tag_names(&db, c), vec!["finance", "invoice"]);
+        assert_eq!(tag_names(&db, b), vec!["finance", "receipt"]);
+    }
+
+    #[test]
+    fn test_implication_cycles_are_rejected() {
+        let db = create_test_db();
+        let file = insert_test_file(&db, "/test/a.raw");
+        for name in ["raw-photo", "photo", "media"] {
+            db.add_tag_to_file_by_name(file, name, false).unwrap();
+        }
+
+        db.add_tag_implication(tag_id(&db, "raw-photo"), tag_id(&db, "photo")).unwrap();
+        db.add_tag_implication(tag_id(&db, "photo"), tag_id(&db, "media")).unwrap();
+
+        let err = db.add_tag_implication(tag_id(&db, "media"), tag_id(&db, "raw-photo")).unwrap_err();
+        assert!(err.to_string().contains("media → raw-photo → photo → media"), "{}", err);
+        assert!(db.add_tag_implication(tag_id(&db, "photo"), tag_id(&db, "photo")).is_err());
+        assert_eq!(db.get_tag_implications(None).unwrap().len(), 2);
+    }
+
+    fn search_by_attributes(db: &Database, terms: &[&str]) -> Vec<String> {
+        let response = db.search_files(&SearchQuery::parse(&terms.join(" "), 50, 0).unwrap()).unwrap();
+        assert_eq!(response.total as usize, response.results.len());
+        let mut paths: Vec<String> = response.results.into_iter().map(|r| r.file.path).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_parse_attribute_filter() {
+        let filter = AttributeFilter::parse("rating>=4").unwrap();
+        assert_eq!((filter.key.as_str(), filter.op, filter.value.as_str()), ("rating", CompareOp::Ge, "4"));
+        assert_eq!(AttributeFilter::parse("status=review").unwrap().op, CompareOp::Eq);
+        assert_eq!(AttributeFilter::parse("status!=done").unwrap().op, CompareOp::Ne);
+        assert_eq!(AttributeFilter::parse("due<2024-06-30").unwrap().op, CompareOp::Lt);
+
+        assert!(AttributeFilter::parse("report").is_none());
+        assert!(AttributeFilter::parse("=4").is_none());
+        assert!(AttributeFilter::parse("rating>=").is_none());
+        assert!(AttributeFilter::parse("my rating=4").is_none());
+    }
+
+    #[test]
+    fn test_key_value_tags() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.jpg");
+        let b = insert_test_file(&db, "/test/b.jpg");
+        let c = insert_test_file(&db, "/test/c.jpg");
+
+        db.set_tag_value(a, "rating", Some(&TagValue::Number(5.0))).unwrap();
+        db.set_tag_value(b, "rating", Some(&TagValue::Number(4.0))).unwrap();
+        db.set_tag_value(c, "rating", Some(&TagValue::Number(10.0))).unwrap();
+        db.set_tag_value(c, "rating", Some(&TagValue::Number(2.0))).unwrap();
+        db.set_tag_value(a, "status", Some(&TagValue::String("review".to_string()))).unwrap();
+        db.set_tag_value(b, "status", Some(&TagValue::String("done".to_string()))).unwrap();
+        let due = chrono::NaiveDate::from_ymd_opt(2024, 6, 30).unwrap();
+        db.set_tag_value(b, "due", Some(&TagValue::Date(due))).unwrap();
+
+        // 值随文件的标签返回，重复设置只更新值
+        let rating = db.get_tags_by_file(c).unwrap().into_iter().find(|t| t.name == "rating").unwrap();
+        assert_eq!(rating.value, Some(TagValue::Number(2.0)));
+        assert_eq!(use_count(&db, "rating"), 3);
+
+        // 数字按数值比较（10 > 4），字符串不区分大小写，日期按日期比较
+        assert_eq!(search_by_attributes(&db, &["rating>=4"]), vec!["/test/a.jpg", "/test/b.jpg"]);
+        assert_eq!(search_by_attributes(&db, &["rating<3"]), vec!["/test/c.jpg"]);
+        assert_eq!(search_by_attributes(&db, &["status=Review"]), vec!["/test/a.jpg"]);
+        assert_eq!(search_by_attributes(&db, &["rating>=4", "status!=review"]), vec!["/test/b.jpg"]);
+        assert_eq!(search_by_attributes(&db, &["due<=2024-12-31"]), vec!["/test/b.jpg"]);
+        assert!(search_by_attributes(&db, &["status>=4"]).is_empty());
+
+        let values: Vec<(TagValue, i64)> = db.get_tag_values("rating").unwrap()
+            .into_iter()
+            .map(|v| (v.value, v.count))
+            .collect();
+        assert_eq!(values.len(), 3);
+        assert!(values.contains(&(TagValue::Number(5.0), 1)));
+
+        // 清除值后标签仍保留
+        db.set_tag_value(a, "status", None).unwrap();
+        assert!(tag_names(&db, a).contains(&"status".to_string()));
+        assert!(search_by_attributes(&db, &["status=review"]).is_empty());
+        assert_eq!(db.get_tag_values("status").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_attribute_filter_non_numeric_words() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.jpg");
+        let b = insert_test_file(&db, "/test/b.jpg");
+        db.set_tag_value(a, "status", Some(&TagValue::String("NaN".to_string()))).unwrap();
+        db.set_tag_value(b, "status", Some(&TagValue::String("Infinity".to_string()))).unwrap();
+        db.set_tag_value(b, "rating", Some(&TagValue::Number(1e10))).unwrap();
+
+        // nan、inf 等按字符串比较，而不是被解析为浮点数
+        assert_eq!(search_by_attributes(&db, &["status=nan"]), vec!["/test/a.jpg"]);
+        assert_eq!(search_by_attributes(&db, &["status=infinity"]), vec!["/test/b.jpg"]);
+        assert!(search_by_attributes(&db, &["rating<inf"]).is_empty());
+        assert!(search_by_attributes(&db, &["rating<1e999"]).is_empty());
+        assert_eq!(search_by_attributes(&db, &["rating>-1.5E+3"]), vec!["/test/b.jpg"]);
+    }
+
+    #[test]
+    fn test_exclusive_tag_groups() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+
+        db.add_tag_to_file_by_name(a, "status:in-progress", false).unwrap();
+        let status = db.create_tag_group("status", true).unwrap();
+        let client = db.create_tag_group("Client", false).unwrap();
+
+        // 手动添加：同组的旧标签被替换，新建的 status:* 标签自动归入分组
+        db.add_tag_to_file_by_name(a, "status:done", false).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["status:done"]);
+        assert_eq!(use_count(&db, "status:in-progress"), 0);
+
+        // 非互斥分组不受影响
+        db.add_tag_to_file_by_name(a, "client:acme", false).unwrap();
+        db.add_tag_to_file_by_name(a, "client:globex", false).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["client:acme", "client:globex", "status:done"]);
+
+        // 批量添加同样执行互斥；自动标签不替换同组的手动标签
+        db.batch_add_tags(&[a, b], &["status:in-progress".to_string()]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["client:acme", "client:globex", "status:in-progress"]);
+        db.sync_auto_tags(b, &auto_tags(&["status:done"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, b), vec!["status:in-progress"]);
+
+        // 手动分配到互斥分组的标签
+        db.add_tag_to_file_by_name(b, "review", false).unwrap();
+        db.set_tag_group(tag_id(&db, "review"), Some(status)).unwrap();
+        db.add_tag_to_file_by_name(b, "status:in-progress", false).unwrap();
+        assert_eq!(tag_names(&db, b), vec!["status:in-progress"]);
+
+        let grouped = db.get_grouped_tags().unwrap();
+        let names: Vec<Option<&str>> = grouped.iter().map(|g| g.group.as_ref().map(|g| g.name.as_str())).collect();
+        assert_eq!(names, vec![Some("Client"), Some("status")]);
+        let status_tags: Vec<&str> = grouped[1].tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(status_tags.len(), 3);
+        assert!(status_tags.contains(&"review"));
+
+        assert!(db.create_tag_group("STATUS", false).is_err());
+        db.delete_tag_group(client).unwrap();
+        let grouped = db.get_grouped_tags().unwrap();
+        assert!(grouped.last().unwrap().group.is_none());
+        assert_eq!(grouped.last().unwrap().tags.len(), 2);
+    }
+
+    #[test]
+    fn test_manual_tag_wins_over_auto_tag_in_exclusive_group() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        db.create_tag_group("status", true).unwrap();
+        db.add_tag_to_file_by_name(a, "status:done", false).unwrap();
+
+        // 规则产生同组的 status:new，每次重新扫描都不应删除手动的 status:done
+        for _ in 0..2 {
+            db.sync_auto_tags(a, &auto_tags(&["status:new"]), &[]).unwrap();
+            assert_eq!(tag_names(&db, a), vec!["status:done"]);
+            assert_eq!(use_count(&db, "status:done"), 1);
+        }
+
+        // 移除手动标签后，自动标签照常添加，且自动标签之间仍然互斥
+        db.remove_tag_from_file(a, tag_id(&db, "status:done")).unwrap();
+        db.sync_auto_tags(a, &auto_tags(&["status:new"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["status:new"]);
+        db.sync_auto_tags(a, &auto_tags(&["status:new", "status:stale"]), &[]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["status:stale"]);
+    }
+
+    #[test]
+    fn test_directory_tags_are_inherited() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/projects/acme/a.txt");
+        let b = insert_test_file(&db, "/projects/acme/docs/b.txt");
+        let other = insert_test_file(&db, "/projects/acme-old/c.txt");
+
+        db.add_tag_to_file_by_name(b, "client:acme", false).unwrap();
+        db.add_directory_tag("/projects/acme/", "client:acme").unwrap();
+        db.add_directory_tag("/projects/acme/docs", "docs").unwrap();
+
+        assert_eq!(tag_names(&db, a), vec!["client:acme"]);
+        assert_eq!(tag_names(&db, b), vec!["client:acme", "docs"]);
+        assert!(tag_names(&db, other).is_empty());
+        assert_eq!(use_count(&db, "client:acme"), 2);
+
+        // 继承的标签标明来源目录，直接添加的标签不受影响
+        let tags = db.get_tags_by_file(b).unwrap();
+        let inherited = |name: &str| tags.iter().find(|t| t.name == name).unwrap().inherited_from.clone();
+        assert_eq!(inherited("client:acme"), None);
+        assert_eq!(inherited("docs").as_deref(), Some("/projects/acme/docs"));
+        assert!(db.remove_tag_from_file(a, tag_id(&db, "client:acme")).is_err());
+
+        // 之后索引的文件同样继承，继承标签可作为推导来源并可被搜索
+        db.add_tag_to_file_by_name(a, "finance", false).unwrap();
+        db.remove_tag_from_file(a, tag_id(&db, "finance")).unwrap();
+        db.add_tag_implication(tag_id(&db, "client:acme"), tag_id(&db, "finance")).unwrap();
+        let d = insert_test_file(&db, "/projects/acme/d.txt");
+        assert_eq!(tag_names(&db, d), vec!["client:acme", "finance"]);
+        assert_eq!(search_by_keyword(&db, "docs").len(), 1);
+
+        // 文件移出目录后不再继承
+        let mut moved = db.get_file_by_id(d).unwrap().unwrap();
+        moved.path = "/archive/d.txt".to_string();
+        db.update_file(&moved).unwrap();
+        assert!(tag_names(&db, d).is_empty());
+
+        // 移除目录标签时只移除继承的标签
+        db.remove_directory_tag("/projects/acme", tag_id(&db, "client:acme")).unwrap();
+        assert!(tag_names(&db, a).is_empty());
+        assert_eq!(tag_names(&db, b), vec!["client:acme", "docs", "finance"]);
+        assert_eq!(db.get_directory_tags(None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_direct_tags_take_precedence_in_exclusive_groups() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/projects/acme/a.txt");
+        let b = insert_test_file(&db, "/projects/acme/b.txt");
+
+        db.create_tag_group("status", true).unwrap();
+        db.add_tag_to_file_by_name(a, "status:done", false).unwrap();
+        db.add_directory_tag("/projects/acme", "status:draft").unwrap();
+        assert_eq!(tag_names(&db, a), vec!["status:done"]);
+        assert_eq!(tag_names(&db, b), vec!["status:draft"]);
+
+        // 反复重新计算不会在直接标签与继承标签之间来回替换
+        for _ in 0..3 {
+            let file = db.get_file_by_id(a).unwrap().unwrap();
+            db.update_file(&file).unwrap();
+            db.add_directory_tag("/projects/acme", "docs").unwrap();
+            assert_eq!(tag_names(&db, a), vec!["docs", "status:done"]);
+        }
+
+        // 推导标签同样让位于直接标签
+        let other = insert_test_file(&db, "/archive/c.txt");
+        db.add_tag_to_file_by_name(other, "status:review", false).unwrap();
+        db.remove_tag_from_file(other, tag_id(&db, "status:review")).unwrap();
+        db.add_tag_implication(tag_id(&db, "docs"), tag_id(&db, "status:review")).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["docs", "status:done"]);
+        assert_eq!(tag_names(&db, b), vec!["docs", "status:draft", "status:review"]);
+        assert_eq!(use_count(&db, "status:done"), 1);
+        assert_eq!(use_count(&db, "status:draft"), 1);
+
+        // 移除直接标签后恢复继承和推导的标签，再次添加时它们让位
+        db.remove_tag_from_file(a, tag_id(&db, "status:done")).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["docs", "status:draft", "status:review"]);
+        db.add_tag_to_file_by_name(a, "status:done", false).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["docs", "status:done"]);
+        assert_eq!(use_count(&db, "status:draft"), 1);
+    }
+
+    #[test]
+    fn test_batch_remove_and_set_tags() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+
+        db.add_tag_to_file_by_name(a, "draft", false).unwrap();
+        db.add_tag_to_file_by_name(a, "work", false).unwrap();
+        db.add_tag_to_file_by_name(b, "work", false).unwrap();
+        db.add_tag_to_file_by_name(b, "final", false).unwrap();
+        db.sync_auto_tags(a, &auto_tags(&["文本"]), &[]).unwrap();
+
+        db.batch_remove_tags(&[a, b], &["work".to_string(), "missing".to_string()]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["draft", "文本"]);
+        assert_eq!(tag_names(&db, b), vec!["final"]);
+        assert_eq!(use_count(&db, "work"), 0);
+
+        // 替换手动标签，自动标签保留
+        db.batch_set_tags(&[a, b], &["final".to_string(), "work".to_string()]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["final", "work", "文本"]);
+        assert_eq!(tag_names(&db, b), vec!["final", "work"]);
+        assert_eq!(use_count(&db, "draft"), 0);
+        assert_eq!(use_count(&db, "final"), 2);
+        assert_eq!(use_count(&db, "work"), 2);
+
+        db.batch_set_tags(&[a], &[]).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["文本"]);
+        assert_eq!(use_count(&db, "final"), 1);
+    }
+
+    #[test]
+    fn test_retag_by_query() {
+        let db = create_test_db();
+        let mut reports = Vec::new();
+        for i in 0..30 {
+            let id = insert_test_file(&db, &format!("/test/report{}.txt", i));
+            db.add_tag_to_file_by_name(id, "inbox", false).unwrap();
+            reports.push(id);
+        }
+        let other = insert_test_file(&db, "/test/notes.txt");
+        db.add_tag_to_file_by_name(other, "inbox", false).unwrap();
+
+        db.add_tag_to_file_by_name(other, "archived", false).unwrap();
+
+        // 分页参数被忽略，整个结果集都被处理
+        let query = SearchQuery::parse("inbox tag:inbox", 10, 0).unwrap();
+
+        let affected = db.retag_by_query(&query, &["archived".to_string()], &["inbox".to_string()]).unwrap();
+        assert_eq!(affected, 31);
+        assert_eq!(use_count(&db, "inbox"), 0);
+        assert_eq!(use_count(&db, "archived"), 31);
+        assert_eq!(tag_names(&db, reports[29]), vec!["archived"]);
+        assert_eq!(tag_names(&db, other), vec!["archived"]);
+    }
+
+    #[test]
+    fn test_parse_search_query() {
+        let term = |t: QueryTerm| QueryExpr::Term(t);
+        let keyword = |k: &str| term(QueryTerm::Keyword(k.to_string()));
+
+        let query = SearchQuery::parse(
+            r#"tag:invoice type:image size:>10MB modified:2024-01..2024-06 ext:PDF -tag:draft "exact phrase" (a OR b) path:~/work"#,
+            20,
+            40,
+        ).unwrap();
+        assert_eq!((query.limit, query.offset), (20, 40));
+        assert_eq!(query.expr, Some(QueryExpr::And(vec![
+            term(QueryTerm::Tag("invoice".to_string())),
+            term(QueryTerm::Type(FileType::Image)),
+            term(QueryTerm::Size { op: CompareOp::Gt, bytes: 10 * 1024 * 1024 }),
+            term(QueryTerm::Modified {
+                from: NaiveDate::from_ymd_opt(2024, 1, 1),
+                until: NaiveDate::from_ymd_opt(2024, 7, 1),
+            }),
+            term(QueryTerm::Extension("pdf".to_string())),
+            QueryExpr::Not(Box::new(term(QueryTerm::Tag("draft".to_string())))),
+            term(QueryTerm::Phrase("exact phrase".to_string())),
+            QueryExpr::Or(vec![keyword("a"), keyword("b")]),
+            term(QueryTerm::Path("~/work".to_string())),
+        ])));
+
+        // AND 优先于 OR，显式 AND 与空格等价
+        assert_eq!(
+            SearchQuery::parse("a AND b OR c", 10, 0).unwrap().expr,
+            Some(QueryExpr::Or(vec![QueryExpr::And(vec![keyword("a"), keyword("b")]), keyword("c")]))
+        );
+        assert_eq!(
+            SearchQuery::parse(r#"tag:"my tag" type:image,视频 size:1.5k..2MB"#, 10, 0).unwrap().expr,
+            Some(QueryExpr::And(vec![
+                term(QueryTerm::Tag("my tag".to_string())),
+                QueryExpr::Or(vec![term(QueryTerm::Type(FileType::Image)), term(QueryTerm::Type(FileType::Video))]),
+                QueryExpr::And(vec![
+                    term(QueryTerm::Size { op: CompareOp::Ge, bytes: 1536 }),
+                    term(QueryTerm::Size { op: CompareOp::Le, bytes: 2 * 1024 * 1024 }),
+                ]),
+            ]))
+        );
+        assert_eq!(
+            SearchQuery::parse("modified:>2024-02 rating>=4 note:x", 10, 0).unwrap().expr,
+            Some(QueryExpr::And(vec![
+                term(QueryTerm::Modified { from: NaiveDate::from_ymd_opt(2024, 3, 1), until: None }),
+                term(QueryTerm::Attribute(AttributeFilter::parse("rating>=4").unwrap())),
+                keyword("note:x"),
+            ]))
+        );
+        assert_eq!(SearchQuery::parse("   ", 10, 0).unwrap().expr, None);
+
+        // 引号内的 "" 表示一个引号字符
+        assert_eq!(
+            SearchQuery::parse(r#""say ""hi""" tag:"12"" vinyl" OR "(a OR b)""#, 10, 0).unwrap().expr,
+            Some(QueryExpr::Or(vec![
+                QueryExpr::And(vec![
+                    term(QueryTerm::Phrase(r#"say "hi""#.to_string())),
+                    term(QueryTerm::Tag(r#"12" vinyl"#.to_string())),
+                ]),
+                term(QueryTerm::Phrase("(a OR b)".to_string())),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_parse_search_query_errors() {
+        let cases: &[(&str, usize, usize)] = &[
+            ("a )", 2, 3),
+            ("(a b", 0, 1),
+            ("a OR", 2, 4),
+            ("OR a", 0, 2),
+            ("a AND OR b", 2, 5),
+            ("say \"hello", 4, 10),
+            ("say \"hello\"\"", 4, 12),
+            ("a -", 2, 3),
+            ("a ()", 2, 4),
+            ("size:>10XB", 5, 10),
+            ("modified:2024-13", 9, 16),
+            ("type:song", 5, 9),
+            ("ext:pdf tag:", 8, 12),
+        ];
+        for &(input, start, end) in cases {
+            let err = SearchQuery::parse(input, 10, 0).unwrap_err();
+            assert_eq!((err.start, err.end), (start, end), "{}: {}", input, err);
+        }
+
+        // 位置按字符而非字节计算
+        let err: QueryParseError = SearchQuery::parse("报告 )", 10, 0).unwrap_err();
+        assert_eq!((err.start, err.end), (3, 4));
+        assert_eq!(err.to_string(), "搜索语句第 4 个字符处多余的右括号");
+        let err = SearchQuery::parse("😀 )", 10, 0).unwrap_err();
+        assert_eq!((err.start, err.end), (2, 3));
+    }
+
+    #[test]
+    fn test_query_nesting_depth_limit() {
+        // 过深的括号或 `-` 嵌套返回解析错误，而不是耗尽栈空间
+        for prefix in ["(", "-"] {
+            let input = format!("{}a", prefix.repeat(2000));
+            let err = SearchQuery::parse(&input, 10, 0).unwrap_err();
+            assert_eq!((err.start, err.end), (64, 65), "{}", prefix);
+            assert!(err.message.contains("嵌套层数超过 64 层"));
+        }
+
+        let nested = format!("{}a{}", "(-".repeat(32), ")".repeat(32));
+        assert!(SearchQuery::parse(&nested, 10, 0).is_ok());
+        let too_deep = format!("-{}", nested);
+        assert!(SearchQuery::parse(&too_deep, 10, 0).is_err());
+    }
+
+    #[test]
+    fn test_search_query_language() {
+        let db = create_test_db();
+        let insert = |path: &str, file_type: FileType, size: i64, modified: (i32, u32, u32), tags: &[&str]| {
+            let name = path.rsplit('/').next().unwrap();
+            let modified_at = Local
+                .with_ymd_and_hms(modified.0, modified.1, modified.2, 12, 0, 0)
+                .unwrap()
+                .with_timezone(&Utc);
+            let id = db.create_file(&File {
+                id: None,
+                path: path.to_string(),
+                name: name.to_string(),
+                extension: name.rsplit('.').next().unwrap().to_string(),
+                size,
+                file_type,
+                created_at: Utc::now(),
+                modified_at,
+                accessed_at: Utc::now(),
+                status: FileStatus::Active,
+                indexed_at: Utc::now(),
+                metadata: None,
+                tags: None,
+            }).unwrap();
+            for tag in tags {
+                db.add_tag_to_file_by_name(id, tag, false).unwrap();
+            }
+        };
+        const MB: i64 = 1024 * 1024;
+        insert("/home/u/work/invoice-2024.pdf", FileType::Other, 20 * MB, (2024, 3, 15), &["invoice"]);
+        insert("/home/u/work/invoice-draft.pdf", FileType::Other, MB, (2024, 6, 30), &["invoice", "draft"]);
+        insert("/home/u/photos/beach.jpg", FileType::Image, 12 * MB, (2024, 7, 1), &["holiday"]);
+        insert("/home/u/notes/quarterly report.txt", FileType::Text, 2048, (2023, 12, 31), &[]);
+        insert("/home/u/notes/年度报告.txt", FileType::Text, 4096, (2024, 1, 1), &[]);
+
+        let search = |input: &str| {
+            let response = db.search_files(&SearchQuery::parse(input, 50, 0).unwrap()).unwrap();
+            assert_eq!(response.total as usize, response.results.len(), "{}", input);
+            let mut names: Vec<String> = response.results.into_iter().map(|r| r.file.name).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(search("tag:invoice -tag:draft"), vec!["invoice-2024.pdf"]);
+        assert_eq!(search("ext:pdf size:>10MB"), vec!["invoice-2024.pdf"]);
+        assert_eq!(search("type:image OR size:<=2KB"), vec!["beach.jpg", "quarterly report.txt"]);
+        assert_eq!(search("modified:2024-01..2024-06 ext:pdf"), vec!["invoice-2024.pdf", "invoice-draft.pdf"]);
+        assert_eq!(search("modified:2024"), vec!["beach.jpg", "invoice-2024.pdf", "invoice-draft.pdf", "年度报告.txt"]);
+        assert_eq!(search(r#""quarterly report""#), vec!["quarterly report.txt"]);
+        assert!(search(r#""report quarterly""#).is_empty());
+        assert_eq!(search("(beach OR quarterly) -holiday"), vec!["quarterly report.txt"]);
+        assert_eq!(search("报告 path:/home/u/notes"), vec!["年度报告.txt"]);
+        assert_eq!(search("path:/home/u/photos"), vec!["beach.jpg"]);
+        assert!(search("path:/home/u/photo").is_empty());
+        assert_eq!(search("path:work").len(), 2);
+        assert_eq!(search("").len(), 5);
+
+        // 排除的关键字不影响排序，包含的关键字按 bm25 排序
+        let response = db.search_files(&SearchQuery::parse("(invoice OR holiday) -draft", 50, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 2);
+        assert!(response.results.iter().all(|r| r.relevance < 0.0));
+    }
+
+    #[test]
+    fn test_cjk_tokenizer() {
+        let texts = |tokens: Vec<Token>| tokens.into_iter().map(|t| (t.text, t.colocated)).collect::<Vec<_>>();
+        let owned = |items: &[(&str, bool)]| items.iter().map(|(t, c)| (t.to_string(), *c)).collect::<Vec<_>>();
+
+        assert_eq!(
+            texts(tokenize("年度报告_Q1.pdf", false)),
+            owned(&[
+                ("年度", false), ("年", true),
+                ("度报", false), ("度", true),
+                ("报告", false), ("报", true), ("告", true),
+                ("q1", false), ("pdf", false),
+            ])
+        );
+        assert_eq!(texts(tokenize("年度报告", true)), owned(&[("年度", false), ("度报", false), ("报告", false)]));
+        assert_eq!(texts(tokenize("报", true)), owned(&[("报", false)]));
+
+        // 偏移量为字节位置，可用于截取原文
+        let text = "会议 notes";
+        let tokens = tokenize(text, true);
+        assert_eq!(&text[tokens[0].start..tokens[0].end], "会议");
+        assert_eq!(&text[tokens[1].start..tokens[1].end], "notes");
+    }
+
+    #[test]
+    fn test_cjk_full_text_search() {
+        let db = create_test_db();
+        let report = insert_test_file(&db, "/docs/2024年度报告.docx");
+        insert_test_file(&db, "/docs/报名表.xlsx");
+        insert_test_file(&db, "/docs/告示.txt");
+        let minutes = insert_test_file(&db, "/docs/meeting.txt");
+        db.add_tag_to_file_by_name(minutes, "会议纪要", false).unwrap();
+        db.add_tag_to_file_by_name(report, "财务", false).unwrap();
+
+        assert_eq!(search_by_keyword(&db, "年度报告"), vec!["/docs/2024年度报告.docx"]);
+        assert_eq!(search_by_keyword(&db, "报告"), vec!["/docs/2024年度报告.docx"]);
+        assert_eq!(search_by_keyword(&db, "报").len(), 2);
+        assert_eq!(search_by_keyword(&db, "告").len(), 2);
+        assert_eq!(search_by_keyword(&db, "2024年"), vec!["/docs/2024年度报告.docx"]);
+        assert!(search_by_keyword(&db, "报表").is_empty());
+        assert!(search_by_keyword(&db, "年报").is_empty());
+
+        // 标签名同样可以按中文词检索，中英文混合查询走全文索引并按相关度排序
+        assert_eq!(search_by_keyword(&db, "纪要"), vec!["/docs/meeting.txt"]);
+        let response = db.search_files(&SearchQuery::parse("财务 docx", 10, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 1);
+        assert!(response.results[0].relevance < 0.0);
+        let response = db.search_files(&SearchQuery::parse("会议 OR 报名", 10, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 2);
+        assert!(response.results.iter().all(|r| r.relevance < 0.0));
+    }
+
+    /// 用于模糊测试的文件，名称中包含各种引号和通配符
+    fn create_search_fuzz_db() -> Database {
+        let db = create_test_db();
+        for path in ["/fuzz/it's \"quoted\".txt", "/fuzz/100%_done\\.md", "/fuzz/年度报告 (final).pdf"] {
+            let id = insert_test_file(&db, path);
+            db.add_tag_to_file_by_name(id, "o'reilly", false).unwrap();
+        }
+        db
+    }
+
+    /// 对同一段文本以各种方式构造查询，都不应产生 SQL 错误
+    fn assert_search_ok(db: &Database, text: &str) {
+        let term = |t: QueryTerm| QueryExpr::Term(t);
+        let exprs = vec![
+            term(QueryTerm::Keyword(text.to_string())),
+            term(QueryTerm::Phrase(text.to_string())),
+            QueryExpr::Not(Box::new(term(QueryTerm::Keyword(text.to_string())))),
+            QueryExpr::Or(vec![term(QueryTerm::Tag(text.to_string())), term(QueryTerm::Path(text.to_string()))]),
+            QueryExpr::And(vec![
+                term(QueryTerm::Extension(text.to_string())),
+                term(QueryTerm::Attribute(AttributeFilter { key: text.to_string(), op: CompareOp::Ne, value: text.to_string() })),
+            ]),
+        ];
+        let parsed = SearchQuery::parse(text, 10, 0).ok().and_then(|q| q.expr);
+        for expr in exprs.into_iter().chain(parsed) {
+            for fuzzy in [None, Some(FuzzyOptions::default())] {
+                let query = SearchQuery { expr: Some(expr.clone()), limit: 10, offset: 0, fuzzy };
+                if let Err(e) = db.search_files(&query) {
+                    panic!("{:?} 搜索失败: {}", query, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_with_special_characters() {
+        let db = create_search_fuzz_db();
+        for text in [
+            "", " ", "'", "''", "\"", "\"\"", "%", "_", "\\", "*", "^", "-", ":", "(", ")", "NEAR(a b)", "a*",
+            "it's", "o'reilly", "100%", "\0", "'; DROP TABLE files; --", "\" OR 1=1 --", "。", "🙂", "\u{202e}",
+        ] {
+            assert_search_ok(&db, text);
+        }
+
+        assert_eq!(search_by_keyword(&db, "it's").len(), 1);
+        assert_eq!(search_by_keyword(&db, "o'reilly").len(), 3);
+        let response = db.search_files(&SearchQuery::parse("path:100%_", 10, 0).unwrap()).unwrap();
+        assert_eq!(response.total, 1);
+        assert_eq!(db.get_file_count().unwrap(), 3);
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_search_accepts_arbitrary_text(texts in prop::collection::vec(any::<String>(), 1..4)) {
+            let db = create_search_fuzz_db();
+            for text in &texts {
+                assert_search_ok(&db, text);
+            }
+            assert_search_ok(&db, &texts.join(" "));
+        }
+    }
+
+    #[test]
+    fn test_approximate_match() {
+        let highlight = |start, end, distance| Some(MatchHighlight { start, end, distance });
+
+        assert_eq!(approximate_match("receive", "Receive_Form.pdf", 0), highlight(0, 7, 0));
+        assert_eq!(approximate_match("recieve", "receive_form.pdf", 2), highlight(0, 7, 2));
+        assert_eq!(approximate_match("recieve", "receive_form.pdf", 1), None);
+        assert_eq!(approximate_match("IMG_203", "IMG_2031.jpg", 0), highlight(0, 7, 0));
+        assert_eq!(approximate_match("2031", "IMG_2O31.jpg", 1), highlight(4, 8, 1));
+        assert_eq!(approximate_match("报告", "2024年度报告.docx", 0), highlight(6, 8, 0));
+        assert_eq!(approximate_match("", "anything", 3), None);
+    }
+
+    #[test]
+    fn test_fuzzy_filename_search() {
+        let db = create_test_db();
+        let receive = insert_test_file(&db, "/docs/receive_form.pdf");
+        insert_test_file(&db, "/photos/IMG_2031.jpg");
+        insert_test_file(&db, "/photos/IMG_9999.jpg");
+        insert_test_file(&db, "/docs/recital.txt");
+
+        let search = |input: &str, fuzzy: Option<FuzzyOptions>| {
+            let mut query = SearchQuery::parse(input, 10, 0).unwrap();
+            query.fuzzy = fuzzy;
+            let response = db.search_files(&query).unwrap();
+            assert_eq!(response.total as usize, response.results.len(), "{}", input);
+            response.results.into_iter().map(|r| (r.file.name, r.highlights)).collect::<Vec<_>>()
+        };
+        let names = |results: Vec<(String, Vec<MatchHighlight>)>| results.into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        let fuzzy = Some(FuzzyOptions::default());
+
+        // 精确搜索同样返回匹配区间
+        assert_eq!(
+            search("receive", None),
+            vec![("receive_form.pdf".to_string(), vec![MatchHighlight { start: 0, end: 7, distance: 0 }])]
+        );
+
+        assert!(search("recieve", None).is_empty());
+        assert_eq!(
+            search("recieve", fuzzy),
+            vec![("receive_form.pdf".to_string(), vec![MatchHighlight { start: 0, end: 7, distance: 2 }])]
+        );
+        assert!(search("recieve", Some(FuzzyOptions { max_distance: 1 })).is_empty());
+
+        assert!(search("IMG_203", None).is_empty());
+        assert_eq!(names(search("IMG_203", fuzzy)), vec!["IMG_2031.jpg"]);
+
+        // 精确匹配排在模糊匹配之前，排除条件不做模糊匹配
+        assert_eq!(names(search("recital OR recieve", fuzzy)), vec!["recital.txt", "receive_form.pdf"]);
+        assert_eq!(search("(receive OR recital) -recieve", fuzzy).len(), 2);
+
+        // 三元组索引随文件改名和删除同步
+        let mut file = db.get_file_by_id(receive).unwrap().unwrap();
+        file.name = "invoice.pdf".to_string();
+        db.update_file(&file).unwrap();
+        assert!(search("recieve", fuzzy).is_empty());
+        assert_eq!(names(search("invoise", fuzzy)), vec!["invoice.pdf"]);
+        db.delete_file(receive).unwrap();
+        assert!(search("invoise", fuzzy).is_empty());
+    }
+
+    #[test]
+    fn test_use_count_only_counts_active_files() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        let b = insert_test_file(&db, "/test/b.txt");
+        db.add_tag_to_file_by_name(a, "work", false).unwrap();
+        db.add_tag_to_file_by_name(b, "work", false).unwrap();
+        assert_eq!(use_count(&db, "work"), 2);
+
+        db.update_file_status(b, FileStatus::Deleted).unwrap();
+        assert_eq!(use_count(&db, "work"), 1);
+
+        // 非活跃文件上的增删不影响计数
+        db.remove_tag_from_file(b, tag_id(&db, "work")).unwrap();
+        db.add_tag_to_file_by_name(b, "work", false).unwrap();
+        assert_eq!(use_count(&db, "work"), 1);
+
+        db.update_file_status(b, FileStatus::Active).unwrap();
+        assert_eq!(use_count(&db, "work"), 2);
+
+        // 重复移除不会重复扣减
+        db.remove_tag_from_file(a, tag_id(&db, "work")).unwrap();
+        db.remove_tag_from_file(a, tag_id(&db, "work")).unwrap();
+        assert_eq!(use_count(&db, "work"), 1);
+
+        db.delete_file(b).unwrap();
+        assert_eq!(use_count(&db, "work"), 0);
+    }
+
+    #[test]
+    fn test_recalculate_tag_counts_fixes_drift() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        db.add_tag_to_file_by_name(a, "work", false).unwrap();
+        db.conn.lock().execute("UPDATE tags SET use_count = 42", []).unwrap();
+
+        db.recalculate_tag_counts().unwrap();
+        assert_eq!(use_count(&db, "work"), 1);
+    }
+
+    #[test]
+    fn test_suggest_tags() {
+        let db = create_test_db();
+        for i in 0..4 {
+            let id = insert_test_file(&db, &format!("/other/{}.txt", i));
+            db.add_tag_to_file_by_name(id, "invoice", false).unwrap();
+            if i < 3 {
+                db.add_tag_to_file_by_name(id, "finance", false).unwrap();
+            }
+        }
+        let sibling = insert_test_file(&db, "/work/acme/notes.txt");
+        db.add_tag_to_file_by_name(sibling, "client:acme", false).unwrap();
+        let nested = insert_test_file(&db, "/work/acme/old/readme.txt");
+        db.add_tag_to_file_by_name(nested, "archived", false).unwrap();
+        let similar = insert_test_file(&db, "/scans/Quarterly_Report_2023.txt");
+        db.add_tag_to_file_by_name(similar, "report", false).unwrap();
+        db.sync_auto_tags(similar, &auto_tags(&["扫描件"]), &[]).unwrap();
+
+        let file = insert_test_file(&db, "/work/acme/quarterly-report-2024.txt");
+        db.add_tag_to_file_by_name(file, "invoice", false).unwrap();
+
+        let suggestions = db.suggest_tags(file, 10).unwrap();
+        let names: Vec<&str> = suggestions.iter().map(|s| s.tag.name.as_str()).collect();
+        assert_eq!(names, vec!["finance", "client:acme", "report"]);
+
+        // 共现比例 3/4，同目录 1/1，相似文件名 1/1
+        assert!((suggestions[0].score - 0.5 * 0.75).abs() < 1e-9);
+        assert!((suggestions[1].score - 0.3).abs() < 1e-9);
+        assert!((suggestions[2].score - 0.2).abs() < 1e-9);
+        match &suggestions[0].reasons[..] {
+            [SuggestionReason::CoOccurrence { tag_name, shared_files, .. }] => {
+                assert_eq!(tag_name, "invoice");
+                assert_eq!(*shared_files, 3);
+            }
+            other => panic!("unexpected reasons: {:?}", other),
+        }
+        match &suggestions[2].reasons[..] {
+            [SuggestionReason::SimilarNames { matched, total, examples }] => {
+                assert_eq!((*matched, *total), (1, 1));
+                assert_eq!(examples, &vec!["Quarterly_Report_2023.txt".to_string()]);
+            }
+            other => panic!("unexpected reasons: {:?}", other),
+        }
+
+        assert_eq!(db.suggest_tags(file, 1).unwrap().len(), 1);
+        assert!(db.suggest_tags(9999, 10).is_err());
+    }
+
+    #[test]
+    fn test_system_tags_are_protected() {
+        let db = create_test_db();
+        let a = insert_test_file(&db, "/test/a.txt");
+        db.sync_auto_tags(a, &auto_tags(&["图片", "client:acme"]), &[]).unwrap();
+        db.add_tag_to_file_by_name(a, "vacation", false).unwrap();
+        db.add_tag_to_file_by_name(a, "图片/2024", false).unwrap();
+
+        // 手动添加时创建自定义标签，保留名称的上级标签仍为系统标签
+        let tag_type = |name: &str| db.get_tag_by_name(name).unwrap().unwrap().tag_type;
+        assert_eq!(tag_type("vacation"), TagType::Custom);
+        assert_eq!(tag_type("client:acme"), TagType::System);
+        assert_eq!(tag_type("图片"), TagType::System);
+
+        let system = tag_id(&db, "client:acme");
+        assert!(matches!(db.delete_tag(system, true), Err(AppError::SystemTagProtected(_))));
+        assert!(matches!(db.rename_tag(system, "client:globex", false), Err(AppError::SystemTagProtected(_))));
+        assert!(matches!(db.move_tag(system, Some(tag_id(&db, "vacation"))), Err(AppError::SystemTagProtected(_))));
+        assert!(matches!(db.merge_tags(&[system], tag_id(&db, "vacation")), Err(AppError::SystemTagProtected(_))));
+        // 删除自定义标签的子树时不能连带删除系统标签
+        db.move_tag(tag_id(&db, "vacation"), Some(tag_id(&db, "图片"))).unwrap();
+        assert!(matches!(db.delete_tag(tag_id(&db, "图片"), false), Err(AppError::SystemTagProtected(_))));
+        assert_eq!(tag_names(&db, a), vec!["client:acme", "图片", "图片/2024", "图片/vacation"]);
+
+        // 自定义标签不能使用保留名称或已有系统标签的名称，合并到系统标签不受限制
+        let custom = |name: &str| Tag {
+            id: None,
+            name: name.to_string(),
+            display_name: name.to_string(),
+            tag_type: TagType::Custom,
+            color: "#FF0000".to_string(),
+            icon: None,
+            use_count: 0,
+            created_at: Utc::now(),
+            parent_id: None,
+            value: None,
+            inherited_from: None,
+            implied_by: None,
+        };
+        assert!(matches!(db.create_tag(&custom("视频")), Err(AppError::ReservedTagName(_))));
+        assert!(matches!(db.create_tag(&custom("client:acme")), Err(AppError::ReservedTagName(_))));
+        let pics = db.create_tag(&custom("pics")).unwrap();
+        assert!(matches!(db.rename_tag(pics, "视频", false), Err(AppError::ReservedTagName(_))));
+        db.merge_tags(&[pics], system).unwrap();
+
+        // 配置允许后可以修改系统标签
+        db.set_setting("allow_system_tag_changes", "true").unwrap();
+        db.rename_tag(system, "client:globex", false).unwrap();
+        db.delete_tag(tag_id(&db, "client:globex"), true).unwrap();
+        assert_eq!(tag_names(&db, a), vec!["图片", "图片/2024", "图片/vacation"]);
+    }
+
+    const PROP_TAGS: [&str; 5] = ["work", "draft", "finance", "client:acme", "docs"];
+    const PROP_FILES: [&str; 4] = ["/p/acme/a.txt", "/p/acme/docs/b.txt", "/p/other/c.txt", "/p/d.txt"];
+
+    /// 属性测试中对数据库执行的操作，下标分别指向 PROP_FILES 与 PROP_TAGS
+    #[derive(Debug, Clone)]
+    enum CountOp {
+        Add(usize, usize, bool),
+        Remove(usize, usize),
+        BatchAdd(Vec<usize>, Vec<usize>),
+        BatchRemove(Vec<usize>, Vec<usize>),
+        BatchSet(Vec<usize>, Vec<usize>),
+        SyncAuto(usize, Vec<usize>),
+        SetStatus(usize, bool),
+        Move(usize, usize),
+        Delete(usize),
+        Reindex(usize),
+        Merge(usize, usize),
+        DeleteTag(usize),
+        Imply(usize, usize),
+        Unimply(usize, usize),
+        DirectoryTag(usize, bool),
+    }
+
+    fn count_op() -> impl Strategy<Value = CountOp> {
+        let file = 0..PROP_FILES.len();
+        let tag = 0..PROP_TAGS.len();
+        let files = prop::collection::vec(0..PROP_FILES.len(), 0..3);
+        let tags = prop::collection::vec(0..PROP_TAGS.len(), 0..3);
+        prop_oneof![
+            3 => (file.clone(), tag.clone(), any::<bool>()).prop_map(|(f, t, auto)| CountOp::Add(f, t, auto)),
+            2 => (file.clone(), tag.clone()).prop_map(|(f, t)| CountOp::Remove(f, t)),
+            1 => (files.clone(), tags.clone()).prop_map(|(f, t)| CountOp::BatchAdd(f, t)),
+            1 => (files.clone(), tags.clone()).prop_map(|(f, t)| CountOp::BatchRemove(f, t)),
+            1 => (files, tags.clone()).prop_map(|(f, t)| CountOp::BatchSet(f, t)),
+            1 => (file.clone(), tags).prop_map(|(f, t)| CountOp::SyncAuto(f, t)),
+            1 => (file.clone(), any::<bool>()).prop_map(|(f, active)| CountOp::SetStatus(f, active)),
+            1 => (file.clone(), file.clone()).prop_map(|(f, to)| CountOp::Move(f, to)),
+            1 => file.clone().prop_map(CountOp::Delete),
+            1 => file.prop_map(CountOp::Reindex),
+            1 => (tag.clone(), tag.clone()).prop_map(|(s, t)| CountOp::Merge(s, t)),
+            1 => tag.clone().prop_map(CountOp::DeleteTag),
+            1 => (tag.clone(), tag.clone()).prop_map(|(s, t)| CountOp::Imply(s, t)),
+            1 => (tag.clone(), tag.clone()).prop_map(|(s, t)| CountOp::Unimply(s, t)),
+            1 => (tag, any::<bool>()).prop_map(|(t, add)| CountOp::DirectoryTag(t, add)),
+        ]
+    }
+
+    /// 执行一次操作；操作对象不存在或规则冲突导致的错误属于预期，直接忽略
+    fn apply_count_op(db: &Database, op: &CountOp) {
+        let file_id = |i: usize| db.get_file_by_path(PROP_FILES[i]).unwrap().and_then(|f| f.id);
+        let tag_id = |i: usize| db.get_tag_by_name(PROP_TAGS[i]).unwrap().and_then(|t| t.id);
+        let file_ids = |ids: &[usize]| ids.iter().filter_map(|&i| file_id(i)).collect::<Vec<_>>();
+        let tag_names = |ids: &[usize]| ids.iter().map(|&i| PROP_TAGS[i].to_string()).collect::<Vec<_>>();
+
+        match op {
+            CountOp::Add(f, t, auto) => {
+                if let Some(f) = file_id(*f) {
+                    let _ = db.add_tag_to_file_by_name(f, PROP_TAGS[*t], *auto);
+                }
+            }
+            CountOp::Remove(f, t) => {
+                if let (Some(f), Some(t)) = (file_id(*f), tag_id(*t)) {
+                    let _ = db.remove_tag_from_file(f, t);
+                }
+            }
+            CountOp::BatchAdd(f, t) => {
+                let _ = db.batch_add_tags(&file_ids(f), &tag_names(t));
+            }
+            CountOp::BatchRemove(f, t) => {
+                let _ = db.batch_remove_tags(&file_ids(f), &tag_names(t));
+            }
+            CountOp::BatchSet(f, t) => {
+                let _ = db.batch_set_tags(&file_ids(f), &tag_names(t));
+            }
+            CountOp::SyncAuto(f, t) => {
+                if let Some(f) = file_id(*f) {
+                    let names: Vec<&str> = t.iter().map(|&i| PROP_TAGS[i]).collect();
+                    let _ = db.sync_auto_tags(f, &auto_tags(&names), &[]);
+                }
+            }
+            CountOp::SetStatus(f, active) => {
+                if let Some(f) = file_id(*f) {
+                    let status = if *active { FileStatus::Active } else { FileStatus::Deleted };
+                    db.update_file_status(f, status).unwrap();
+                }
+            }
+            CountOp::Move(f, to) => {
+                if let Some(mut file) = db.get_file_by_path(PROP_FILES[*f]).unwrap() {
+                    if db.get_file_by_path(PROP_FILES[*to]).unwrap().is_none() {
+                        file.path = PROP_FILES[*to].to_string();
+                        db.update_file(&file).unwrap();
+                    }
+                }
+            }
+            CountOp::Delete(f) => {
+                if let Some(f) = file_id(*f) {
+                    db.delete_file(f).unwrap();
+                }
+            }
+            CountOp::Reindex(f) => {
+                if file_id(*f).is_none() {
+                    insert_test_file(db, PROP_FILES[*f]);
+                }
+            }
+            CountOp::Merge(s, t) => {
+                if let (Some(s), Some(t)) = (tag_id(*s), tag_id(*t)) {
+                    let _ = db.merge_tags(&[s], t);
+                }
+            }
+            CountOp::DeleteTag(t) => {
+                if let Some(t) = tag_id(*t) {
+                    let _ = db.delete_tag(t, false);
+                }
+            }
+            CountOp::Imply(s, t) => {
+                if let (Some(s), Some(t)) = (tag_id(*s), tag_id(*t)) {
+                    let _ = db.add_tag_implication(s, t);
+                }
+            }
+            CountOp::Unimply(s, t) => {
+                if let (Some(s), Some(t)) = (tag_id(*s), tag_id(*t)) {
+                    let _ = db.remove_tag_implication(s, t);
+                }
+            }
+            CountOp::DirectoryTag(t, add) => {
+                if *add {
+                    let _ = db.add_directory_tag("/p/acme", PROP_TAGS[*t]);
+                } else if let Some(t) = tag_id(*t) {
+                    let _ = db.remove_directory_tag("/p/acme", t);
+                }
+            }
+        }
+    }
+
+    /// use_count 与活跃文件上实际关联数不一致的标签：(标签名, use_count, 实际关联数)
+    fn count_mismatches(db: &Database) -> Vec<(String, i64, i64)> {
+        let conn = db.conn.lock();
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.name, t.use_count, (
+                     SELECT COUNT(*) FROM file_tags ft JOIN files f ON ft.file_id = f.id
+                     WHERE ft.tag_id = t.id AND f.status = 'active'
+                 ) AS actual
+                 FROM tags t WHERE t.use_count != actual",
+            )
+            .unwrap();
+        let mismatches = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .unwrap();
+        mismatches
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_use_count_matches_active_associations(ops in prop::collection::vec(count_op(), 1..40)) {
+            let db = create_test_db();
+            for path in PROP_FILES {
+                insert_test_file(&db, path);
+            }
+
+            for op in &ops {
+                apply_count_op(&db, op);
+                let mismatches = count_mismatches(&db);
+                prop_assert!(mismatches.is_empty(), "{:?} 之后计数不一致: {:?}", op, mismatches);
+            }
+        }
+    }
 }
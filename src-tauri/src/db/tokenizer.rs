@@ -0,0 +1,214 @@
+use crate::error::Result;
+use rusqlite::ffi;
+use rusqlite::Connection;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+/// 分词器名称，建表时使用 `tokenize="cjk"`
+pub(super) const TOKENIZER_NAME: &str = "cjk";
+
+/// 分词结果，位置为 UTF-8 字节偏移
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Token {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+    /// 与上一个词位于同一位置
+    pub colocated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Cjk,
+    Word,
+    Separator,
+}
+
+fn char_class(c: char) -> CharClass {
+    let code = c as u32;
+    let cjk = (0x4e00..=0x9fff).contains(&code)     // 基本汉字
+        || (0x3400..=0x4dbf).contains(&code)        // 扩展汉字A
+        || (0x20000..=0x2ebef).contains(&code)      // 扩展汉字B-F
+        || (0xf900..=0xfaff).contains(&code)        // 兼容汉字
+        || (0x3040..=0x30ff).contains(&code)        // 平假名、片假名
+        || (0xac00..=0xd7af).contains(&code);       // 韩文音节
+
+    if cjk {
+        CharClass::Cjk
+    } else if c.is_alphanumeric() {
+        CharClass::Word
+    } else {
+        CharClass::Separator
+    }
+}
+
+/// 切分文本
+///
+/// - 字母、数字连续的部分作为一个词，转为小写；
+/// - 中日韩文字按相邻两字切分（bigram），建立索引时每个字另外以单字词放在同一位置，使单字查询也能命中；
+///   `query` 为 true 时只输出 bigram，`"年度报告"` 因此成为 `年度 度报 报告` 三个连续词组成的短语；
+/// - 其余字符（空白、标点、下划线等）作为分隔符。
+pub(super) fn tokenize(text: &str, query: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut run: Vec<(usize, char)> = Vec::new();
+    let mut run_class = CharClass::Separator;
+
+    // 末尾追加一个分隔符，保证最后一段被输出
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        let class = char_class(c);
+        if class != run_class {
+            push_run(text, &run, run_class, query, &mut tokens);
+            run.clear();
+            run_class = class;
+        }
+        if class != CharClass::Separator {
+            run.push((i, c));
+        }
+    }
+
+    tokens
+}
+
+/// 输出一段同类字符
+fn push_run(text: &str, run: &[(usize, char)], class: CharClass, query: bool, tokens: &mut Vec<Token>) {
+    let Some(&(first, _)) = run.first() else { return };
+    let end_of = |k: usize| run[k].0 + run[k].1.len_utf8();
+    let mut push = |start: usize, end: usize, colocated: bool| {
+        tokens.push(Token { text: text[start..end].to_lowercase(), start, end, colocated });
+    };
+
+    match class {
+        CharClass::Word => push(first, end_of(run.len() - 1), false),
+        CharClass::Cjk if run.len() == 1 => push(first, end_of(0), false),
+        CharClass::Cjk => {
+            for (k, pair) in run.windows(2).enumerate() {
+                push(pair[0].0, end_of(k + 1), false);
+                if !query {
+                    push(pair[0].0, end_of(k), true);
+                }
+            }
+            // 最后一个字与最后一个 bigram 同位置，不占用单独的位置
+            if !query {
+                let last = run.len() - 1;
+                push(run[last].0, end_of(last), true);
+            }
+        }
+        CharClass::Separator => {}
+    }
+}
+
+/// 在连接上注册 `cjk` 分词器，必须在访问 FTS 表之前调用
+///
+/// `unicode61` 会把连续的汉字当作一个词，无法按词检索中文，FTS 表因此改用此分词器。
+pub(super) fn register_cjk_tokenizer(conn: &Connection) -> Result<()> {
+    unsafe {
+        let api = fts5_api(conn)?;
+        let create = (*api).xCreateTokenizer.ok_or_else(|| failure(ffi::SQLITE_ERROR, "FTS5 不支持注册分词器"))?;
+
+        // FTS5 会复制这个结构体，局部变量即可
+        let mut tokenizer = ffi::fts5_tokenizer {
+            xCreate: Some(x_create),
+            xDelete: Some(x_delete),
+            xTokenize: Some(x_tokenize),
+        };
+        let name = std::ffi::CString::new(TOKENIZER_NAME).expect("分词器名称不含 NUL");
+        let rc = create(api, name.as_ptr(), ptr::null_mut(), &mut tokenizer, None);
+        if rc != ffi::SQLITE_OK {
+            return Err(failure(rc, "注册 FTS5 分词器失败").into());
+        }
+    }
+
+    Ok(())
+}
+
+fn failure(code: c_int, message: &str) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(ffi::Error::new(code), Some(message.to_string()))
+}
+
+/// 通过 `SELECT fts5(?1)` 取得连接的 fts5_api 指针
+unsafe fn fts5_api(conn: &Connection) -> Result<*mut ffi::fts5_api> {
+    let db = conn.handle();
+    let mut stmt = ptr::null_mut();
+    let rc = ffi::sqlite3_prepare_v2(db, c"SELECT fts5(?1)".as_ptr(), -1, &mut stmt, ptr::null_mut());
+    if rc != ffi::SQLITE_OK {
+        return Err(failure(rc, "当前 SQLite 未启用 FTS5").into());
+    }
+
+    let mut api: *mut ffi::fts5_api = ptr::null_mut();
+    ffi::sqlite3_bind_pointer(
+        stmt,
+        1,
+        (&mut api as *mut *mut ffi::fts5_api).cast::<c_void>(),
+        c"fts5_api_ptr".as_ptr(),
+        None,
+    );
+    ffi::sqlite3_step(stmt);
+    ffi::sqlite3_finalize(stmt);
+
+    if api.is_null() {
+        return Err(failure(ffi::SQLITE_ERROR, "无法获取 FTS5 接口").into());
+    }
+    Ok(api)
+}
+
+/// 分词器实例不需要状态
+struct CjkTokenizer;
+
+unsafe extern "C" fn x_create(
+    _user_data: *mut c_void,
+    _args: *mut *const c_char,
+    _arg_count: c_int,
+    out: *mut *mut ffi::Fts5Tokenizer,
+) -> c_int {
+    *out = Box::into_raw(Box::new(CjkTokenizer)).cast::<ffi::Fts5Tokenizer>();
+    ffi::SQLITE_OK
+}
+
+unsafe extern "C" fn x_delete(tokenizer: *mut ffi::Fts5Tokenizer) {
+    if !tokenizer.is_null() {
+        drop(Box::from_raw(tokenizer.cast::<CjkTokenizer>()));
+    }
+}
+
+type TokenCallback = unsafe extern "C" fn(*mut c_void, c_int, *const c_char, c_int, c_int, c_int) -> c_int;
+
+unsafe extern "C" fn x_tokenize(
+    _tokenizer: *mut ffi::Fts5Tokenizer,
+    ctx: *mut c_void,
+    flags: c_int,
+    text: *const c_char,
+    text_len: c_int,
+    callback: Option<TokenCallback>,
+) -> c_int {
+    let Some(callback) = callback else { return ffi::SQLITE_ERROR };
+    let bytes = if text.is_null() || text_len <= 0 {
+        &[][..]
+    } else {
+        std::slice::from_raw_parts(text.cast::<u8>(), text_len as usize)
+    };
+    // 非法的 UTF-8 只处理合法的前缀部分
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap_or_default(),
+    };
+
+    let query = flags & ffi::FTS5_TOKENIZE_QUERY != 0;
+    let Ok(tokens) = std::panic::catch_unwind(|| tokenize(text, query)) else {
+        return ffi::SQLITE_ERROR;
+    };
+
+    for token in tokens {
+        let rc = callback(
+            ctx,
+            if token.colocated { ffi::FTS5_TOKEN_COLOCATED } else { 0 },
+            token.text.as_ptr().cast::<c_char>(),
+            token.text.len() as c_int,
+            token.start as c_int,
+            token.end as c_int,
+        );
+        if rc != ffi::SQLITE_OK {
+            return rc;
+        }
+    }
+    ffi::SQLITE_OK
+}
@@ -13,7 +13,12 @@ pub enum AppError {
     #[allow(dead_code)]
     PermissionDenied(String),
     ConfigNotFound(String),
-    #[allow(dead_code)]
+    InvalidRule(String),
+    UnknownProvider(String),
+    TagConflict(String),
+    SystemTagProtected(String),
+    ReservedTagName(String),
+    InvalidTag(String),
     Unknown(String),
 }
 
@@ -28,6 +33,12 @@ impl std::fmt::Display for AppError {
             AppError::AlreadyWatched(s) => write!(f, "目录已监控: {}", s),
             AppError::PermissionDenied(s) => write!(f, "权限不足: {}", s),
             AppError::ConfigNotFound(s) => write!(f, "未找到配置: {}", s),
+            AppError::InvalidRule(s) => write!(f, "无效的标签规则: {}", s),
+            AppError::UnknownProvider(s) => write!(f, "未知的标签提供者: {}", s),
+            AppError::TagConflict(s) => write!(f, "标签已存在: {}", s),
+            AppError::SystemTagProtected(s) => write!(f, "系统标签不能删除或修改名称: {}", s),
+            AppError::ReservedTagName(s) => write!(f, "标签名为系统保留名称: {}", s),
+            AppError::InvalidTag(s) => write!(f, "无效的标签操作: {}", s),
             AppError::Unknown(s) => write!(f, "未知错误: {}", s),
         }
     }
@@ -1,56 +1,154 @@
-use super::rules::{TagRule, default_rules};
-use crate::db::File;
+use super::rules::{CompiledRule, TagRule, default_rules, deepest_root};
+use super::store::load_rules;
+use crate::db::{AutoTag, Database, File};
+use crate::error::Result;
+use serde::Serialize;
+
+/// 规则在某个文件上执行失败的记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleError {
+    pub rule_name: String,
+    pub path: String,
+    pub message: String,
+}
+
+/// 为文件生成的自动标签，以及执行失败的规则
+#[derive(Debug, Clone, Default)]
+pub struct GeneratedTags {
+    pub tags: Vec<AutoTag>,
+    /// 执行失败的规则名称，同步时保留这些规则以前产生的标签
+    pub failed_rules: Vec<String>,
+}
 
 /// 自动标签生成器
 pub struct AutoTagger {
-    rules: Vec<TagRule>,
+    rules: Vec<CompiledRule>,
+    /// 监控根目录，路径模板规则相对于这些目录匹配
+    roots: Vec<String>,
 }
 
 impl AutoTagger {
     /// 创建新的自动标签生成器（使用默认规则）
     pub fn new() -> Self {
-        AutoTagger {
-            rules: default_rules(),
-        }
+        Self::with_rules(default_rules()).expect("默认规则必须能够编译")
     }
 
-    /// 使用自定义规则创建（预留功能）
-    #[allow(dead_code)]
-    pub fn with_rules(rules: Vec<TagRule>) -> Self {
-        AutoTagger { rules }
+    /// 使用自定义规则创建，规则中的正则与通配符在此处统一编译
+    pub fn with_rules(rules: Vec<TagRule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(CompiledRule::compile)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(AutoTagger { rules, roots: Vec::new() })
     }
 
-    /// 为文件生成标签
-    pub fn generate_tags(&self, file: &File) -> Vec<String> {
-        self.rules
-            .iter()
-            .filter(|rule| rule.matches(file))
-            .map(|rule| rule.name.clone())
-            .collect()
+    /// 使用已保存的规则创建，并以所有监控目录作为根目录
+    pub fn load(db: &Database) -> Result<Self> {
+        let roots = db.get_watched_directories()?.into_iter().map(|d| d.path).collect();
+        Ok(Self::with_rules(load_rules(db)?)?.with_roots(roots))
+    }
+
+    /// 设置监控根目录
+    pub fn with_roots(mut self, roots: Vec<String>) -> Self {
+        self.roots = roots;
+        self
     }
 
-    /// 获取所有规则（预留功能）
+    /// 为文件生成标签（已去重，保持规则顺序）（预留功能）
     #[allow(dead_code)]
-    pub fn rules(&self) -> &[TagRule] {
-        &self.rules
+    pub fn generate_tags(&self, file: &File) -> Vec<String> {
+        self.generate(file).tags.into_iter().map(|t| t.name).collect()
+    }
+
+    /// 为文件生成标签并附带来源规则，同名标签以最先匹配的规则为准
+    ///
+    /// 执行失败的规则（如脚本超时）会被跳过并输出到日志，其名称记录在 `failed_rules` 中。
+    pub fn generate(&self, file: &File) -> GeneratedTags {
+        let (tags, errors) = self.evaluate(file);
+        let mut failed_rules = Vec::with_capacity(errors.len());
+        for error in errors {
+            eprintln!("[Tagger] {}", error.message);
+            failed_rules.push(error.rule_name);
+        }
+        GeneratedTags { tags, failed_rules }
+    }
+
+    /// 为文件生成标签，同时返回各规则的执行错误
+    pub fn evaluate(&self, file: &File) -> (Vec<AutoTag>, Vec<RuleError>) {
+        let root = self.root_of(&file.path);
+        let mut tags: Vec<AutoTag> = Vec::new();
+        let mut errors = Vec::new();
+
+        for compiled in &self.rules {
+            let names = match compiled.apply(file, root) {
+                Ok(names) => names,
+                Err(e) => {
+                    errors.push(RuleError {
+                        rule_name: compiled.rule.name.clone(),
+                        path: file.path.clone(),
+                        message: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+
+            for name in names {
+                if tags.iter().any(|t| t.name == name) {
+                    continue;
+                }
+                tags.push(AutoTag {
+                    name,
+                    rule_name: compiled.rule.name.clone(),
+                    rule_detail: compiled.rule.condition.describe(),
+                });
+            }
+        }
+
+        (tags, errors)
+    }
+
+    /// 仅包含时间相关规则（如今日文件、本周文件）的生成器，用于定期重新评估
+    pub fn time_relative(&self) -> AutoTagger {
+        AutoTagger {
+            rules: self.rules
+                .iter()
+                .filter(|r| r.rule.condition.is_time_relative())
+                .cloned()
+                .collect(),
+            roots: self.roots.clone(),
+        }
+    }
+
+    /// 查找包含该路径的最深监控根目录
+    fn root_of(&self, path: &str) -> Option<&str> {
+        deepest_root(self.roots.iter().map(|r| r.as_str()), path)
+    }
+
+    /// 获取所有规则
+    pub fn rules(&self) -> Vec<&TagRule> {
+        self.rules.iter().map(|r| &r.rule).collect()
     }
 
     /// 添加规则（预留功能）
     #[allow(dead_code)]
-    pub fn add_rule(&mut self, rule: TagRule) {
-        self.rules.push(rule);
+    pub fn add_rule(&mut self, rule: TagRule) -> Result<()> {
+        self.rules.push(CompiledRule::compile(rule)?);
+        Ok(())
     }
 
     /// 移除规则（按名称）（预留功能）
     #[allow(dead_code)]
     pub fn remove_rule(&mut self, name: &str) {
-        self.rules.retain(|r| r.name != name);
+        self.rules.retain(|r| r.rule.name != name);
     }
 
     /// 重置为默认规则（预留功能）
     #[allow(dead_code)]
     pub fn reset_to_default(&mut self) {
-        self.rules = default_rules();
+        let roots = std::mem::take(&mut self.roots);
+        *self = Self::new().with_roots(roots);
     }
 }
 
@@ -64,7 +162,8 @@ impl Default for AutoTagger {
 mod tests {
     use super::*;
     use crate::db::{FileType, FileStatus};
-    use chrono::{Utc, Duration};
+    use crate::tagger::rules::TagCondition;
+    use chrono::Utc;
 
     fn create_test_file(size: i64, file_type: FileType) -> File {
         File {
@@ -80,6 +179,7 @@ mod tests {
             status: FileStatus::Active,
             indexed_at: Utc::now(),
             metadata: None,
+            tags: None,
         }
     }
 
@@ -92,6 +192,17 @@ mod tests {
         assert!(tags.contains(&"图片".to_string()));
     }
 
+    #[test]
+    fn test_default_rule_names_are_reserved() {
+        let rule_names: Vec<String> = default_rules().into_iter().map(|rule| rule.name).collect();
+        for name in &rule_names {
+            assert!(crate::db::is_reserved_tag_name(name), "{} 未列入保留名称", name);
+        }
+        for reserved in crate::db::RESERVED_TAG_NAMES {
+            assert!(rule_names.iter().any(|name| name == reserved), "{} 不是内置规则的标签名", reserved);
+        }
+    }
+
     #[test]
     fn test_small_file_tag() {
         let tagger = AutoTagger::new();
@@ -111,6 +222,71 @@ mod tests {
         assert!(tags.contains(&"视频".to_string()));
     }
 
+    #[test]
+    fn test_generated_tags_are_unique() {
+        let tagger = AutoTagger::with_rules(vec![
+            TagRule {
+                name: "照片".to_string(),
+                condition: TagCondition::FileType(vec![FileType::Image]),
+            },
+            TagRule {
+                name: "{1}".to_string(),
+                condition: TagCondition::PathRegex("/(照片)/".to_string()),
+            },
+        ])
+        .unwrap();
+        let mut file = create_test_file(1024 * 1024, FileType::Image);
+        file.path = "/home/me/照片/file.jpg".to_string();
+        let tags = tagger.generate_tags(&file);
+
+        // "照片" 同时由文件类型规则和路径正则规则产生
+        assert_eq!(tags, vec!["照片".to_string()]);
+    }
+
+    #[test]
+    fn test_generate_records_rule() {
+        let tagger = AutoTagger::new();
+        let file = create_test_file(200 * 1024 * 1024, FileType::Video);
+        let tags = tagger.generate(&file).tags;
+
+        let large = tags.iter().find(|t| t.name == "大文件").unwrap();
+        assert_eq!(large.rule_name, "大文件");
+        assert_eq!(large.rule_detail, "大小 ≥ 100MB");
+    }
+
+    #[test]
+    fn test_script_rule_errors_are_reported_per_rule() {
+        let tagger = AutoTagger::with_rules(vec![
+            TagRule {
+                name: "坏脚本".to_string(),
+                condition: TagCondition::Script("file.missing.field".to_string()),
+            },
+            TagRule {
+                name: "大图".to_string(),
+                condition: TagCondition::Script("tag(\"相册\"); file.size > 1000".to_string()),
+            },
+        ])
+        .unwrap();
+
+        let (tags, errors) = tagger.evaluate(&create_test_file(5000, FileType::Image));
+        let names: Vec<&str> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["相册", "大图"]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].rule_name, "坏脚本");
+
+        let generated = tagger.generate(&create_test_file(5000, FileType::Image));
+        assert_eq!(generated.failed_rules, vec!["坏脚本"]);
+    }
+
+    #[test]
+    fn test_invalid_script_rejected() {
+        let result = AutoTagger::with_rules(vec![TagRule {
+            name: "坏".to_string(),
+            condition: TagCondition::Script("if {".to_string()),
+        }]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_today_file_tag() {
         let tagger = AutoTagger::new();
@@ -120,4 +296,95 @@ mod tests {
         let tags = tagger.generate_tags(&file);
         assert!(tags.contains(&"今日文件".to_string()));
     }
+
+    #[test]
+    fn test_time_relative_subset() {
+        let tagger = AutoTagger::new().time_relative();
+        let file = create_test_file(200 * 1024 * 1024, FileType::Video);
+        let tags = tagger.generate_tags(&file);
+
+        assert!(tags.contains(&"今日文件".to_string()));
+        assert!(!tags.contains(&"大文件".to_string()));
+        assert!(!tags.contains(&"视频".to_string()));
+    }
+
+    #[test]
+    fn test_name_regex_capture_in_tag_name() {
+        let tagger = AutoTagger::with_rules(vec![TagRule {
+            name: "client:{client}".to_string(),
+            condition: TagCondition::NameRegex(r"^(?P<client>ACME|Globex)_.*".to_string()),
+        }])
+        .unwrap();
+
+        let mut file = create_test_file(1024, FileType::Text);
+        file.name = "Globex_invoice_2024.pdf".to_string();
+        assert_eq!(tagger.generate_tags(&file), vec!["client:Globex".to_string()]);
+
+        file.name = "Initech_invoice.pdf".to_string();
+        assert!(tagger.generate_tags(&file).is_empty());
+    }
+
+    #[test]
+    fn test_path_regex_numbered_capture() {
+        let tagger = AutoTagger::with_rules(vec![TagRule {
+            name: "year:{1}".to_string(),
+            condition: TagCondition::PathRegex(r"/(\d{4})/".to_string()),
+        }])
+        .unwrap();
+
+        let mut file = create_test_file(1024, FileType::Text);
+        file.path = "/projects/acme/2024/report.txt".to_string();
+        assert_eq!(tagger.generate_tags(&file), vec!["year:2024".to_string()]);
+    }
+
+    #[test]
+    fn test_path_glob() {
+        let tagger = AutoTagger::with_rules(vec![TagRule {
+            name: "发票".to_string(),
+            condition: TagCondition::PathGlob("**/invoices/*.pdf".to_string()),
+        }])
+        .unwrap();
+
+        let mut file = create_test_file(1024, FileType::Other);
+        file.path = r"C:\Users\me\Invoices\march.PDF".to_string();
+        assert_eq!(tagger.generate_tags(&file), vec!["发票".to_string()]);
+
+        file.path = "/home/me/invoices/2024/march.pdf".to_string();
+        assert!(tagger.generate_tags(&file).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_regex_is_rejected() {
+        let result = AutoTagger::with_rules(vec![TagRule {
+            name: "broken".to_string(),
+            condition: TagCondition::NameRegex("(unclosed".to_string()),
+        }]);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_path_template_derives_tags_below_root() {
+        let tagger = AutoTagger::with_rules(vec![TagRule {
+            name: "项目结构".to_string(),
+            condition: TagCondition::PathTemplate("{client}/{year}/*".to_string()),
+        }])
+        .unwrap()
+        .with_roots(vec!["/work".to_string(), "/work/projects".to_string()]);
+
+        let mut file = create_test_file(1024, FileType::Text);
+        file.path = "/work/projects/acme/2024/design/spec.md".to_string();
+        assert_eq!(
+            tagger.generate_tags(&file),
+            vec!["client:acme".to_string(), "year:2024".to_string()]
+        );
+
+        // 层级不足
+        file.path = "/work/projects/acme/2024/spec.md".to_string();
+        assert!(tagger.generate_tags(&file).is_empty());
+
+        // 不在监控根目录下
+        file.path = "/elsewhere/acme/2024/design/spec.md".to_string();
+        assert!(tagger.generate_tags(&file).is_empty());
+    }
 }
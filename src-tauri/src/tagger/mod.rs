@@ -1,8 +1,18 @@
 mod rules;
 mod auto;
+mod schedule;
+mod store;
+mod preview;
+mod provider;
+mod script;
 
 // AutoTagger 是实际使用的
 pub use auto::AutoTagger;
+pub use rules::TagRule;
+pub use schedule::spawn_date_tag_scheduler;
+pub use preview::{apply_rule, preview_rule, RulePreview};
+pub use store::{export_rules, import_rules, ImportMode, ImportSummary};
+pub use provider::{set_directory_providers, ProviderInfo, ProviderRegistry};
 
 use crate::db::{Database, File};
 use crate::error::Result;
@@ -24,19 +34,16 @@ impl TagGenerator {
         }
     }
 
-    /// 为文件生成并添加自动标签
+    /// 为文件生成自动标签，并移除已不再匹配的自动标签
     pub fn process_file(&self, file: &File) -> Result<usize> {
-        let tags = self.tagger.generate_tags(file);
-        let mut added_count = 0;
+        let generated = self.tagger.generate(file);
 
         if let Some(file_id) = file.id {
-            for tag_name in tags {
-                self.db.add_tag_to_file_by_name(file_id, &tag_name, true)?;
-                added_count += 1;
-            }
+            self.db.sync_auto_tags(file_id, &generated.tags, &generated.failed_rules)?;
+            return Ok(generated.tags.len());
         }
 
-        Ok(added_count)
+        Ok(0)
     }
 
     /// 批量处理文件
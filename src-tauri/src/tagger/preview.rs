@@ -0,0 +1,249 @@
+use super::auto::RuleError;
+use super::provider::{ProviderSettings, RULES_PROVIDER_ID};
+use super::rules::TagRule;
+use super::store::{load_rules, rules_to_setting, TAG_RULES_KEY};
+use super::AutoTagger;
+use crate::db::{AutoTag, Database, File};
+use crate::error::{AppError, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// 预览结果中示例文件的数量上限
+const SAMPLE_SIZE: usize = 20;
+
+/// 规则预览结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RulePreview {
+    /// 匹配规则的文件数
+    pub match_count: usize,
+    /// 匹配文件示例
+    pub sample: Vec<File>,
+    /// 各标签将新增、移除的文件数
+    pub tag_changes: Vec<TagChange>,
+    /// 规则执行失败的文件数（如脚本出错或超时）
+    pub error_count: usize,
+    /// 执行失败示例
+    pub errors: Vec<RuleError>,
+}
+
+/// 单个标签的变化
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TagChange {
+    pub tag_name: String,
+    /// 将新增该标签的文件数
+    pub added: usize,
+    /// 将移除该标签的文件数（同名规则以前产生、现在不再产生）
+    pub removed: usize,
+}
+
+/// 规则对索引的评估结果
+struct Evaluation {
+    preview: RulePreview,
+    /// 每个匹配文件应有的自动标签
+    assignments: Vec<(i64, Vec<AutoTag>)>,
+}
+
+/// 预览规则对已索引文件的影响，不写入任何数据
+pub fn preview_rule(db: &Database, rule: &TagRule) -> Result<RulePreview> {
+    Ok(evaluate(db, rule)?.preview)
+}
+
+/// 应用规则：在同一事务中更新已索引文件的自动标签，并启用该规则
+///
+/// 同名的已有规则会被替换，其产生、但新规则不再产生的自动标签随之移除。
+/// 规则在任一文件上执行失败时不做任何修改。
+pub fn apply_rule(db: &Database, rule: TagRule) -> Result<RulePreview> {
+    let evaluation = evaluate(db, &rule)?;
+    if let Some(error) = evaluation.preview.errors.first() {
+        return Err(AppError::InvalidRule(format!(
+            "规则在 {} 个文件上执行失败，例如: {}",
+            evaluation.preview.error_count, error.message
+        )));
+    }
+
+    let mut rules = load_rules(db)?;
+    rules.retain(|r| r.name != rule.name);
+    rules.push(rule.clone());
+    let setting = rules_to_setting(&rules)?;
+
+    db.apply_rule_tags(&rule.name, &evaluation.assignments, (TAG_RULES_KEY, &setting))?;
+
+    Ok(evaluation.preview)
+}
+
+/// 对所有活跃文件评估规则
+fn evaluate(db: &Database, rule: &TagRule) -> Result<Evaluation> {
+    let settings = ProviderSettings::load(db)?;
+    let tagger = AutoTagger::with_rules(vec![rule.clone()])?.with_roots(settings.roots());
+
+    // 同名规则以前产生的自动标签
+    let mut previous: HashMap<i64, Vec<String>> = HashMap::new();
+    for (file_id, tag_name) in db.get_auto_tags_by_rule(&rule.name)? {
+        previous.entry(file_id).or_default().push(tag_name);
+    }
+
+    let mut match_count = 0;
+    let mut sample = Vec::new();
+    let mut changes: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut assignments = Vec::new();
+    let mut error_count = 0;
+    let mut errors = Vec::new();
+
+    for file in db.get_files(None, None)? {
+        let file_id = match file.id {
+            Some(id) => id,
+            None => continue,
+        };
+        // 规则引擎在该目录被停用时，规则不产生任何标签
+        if !settings.is_enabled(RULES_PROVIDER_ID, &file.path) {
+            previous.remove(&file_id);
+            continue;
+        }
+
+        let (tags, failures) = tagger.evaluate(&file);
+        if !failures.is_empty() {
+            error_count += 1;
+            if errors.len() < SAMPLE_SIZE {
+                errors.extend(failures);
+            }
+            continue;
+        }
+
+        for old in previous.remove(&file_id).unwrap_or_default() {
+            if !tags.iter().any(|t| t.name == old) {
+                changes.entry(old).or_default().1 += 1;
+            }
+        }
+
+        if tags.is_empty() {
+            continue;
+        }
+        match_count += 1;
+
+        for tag in &tags {
+            let already_tagged = file.tags.iter().flatten().any(|t| t.name == tag.name);
+            if !already_tagged {
+                changes.entry(tag.name.clone()).or_default().0 += 1;
+            }
+        }
+
+        if sample.len() < SAMPLE_SIZE {
+            sample.push(file);
+        }
+        assignments.push((file_id, tags));
+    }
+
+    let tag_changes = changes
+        .into_iter()
+        .map(|(tag_name, (added, removed))| TagChange { tag_name, added, removed })
+        .collect();
+
+    Ok(Evaluation {
+        preview: RulePreview {
+            match_count,
+            sample,
+            tag_changes,
+            error_count,
+            errors,
+        },
+        assignments,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{FileStatus, FileType};
+    use crate::tagger::rules::TagCondition;
+    use chrono::Utc;
+
+    fn insert_file(db: &Database, name: &str, size: i64) -> i64 {
+        db.create_file(&File {
+            id: None,
+            path: format!("/test/{}", name),
+            name: name.to_string(),
+            extension: "bin".to_string(),
+            size,
+            file_type: FileType::Binary,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            accessed_at: Utc::now(),
+            status: FileStatus::Active,
+            indexed_at: Utc::now(),
+            metadata: None,
+            tags: None,
+        })
+        .unwrap()
+    }
+
+    fn size_rule(min: u64) -> TagRule {
+        TagRule {
+            name: "超大".to_string(),
+            condition: TagCondition::FileSize { min: Some(min), max: None },
+        }
+    }
+
+    #[test]
+    fn test_preview_does_not_write() {
+        let db = Database::in_memory().unwrap();
+        insert_file(&db, "small.bin", 10);
+        insert_file(&db, "big.bin", 1000);
+
+        let preview = preview_rule(&db, &size_rule(100)).unwrap();
+        assert_eq!(preview.match_count, 1);
+        assert_eq!(preview.sample[0].name, "big.bin");
+        assert_eq!(
+            preview.tag_changes,
+            vec![TagChange { tag_name: "超大".to_string(), added: 1, removed: 0 }]
+        );
+
+        assert!(db.get_tag_by_name("超大").unwrap().is_none());
+        assert!(db.get_setting(TAG_RULES_KEY).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_apply_then_tighten_rule() {
+        let db = Database::in_memory().unwrap();
+        let medium = insert_file(&db, "medium.bin", 500);
+        let big = insert_file(&db, "big.bin", 1000);
+
+        apply_rule(&db, size_rule(100)).unwrap();
+        assert_eq!(db.get_tag_by_name("超大").unwrap().unwrap().use_count, 2);
+        assert!(load_rules(&db).unwrap().contains(&size_rule(100)));
+
+        // 收紧阈值：预览应报告 medium 将被移除标签
+        let preview = preview_rule(&db, &size_rule(800)).unwrap();
+        assert_eq!(
+            preview.tag_changes,
+            vec![TagChange { tag_name: "超大".to_string(), added: 0, removed: 1 }]
+        );
+
+        apply_rule(&db, size_rule(800)).unwrap();
+        assert!(db.get_tags_by_file(medium).unwrap().is_empty());
+        assert_eq!(db.get_tags_by_file(big).unwrap().len(), 1);
+        assert_eq!(db.get_tag_by_name("超大").unwrap().unwrap().use_count, 1);
+
+        let rules = load_rules(&db).unwrap();
+        assert_eq!(rules.iter().filter(|r| r.name == "超大").count(), 1);
+    }
+
+    #[test]
+    fn test_failing_script_is_reported_and_not_applied() {
+        let db = Database::in_memory().unwrap();
+        insert_file(&db, "a.bin", 10);
+
+        let rule = TagRule {
+            name: "脚本".to_string(),
+            condition: TagCondition::Script("file.size / 0 > 1".to_string()),
+        };
+
+        let preview = preview_rule(&db, &rule).unwrap();
+        assert_eq!(preview.error_count, 1);
+        assert_eq!(preview.errors[0].path, "/test/a.bin");
+
+        assert!(apply_rule(&db, rule).is_err());
+        assert!(db.get_setting(TAG_RULES_KEY).unwrap().is_none());
+    }
+}
@@ -0,0 +1,455 @@
+use super::auto::{AutoTagger, GeneratedTags};
+use super::rules::deepest_root;
+use crate::db::{AutoTag, Database, File};
+use crate::error::{AppError, Result};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// 规则引擎提供者的标识
+pub const RULES_PROVIDER_ID: &str = "rules";
+
+/// 低于该置信度的标签不会写入
+const MIN_CONFIDENCE: f32 = 0.5;
+
+/// 提供者读取文件内容的上限（字节）
+const MAX_CONTENT_BYTES: u64 = 1024 * 1024;
+
+/// 提供者产生的标签
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProvidedTag {
+    pub name: String,
+    /// 置信度，范围 0.0 ~ 1.0
+    pub confidence: f32,
+    /// 产生该标签的规则或来源（记录在 file_tags.rule_name）
+    pub source: String,
+    /// 来源说明（记录在 file_tags.rule_detail）
+    pub detail: String,
+}
+
+/// 提供者对单个文件的输出
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderOutput {
+    pub tags: Vec<ProvidedTag>,
+    /// 执行失败的规则或来源，它们以前产生的标签保持不变
+    pub failed_sources: Vec<String>,
+}
+
+impl From<Vec<ProvidedTag>> for ProviderOutput {
+    fn from(tags: Vec<ProvidedTag>) -> Self {
+        ProviderOutput { tags, failed_sources: Vec::new() }
+    }
+}
+
+/// 提供者的输入：文件记录，以及按需读取的内容和元数据
+///
+/// 内容与元数据只在第一次访问时读取，多个提供者之间共享。
+pub struct FileContext<'a> {
+    pub file: &'a File,
+    content: OnceCell<Option<Vec<u8>>>,
+    metadata: OnceCell<Option<std::fs::Metadata>>,
+}
+
+impl<'a> FileContext<'a> {
+    pub fn new(file: &'a File) -> Self {
+        FileContext {
+            file,
+            content: OnceCell::new(),
+            metadata: OnceCell::new(),
+        }
+    }
+
+    /// 文件内容（最多读取前 1MB），无法读取时返回 None（预留功能）
+    #[allow(dead_code)]
+    pub fn content(&self) -> Option<&[u8]> {
+        self.content
+            .get_or_init(|| {
+                let file = std::fs::File::open(&self.file.path).ok()?;
+                let mut buffer = Vec::new();
+                file.take(MAX_CONTENT_BYTES).read_to_end(&mut buffer).ok()?;
+                Some(buffer)
+            })
+            .as_deref()
+    }
+
+    /// 文件系统元数据，无法读取时返回 None（预留功能）
+    #[allow(dead_code)]
+    pub fn metadata(&self) -> Option<&std::fs::Metadata> {
+        self.metadata
+            .get_or_init(|| std::fs::metadata(&self.file.path).ok())
+            .as_ref()
+    }
+}
+
+/// 标签提供者
+///
+/// 规则引擎、EXIF、音频标签、项目识别等来源各自实现该 trait，注册到 [`ProviderRegistry`]。
+pub trait TagProvider: Send + Sync {
+    /// 唯一标识，用于按目录启用和统计
+    fn id(&self) -> &str;
+
+    /// 显示名称
+    fn name(&self) -> &str;
+
+    /// 为文件产生标签
+    fn provide(&self, ctx: &FileContext) -> Result<ProviderOutput>;
+}
+
+/// 规则引擎提供者，包装 [`AutoTagger`]
+pub struct RuleProvider {
+    tagger: AutoTagger,
+}
+
+impl RuleProvider {
+    pub fn new(tagger: AutoTagger) -> Self {
+        RuleProvider { tagger }
+    }
+}
+
+impl TagProvider for RuleProvider {
+    fn id(&self) -> &str {
+        RULES_PROVIDER_ID
+    }
+
+    fn name(&self) -> &str {
+        "规则引擎"
+    }
+
+    fn provide(&self, ctx: &FileContext) -> Result<ProviderOutput> {
+        let generated = self.tagger.generate(ctx.file);
+
+        Ok(ProviderOutput {
+            tags: generated
+                .tags
+                .into_iter()
+                .map(|tag| ProvidedTag {
+                    name: tag.name,
+                    confidence: 1.0,
+                    source: tag.rule_name,
+                    detail: tag.rule_detail,
+                })
+                .collect(),
+            failed_sources: generated.failed_rules,
+        })
+    }
+}
+
+/// 提供者运行统计
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderStats {
+    /// 处理的文件数
+    pub files: u64,
+    /// 产生的标签数（过滤低置信度之前）
+    pub tags: u64,
+    /// 出错次数
+    pub errors: u64,
+    /// 累计耗时（毫秒）
+    pub total_ms: f64,
+    /// 单个文件的最长耗时（毫秒）
+    pub max_ms: f64,
+    /// 最近一次错误
+    pub last_error: Option<String>,
+}
+
+impl ProviderStats {
+    fn record(&mut self, elapsed: Duration, result: &Result<ProviderOutput>) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        self.files += 1;
+        self.total_ms += ms;
+        self.max_ms = self.max_ms.max(ms);
+
+        match result {
+            Ok(output) => self.tags += output.tags.len() as u64,
+            Err(e) => {
+                self.errors += 1;
+                self.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// 进程内累计的提供者统计（按提供者标识）
+fn stats() -> &'static Mutex<HashMap<String, ProviderStats>> {
+    static STATS: OnceLock<Mutex<HashMap<String, ProviderStats>>> = OnceLock::new();
+    STATS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// 提供者信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderInfo {
+    pub id: String,
+    pub name: String,
+    pub stats: ProviderStats,
+}
+
+/// 各监控目录启用的提供者
+#[derive(Debug, Clone, Default)]
+pub struct ProviderSettings {
+    /// (监控目录路径, 启用的提供者)，None 表示启用全部
+    directories: Vec<(String, Option<Vec<String>>)>,
+}
+
+impl ProviderSettings {
+    /// 从监控目录配置读取
+    pub fn load(db: &Database) -> Result<Self> {
+        let directories = db
+            .get_watched_directories()?
+            .into_iter()
+            .map(|d| (d.path, d.tag_providers))
+            .collect();
+
+        Ok(ProviderSettings { directories })
+    }
+
+    /// 监控目录路径
+    pub fn roots(&self) -> Vec<String> {
+        self.directories.iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    /// 提供者是否对该路径启用（按最深的监控目录判断，不在监控目录下时启用全部）
+    pub fn is_enabled(&self, provider_id: &str, path: &str) -> bool {
+        let root = deepest_root(self.directories.iter().map(|(p, _)| p.as_str()), path);
+
+        self.directories
+            .iter()
+            .find(|(p, _)| Some(p.as_str()) == root)
+            .and_then(|(_, providers)| providers.as_ref())
+            .is_none_or(|providers| providers.iter().any(|id| id == provider_id))
+    }
+}
+
+/// 提供者注册表
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn TagProvider>>,
+    settings: ProviderSettings,
+}
+
+impl ProviderRegistry {
+    pub fn new(settings: ProviderSettings) -> Self {
+        ProviderRegistry {
+            providers: Vec::new(),
+            settings,
+        }
+    }
+
+    /// 注册提供者，同名标签以先注册的提供者为准
+    pub fn with_provider(mut self, provider: impl TagProvider + 'static) -> Self {
+        self.providers.push(Box::new(provider));
+        self
+    }
+
+    /// 使用已保存的规则和目录配置创建包含所有内置提供者的注册表
+    pub fn load(db: &Database) -> Result<Self> {
+        let settings = ProviderSettings::load(db)?;
+        let tagger = AutoTagger::load(db)?;
+
+        Ok(Self::new(settings).with_provider(RuleProvider::new(tagger)))
+    }
+
+    /// 所有内置提供者的标识
+    pub fn builtin_ids() -> Vec<&'static str> {
+        vec![RULES_PROVIDER_ID]
+    }
+
+    /// 运行对该文件启用的提供者，合并为自动标签
+    ///
+    /// 单个提供者出错时只记入统计，不影响其他提供者；提供者报告的失败来源记录在 `failed_rules` 中。
+    pub fn generate(&self, file: &File) -> GeneratedTags {
+        let ctx = FileContext::new(file);
+        let mut generated = GeneratedTags::default();
+
+        for provider in &self.providers {
+            if !self.settings.is_enabled(provider.id(), &file.path) {
+                continue;
+            }
+
+            let start = Instant::now();
+            let result = provider.provide(&ctx);
+            stats()
+                .lock()
+                .entry(provider.id().to_string())
+                .or_default()
+                .record(start.elapsed(), &result);
+
+            let output = match result {
+                Ok(output) => output,
+                Err(e) => {
+                    eprintln!("[Tagger] 提供者 {} 处理 {} 失败: {}", provider.id(), file.path, e);
+                    continue;
+                }
+            };
+
+            generated.failed_rules.extend(output.failed_sources);
+            for tag in output.tags {
+                if tag.confidence < MIN_CONFIDENCE || generated.tags.iter().any(|t| t.name == tag.name) {
+                    continue;
+                }
+                generated.tags.push(AutoTag {
+                    name: tag.name,
+                    rule_name: tag.source,
+                    rule_detail: tag.detail,
+                });
+            }
+        }
+
+        generated
+    }
+
+    /// 已注册提供者及其累计统计
+    pub fn providers(&self) -> Vec<ProviderInfo> {
+        let stats = stats().lock();
+
+        self.providers
+            .iter()
+            .map(|p| ProviderInfo {
+                id: p.id().to_string(),
+                name: p.name().to_string(),
+                stats: stats.get(p.id()).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+/// 设置监控目录启用的提供者，None 表示启用全部
+pub fn set_directory_providers(db: &Database, directory_id: i64, providers: Option<Vec<String>>) -> Result<()> {
+    if let Some(providers) = &providers {
+        let known = ProviderRegistry::builtin_ids();
+        if let Some(unknown) = providers.iter().find(|id| !known.contains(&id.as_str())) {
+            return Err(AppError::UnknownProvider(unknown.clone()));
+        }
+    }
+
+    db.set_directory_tag_providers(directory_id, providers.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{FileStatus, FileType};
+    use chrono::Utc;
+
+    fn create_test_file(path: &str) -> File {
+        File {
+            id: Some(1),
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            extension: "txt".to_string(),
+            size: 100,
+            file_type: FileType::Text,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            accessed_at: Utc::now(),
+            status: FileStatus::Active,
+            indexed_at: Utc::now(),
+            metadata: None,
+            tags: None,
+        }
+    }
+
+    struct FixedProvider {
+        id: &'static str,
+        tags: Vec<(&'static str, f32)>,
+    }
+
+    impl TagProvider for FixedProvider {
+        fn id(&self) -> &str {
+            self.id
+        }
+
+        fn name(&self) -> &str {
+            self.id
+        }
+
+        fn provide(&self, _ctx: &FileContext) -> Result<ProviderOutput> {
+            Ok(self.tags
+                .iter()
+                .map(|(name, confidence)| ProvidedTag {
+                    name: name.to_string(),
+                    confidence: *confidence,
+                    source: self.id.to_string(),
+                    detail: String::new(),
+                })
+                .collect::<Vec<_>>()
+                .into())
+        }
+    }
+
+    struct FailingProvider;
+
+    impl TagProvider for FailingProvider {
+        fn id(&self) -> &str {
+            "test-failing"
+        }
+
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn provide(&self, _ctx: &FileContext) -> Result<ProviderOutput> {
+            Err(AppError::Unknown("boom".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_merge_and_confidence_filter() {
+        let registry = ProviderRegistry::new(ProviderSettings::default())
+            .with_provider(FixedProvider { id: "test-a", tags: vec![("相机", 0.9), ("猜测", 0.2)] })
+            .with_provider(FixedProvider { id: "test-b", tags: vec![("相机", 1.0), ("项目", 0.8)] });
+
+        let tags = registry.generate(&create_test_file("/home/a.txt")).tags;
+        let summary: Vec<(&str, &str)> = tags.iter().map(|t| (t.name.as_str(), t.rule_name.as_str())).collect();
+        assert_eq!(summary, vec![("相机", "test-a"), ("项目", "test-b")]);
+    }
+
+    #[test]
+    fn test_enabled_per_directory() {
+        let settings = ProviderSettings {
+            directories: vec![
+                ("/photos".to_string(), Some(vec!["test-exif".to_string()])),
+                ("/photos/raw".to_string(), None),
+            ],
+        };
+        let registry = ProviderRegistry::new(settings)
+            .with_provider(FixedProvider { id: "test-exif", tags: vec![("照片", 1.0)] })
+            .with_provider(FixedProvider { id: "test-project", tags: vec![("项目", 1.0)] });
+
+        let names = |path: &str| -> Vec<String> {
+            registry.generate(&create_test_file(path)).tags.into_iter().map(|t| t.name).collect()
+        };
+        assert_eq!(names("/photos/a.txt"), vec!["照片"]);
+        assert_eq!(names("/photos/raw/a.txt"), vec!["照片", "项目"]);
+        assert_eq!(names("/elsewhere/a.txt"), vec!["照片", "项目"]);
+    }
+
+    #[test]
+    fn test_failing_provider_is_isolated_and_counted() {
+        let registry = ProviderRegistry::new(ProviderSettings::default())
+            .with_provider(FailingProvider)
+            .with_provider(FixedProvider { id: "test-ok", tags: vec![("正常", 1.0)] });
+
+        let tags = registry.generate(&create_test_file("/home/a.txt")).tags;
+        assert_eq!(tags.len(), 1);
+
+        let info = registry.providers();
+        let failing = info.iter().find(|p| p.id == "test-failing").unwrap();
+        assert!(failing.stats.errors >= 1);
+        assert_eq!(failing.stats.last_error.as_deref(), Some("未知错误: boom"));
+        assert!(info.iter().find(|p| p.id == "test-ok").unwrap().stats.files >= 1);
+    }
+
+    #[test]
+    fn test_rule_provider_keeps_rule_provenance() {
+        let registry = ProviderRegistry::new(ProviderSettings::default())
+            .with_provider(RuleProvider::new(AutoTagger::new()));
+
+        let tags = registry.generate(&create_test_file("/home/a.txt")).tags;
+        let text = tags.iter().find(|t| t.name == "文本").unwrap();
+        assert_eq!(text.rule_name, "文本");
+    }
+}
@@ -1,17 +1,25 @@
+use super::script::ScriptRule;
 use crate::db::{File, FileType};
-use chrono::{Datelike, Utc};
+use crate::error::{AppError, Result};
+use chrono::{DateTime, Datelike, Duration, Local};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// 标签规则定义
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TagRule {
-    /// 规则名称（生成的标签名）
+    /// 规则名称（生成的标签名，正则规则可使用 `{group}` 引用捕获组）
     pub name: String,
     /// 规则条件
     pub condition: TagCondition,
 }
 
 /// 标签条件
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
 pub enum TagCondition {
     /// 文件类型匹配
     FileType(Vec<FileType>),
@@ -21,44 +29,235 @@ pub enum TagCondition {
     DatePattern(DatePattern),
     /// 路径包含指定字符串
     PathContains(String),
-    /// 扩展名匹配（预留功能）
-    #[allow(dead_code)]
+    /// 扩展名匹配
     Extension(Vec<String>),
-    /// 文件名包含指定字符串（预留功能）
-    #[allow(dead_code)]
+    /// 文件名包含指定字符串
     NameContains(String),
+    /// 文件名匹配正则表达式
+    NameRegex(String),
+    /// 路径匹配正则表达式
+    PathRegex(String),
+    /// 路径匹配通配符（如 `**/invoices/*.pdf`，不区分大小写）
+    PathGlob(String),
+    /// 路径模板（如 `{client}/{year}/*`），按监控根目录下的目录层级生成 `client:X`、`year:2024` 等派生标签
+    PathTemplate(String),
+    /// Rhai 脚本，可读取 `file` 的字段，调用 `tag("x")` 添加标签，返回 `true` 时添加规则名称
+    Script(String),
+}
+
+impl TagCondition {
+    /// 条件结果是否随当前时间变化（需要定期重新评估）
+    pub fn is_time_relative(&self) -> bool {
+        matches!(self, TagCondition::DatePattern(_))
+    }
+
+    /// 条件的可读描述，用于说明自动标签的来源
+    pub fn describe(&self) -> String {
+        match self {
+            TagCondition::FileType(types) => {
+                let names: Vec<&str> = types.iter().map(|t| t.display_name()).collect();
+                format!("文件类型为 {}", names.join("/"))
+            }
+            TagCondition::FileSize { min, max } => match (min, max) {
+                (Some(min), Some(max)) => format!("{} ≤ 大小 ≤ {}", format_size(*min), format_size(*max)),
+                (Some(min), None) => format!("大小 ≥ {}", format_size(*min)),
+                (None, Some(max)) => format!("大小 ≤ {}", format_size(*max)),
+                (None, None) => "任意大小".to_string(),
+            },
+            TagCondition::DatePattern(pattern) => format!("修改于{}", pattern.display_name()),
+            TagCondition::PathContains(s) => format!("路径包含 '{}'", s),
+            TagCondition::Extension(exts) => format!("扩展名为 {}", exts.join("/")),
+            TagCondition::NameContains(s) => format!("文件名包含 '{}'", s),
+            TagCondition::NameRegex(p) => format!("文件名匹配正则 '{}'", p),
+            TagCondition::PathRegex(p) => format!("路径匹配正则 '{}'", p),
+            TagCondition::PathGlob(p) => format!("路径匹配通配符 '{}'", p),
+            TagCondition::PathTemplate(t) => format!("路径匹配模板 '{}'", t),
+            TagCondition::Script(_) => "脚本规则".to_string(),
+        }
+    }
+}
+
+/// 格式化文件大小（取能整除的最大单位）
+fn format_size(bytes: u64) -> String {
+    const UNITS: [(u64, &str); 3] = [(1024 * 1024 * 1024, "GB"), (1024 * 1024, "MB"), (1024, "KB")];
+
+    for (unit, suffix) in UNITS {
+        if bytes >= unit && bytes.is_multiple_of(unit) {
+            return format!("{}{}", bytes / unit, suffix);
+        }
+    }
+    format!("{}B", bytes)
 }
 
 /// 日期模式
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum DatePattern {
     /// 今天
     Today,
     /// 昨天
-    #[allow(dead_code)]
     Yesterday,
     /// 本周
     ThisWeek,
     /// 上周
-    #[allow(dead_code)]
     LastWeek,
     /// 本月
     ThisMonth,
     /// 上月
-    #[allow(dead_code)]
     LastMonth,
     /// 本年
-    #[allow(dead_code)]
     ThisYear,
     /// 去年
-    #[allow(dead_code)]
     LastYear,
 }
 
-impl TagRule {
-    /// 检查文件是否匹配此规则
-    pub fn matches(&self, file: &File) -> bool {
-        match &self.condition {
+impl DatePattern {
+    /// 日期模式的中文名称
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            DatePattern::Today => "今天",
+            DatePattern::Yesterday => "昨天",
+            DatePattern::ThisWeek => "本周",
+            DatePattern::LastWeek => "上周",
+            DatePattern::ThisMonth => "本月",
+            DatePattern::LastMonth => "上月",
+            DatePattern::ThisYear => "今年",
+            DatePattern::LastYear => "去年",
+        }
+    }
+}
+
+/// 预编译的标签规则
+///
+/// 正则与通配符只在创建 `AutoTagger` 时编译一次，避免每个文件重复编译。
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub rule: TagRule,
+    matcher: Matcher,
+}
+
+/// 条件的预编译结果
+#[derive(Debug, Clone)]
+enum Matcher {
+    /// 无需预编译的条件
+    Plain,
+    /// 正则表达式
+    Regex(Regex),
+    /// 路径通配符
+    Glob(GlobMatcher),
+    /// 路径模板的各层目录
+    Template(Vec<Segment>),
+    /// 脚本
+    Script(Arc<ScriptRule>),
+}
+
+/// 路径模板中的一层目录
+#[derive(Debug, Clone)]
+enum Segment {
+    /// `{key}`：捕获该层目录名，生成 `key:目录名` 标签
+    Capture(String),
+    /// `*`：匹配任意目录名
+    Any,
+    /// 字面量：目录名需相同（不区分大小写）
+    Literal(String),
+}
+
+impl CompiledRule {
+    /// 编译规则，正则或通配符无效时返回错误
+    pub fn compile(rule: TagRule) -> Result<Self> {
+        let matcher = match &rule.condition {
+            TagCondition::NameRegex(pattern) | TagCondition::PathRegex(pattern) => {
+                let re = Regex::new(pattern).map_err(|e| {
+                    AppError::InvalidRule(format!("规则 '{}' 的正则表达式无效: {}", rule.name, e))
+                })?;
+                Matcher::Regex(re)
+            }
+            TagCondition::PathGlob(pattern) => {
+                let glob = GlobBuilder::new(&normalize_path(pattern))
+                    .case_insensitive(true)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| {
+                        AppError::InvalidRule(format!("规则 '{}' 的通配符无效: {}", rule.name, e))
+                    })?;
+                Matcher::Glob(glob.compile_matcher())
+            }
+            TagCondition::PathTemplate(template) => {
+                Matcher::Template(parse_template(&rule.name, template)?)
+            }
+            TagCondition::Script(source) => {
+                let script = ScriptRule::compile(source).map_err(|e| {
+                    AppError::InvalidRule(format!("规则 '{}' 的{}", rule.name, e))
+                })?;
+                Matcher::Script(Arc::new(script))
+            }
+            _ => Matcher::Plain,
+        };
+
+        Ok(CompiledRule { rule, matcher })
+    }
+
+    /// 对文件应用规则，返回生成的标签名（不匹配时为空）
+    ///
+    /// `root` 为文件所在的监控根目录，仅路径模板规则需要。只有脚本规则会执行失败。
+    pub fn apply(&self, file: &File, root: Option<&str>) -> Result<Vec<String>> {
+        if let Matcher::Script(script) = &self.matcher {
+            let outcome = script.run(file).map_err(|e| {
+                AppError::InvalidRule(format!("规则 '{}' 在 {} 上{}", self.rule.name, file.path, e))
+            })?;
+
+            let mut tags = outcome.tags;
+            if outcome.matched {
+                tags.push(self.rule.name.clone());
+            }
+            return Ok(tags);
+        }
+
+        Ok(self.apply_static(file, root))
+    }
+
+    /// 应用无需执行脚本的规则
+    fn apply_static(&self, file: &File, root: Option<&str>) -> Vec<String> {
+        match &self.matcher {
+            Matcher::Regex(re) => {
+                let haystack = match self.rule.condition {
+                    TagCondition::NameRegex(_) => file.name.as_str(),
+                    _ => file.path.as_str(),
+                };
+                re.captures(haystack)
+                    .and_then(|caps| {
+                        expand_template(&self.rule.name, |key| match key.parse::<usize>() {
+                            Ok(index) => caps.get(index).map(|m| m.as_str()),
+                            Err(_) => caps.name(key).map(|m| m.as_str()),
+                        })
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            Matcher::Glob(glob) => {
+                if glob.is_match(normalize_path(&file.path)) {
+                    vec![self.rule.name.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+            Matcher::Template(segments) => {
+                root.map(|r| match_template(segments, &file.path, r))
+                    .unwrap_or_default()
+            }
+            Matcher::Plain | Matcher::Script(_) => {
+                if self.matches_plain(file) {
+                    vec![self.rule.name.clone()]
+                } else {
+                    Vec::new()
+                }
+            }
+        }
+    }
+
+    /// 检查文件是否匹配无需预编译的条件
+    fn matches_plain(&self, file: &File) -> bool {
+        match &self.rule.condition {
             TagCondition::FileType(types) => types.contains(&file.file_type),
             TagCondition::FileSize { min, max } => {
                 let size = file.size as u64;
@@ -70,7 +269,7 @@ impl TagRule {
                 }
             }
             TagCondition::DatePattern(pattern) => {
-                self.check_date_pattern(file, pattern)
+                check_date_pattern(file, pattern, Local::now())
             }
             TagCondition::PathContains(s) => {
                 file.path.to_lowercase().contains(&s.to_lowercase())
@@ -81,51 +280,161 @@ impl TagRule {
             TagCondition::NameContains(s) => {
                 file.name.to_lowercase().contains(&s.to_lowercase())
             }
+            TagCondition::NameRegex(_)
+            | TagCondition::PathRegex(_)
+            | TagCondition::PathGlob(_)
+            | TagCondition::PathTemplate(_)
+            | TagCondition::Script(_) => false,
         }
     }
 
-    /// 检查日期模式
-    fn check_date_pattern(&self, file: &File, pattern: &DatePattern) -> bool {
-        let file_date = file.modified_at;
-        let now = Utc::now();
+}
 
-        match pattern {
-            DatePattern::Today => {
-                file_date.date_naive() == now.date_naive()
-            }
-            DatePattern::Yesterday => {
-                let yesterday = now - chrono::Duration::days(1);
-                file_date.date_naive() == yesterday.date_naive()
-            }
-            DatePattern::ThisWeek => {
-                let week_start = now - chrono::Duration::days(now.weekday().num_days_from_monday() as i64);
-                file_date >= week_start
-            }
-            DatePattern::LastWeek => {
-                let week_start = now - chrono::Duration::days(now.weekday().num_days_from_monday() as i64);
-                let last_week_start = week_start - chrono::Duration::weeks(1);
-                let last_week_end = week_start;
-                file_date >= last_week_start && file_date < last_week_end
-            }
-            DatePattern::ThisMonth => {
-                file_date.year() == now.year() && file_date.month() == now.month()
-            }
-            DatePattern::LastMonth => {
-                let (year, month) = if now.month() == 1 {
-                    (now.year() - 1, 12)
+/// 解析路径模板
+fn parse_template(rule_name: &str, template: &str) -> Result<Vec<Segment>> {
+    let segments: Vec<Segment> = normalize_path(template)
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            if s == "*" {
+                Ok(Segment::Any)
+            } else if let Some(key) = s.strip_prefix('{').and_then(|k| k.strip_suffix('}')) {
+                if key.is_empty() {
+                    Err(AppError::InvalidRule(format!("规则 '{}' 的路径模板包含空占位符", rule_name)))
                 } else {
-                    (now.year(), now.month() - 1)
-                };
-                file_date.year() == year && file_date.month() == month
-            }
-            DatePattern::ThisYear => {
-                file_date.year() == now.year()
+                    Ok(Segment::Capture(key.to_string()))
+                }
+            } else {
+                Ok(Segment::Literal(s.to_lowercase()))
             }
-            DatePattern::LastYear => {
-                file_date.year() == now.year() - 1
+        })
+        .collect::<Result<_>>()?;
+
+    if segments.is_empty() {
+        return Err(AppError::InvalidRule(format!("规则 '{}' 的路径模板为空", rule_name)));
+    }
+
+    Ok(segments)
+}
+
+/// 按模板匹配文件相对根目录的目录层级，返回派生标签
+///
+/// 目录层级少于模板层数时不匹配；更深的层级不影响匹配。
+fn match_template(segments: &[Segment], path: &str, root: &str) -> Vec<String> {
+    let path = normalize_path(path);
+    let root = normalize_path(root);
+    let relative = match path.strip_prefix(root.trim_end_matches('/')) {
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => return Vec::new(),
+    };
+
+    let mut components: Vec<&str> = relative.split('/').filter(|c| !c.is_empty()).collect();
+    // 最后一段是文件名
+    components.pop();
+
+    if components.len() < segments.len() {
+        return Vec::new();
+    }
+
+    let mut tags = Vec::new();
+    for (segment, component) in segments.iter().zip(components) {
+        match segment {
+            Segment::Capture(key) => tags.push(format!("{}:{}", key, component)),
+            Segment::Any => {}
+            Segment::Literal(literal) => {
+                if component.to_lowercase() != *literal {
+                    return Vec::new();
+                }
             }
         }
     }
+
+    tags
+}
+
+/// 展开标签名模板中的 `{key}` 占位符
+///
+/// 任一占位符取不到值或值为空时返回 `None`，避免生成 `client:` 这样的残缺标签。
+pub(crate) fn expand_template<'a, F>(template: &str, lookup: F) -> Option<String>
+where
+    F: Fn(&str) -> Option<&'a str>,
+{
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(offset) => start + offset,
+            None => break,
+        };
+
+        let value = lookup(&rest[start + 1..end]).filter(|v| !v.is_empty())?;
+        output.push_str(&rest[..start]);
+        output.push_str(value);
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    Some(output)
+}
+
+/// 在给定的监控根目录中查找包含该路径的最深一个
+pub(crate) fn deepest_root<'a>(roots: impl Iterator<Item = &'a str>, path: &str) -> Option<&'a str> {
+    let path = normalize_path(path);
+    roots
+        .filter(|root| {
+            let root = normalize_path(root);
+            path.strip_prefix(root.trim_end_matches('/'))
+                .is_some_and(|rest| rest.starts_with('/'))
+        })
+        .max_by_key(|root| root.len())
+}
+
+/// 统一路径分隔符，使通配符与模板在 Windows 路径上同样生效
+pub(crate) fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// 检查日期模式
+///
+/// 日、周、月的边界均按本地时区计算，周从周一开始。
+pub(crate) fn check_date_pattern(file: &File, pattern: &DatePattern, now: DateTime<Local>) -> bool {
+    let file_date = file.modified_at.with_timezone(&Local).date_naive();
+    let today = now.date_naive();
+    let week_start = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+
+    match pattern {
+        DatePattern::Today => {
+            file_date == today
+        }
+        DatePattern::Yesterday => {
+            file_date == today - Duration::days(1)
+        }
+        DatePattern::ThisWeek => {
+            file_date >= week_start && file_date <= today
+        }
+        DatePattern::LastWeek => {
+            let last_week_start = week_start - Duration::weeks(1);
+            file_date >= last_week_start && file_date < week_start
+        }
+        DatePattern::ThisMonth => {
+            file_date.year() == today.year() && file_date.month() == today.month()
+        }
+        DatePattern::LastMonth => {
+            let (year, month) = if today.month() == 1 {
+                (today.year() - 1, 12)
+            } else {
+                (today.year(), today.month() - 1)
+            };
+            file_date.year() == year && file_date.month() == month
+        }
+        DatePattern::ThisYear => {
+            file_date.year() == today.year()
+        }
+        DatePattern::LastYear => {
+            file_date.year() == today.year() - 1
+        }
+    }
 }
 
 /// 获取默认标签规则
@@ -197,7 +506,7 @@ pub fn default_rules() -> Vec<TagRule> {
             condition: TagCondition::PathContains("desktop".to_string()),
         },
         TagRule {
-            name: "图片".to_string(),
+            name: "图片文件夹".to_string(),
             condition: TagCondition::PathContains("pictures".to_string()),
         },
         TagRule {
@@ -205,7 +514,7 @@ pub fn default_rules() -> Vec<TagRule> {
             condition: TagCondition::PathContains("music".to_string()),
         },
         TagRule {
-            name: "视频".to_string(),
+            name: "视频文件夹".to_string(),
             condition: TagCondition::PathContains("videos".to_string()),
         },
     ]
@@ -0,0 +1,158 @@
+use super::provider::{ProviderRegistry, ProviderSettings, RuleProvider};
+use super::AutoTagger;
+use crate::db::{Database, File};
+use crate::error::Result;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone};
+use tauri::Manager;
+
+/// 最长检查间隔（秒），系统休眠或时钟调整后也能及时发现跨天
+const MAX_CHECK_INTERVAL_SECS: u64 = 3600;
+
+/// 计算下一个本地零点
+///
+/// 周、月的边界都落在本地零点上，因此每天零点重新评估即可覆盖所有时间相关规则。
+pub fn next_local_midnight(now: DateTime<Local>) -> DateTime<Local> {
+    let midnight = (now.date_naive() + Duration::days(1)).and_time(NaiveTime::MIN);
+
+    Local.from_local_datetime(&midnight)
+        .earliest()
+        // 零点落在夏令时间隙中时顺延一小时
+        .or_else(|| Local.from_local_datetime(&(midnight + Duration::hours(1))).earliest())
+        .unwrap_or(now + Duration::hours(1))
+}
+
+/// 重新评估所有活跃文件的时间相关标签（今日文件、本周文件等），返回处理的文件数
+pub fn refresh_time_relative_tags(db: &Database, tagger: &AutoTagger) -> Result<usize> {
+    let tagger = tagger.time_relative();
+    let names: Vec<String> = tagger.rules().iter().map(|r| r.name.clone()).collect();
+    if names.is_empty() {
+        return Ok(0);
+    }
+
+    // 仍然遵循各监控目录的提供者配置
+    let registry = ProviderRegistry::new(ProviderSettings::load(db)?)
+        .with_provider(RuleProvider::new(tagger));
+
+    // 只移除这些规则产生的标签，其他规则和提供者的标签不受影响
+    for_each_active_file(db, |file_id, file| {
+        let generated = registry.generate(file);
+        db.sync_rule_tags(file_id, &names, &generated.tags, &generated.failed_rules)
+    })
+}
+
+/// 用给定提供者重新计算所有活跃文件的自动标签，返回处理的文件数
+pub fn resync_auto_tags(db: &Database, registry: &ProviderRegistry) -> Result<usize> {
+    for_each_active_file(db, |file_id, file| {
+        let generated = registry.generate(file);
+        db.sync_auto_tags(file_id, &generated.tags, &generated.failed_rules)
+    })
+}
+
+/// 对每个活跃文件执行 `sync`，返回处理的文件数
+fn for_each_active_file(db: &Database, sync: impl Fn(i64, &File) -> Result<()>) -> Result<usize> {
+    let files = db.get_files(None, None)?;
+    let mut count = 0;
+
+    for file in &files {
+        if let Some(file_id) = file.id {
+            sync(file_id, file)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// 启动时间标签调度器：启动时及每个本地零点重新评估时间相关标签
+pub fn spawn_date_tag_scheduler(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut last_run: Option<NaiveDate> = None;
+
+        loop {
+            let today = Local::now().date_naive();
+            if last_run != Some(today) {
+                let handle = app.clone();
+                let result = tauri::async_runtime::spawn_blocking(move || {
+                    let db = handle.state::<Database>();
+                    refresh_time_relative_tags(&db, &AutoTagger::load(&db)?)
+                })
+                .await;
+
+                match result {
+                    Ok(Ok(count)) => println!("[Scheduler] 已重新评估 {} 个文件的时间标签", count),
+                    Ok(Err(e)) => eprintln!("[Scheduler] 重新评估时间标签失败: {}", e),
+                    Err(e) => eprintln!("[Scheduler] 调度任务异常: {}", e),
+                }
+                last_run = Some(today);
+            }
+
+            let now = Local::now();
+            let wait = (next_local_midnight(now) - now)
+                .to_std()
+                .unwrap_or_default()
+                .min(std::time::Duration::from_secs(MAX_CHECK_INTERVAL_SECS));
+            // 多等一秒，确保醒来时已经跨过零点
+            tokio::time::sleep(wait + std::time::Duration::from_secs(1)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{File, FileStatus, FileType};
+    use crate::tagger::rules::{check_date_pattern, DatePattern};
+    use chrono::Utc;
+
+    fn local(y: i32, m: u32, d: u32, h: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, 0, 0).earliest().unwrap()
+    }
+
+    fn file_modified_at(modified_at: DateTime<Local>) -> File {
+        File {
+            id: None,
+            path: "/test/file.txt".to_string(),
+            name: "file.txt".to_string(),
+            extension: "txt".to_string(),
+            size: 1024,
+            file_type: FileType::Text,
+            created_at: Utc::now(),
+            modified_at: modified_at.with_timezone(&Utc),
+            accessed_at: Utc::now(),
+            status: FileStatus::Active,
+            indexed_at: Utc::now(),
+            metadata: None,
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn test_next_local_midnight() {
+        let next = next_local_midnight(local(2026, 3, 31, 15));
+        assert_eq!(next.date_naive(), NaiveDate::from_ymd_opt(2026, 4, 1).unwrap());
+        assert!(next > local(2026, 3, 31, 23));
+    }
+
+    #[test]
+    fn test_today_uses_local_day_boundary() {
+        // 本地 00:30 修改的文件，到当天 23:00 仍是“今日文件”，次日则不是
+        let file = file_modified_at(Local.with_ymd_and_hms(2026, 3, 2, 0, 30, 0).earliest().unwrap());
+
+        assert!(check_date_pattern(&file, &DatePattern::Today, local(2026, 3, 2, 23)));
+        assert!(!check_date_pattern(&file, &DatePattern::Today, local(2026, 3, 3, 1)));
+        assert!(check_date_pattern(&file, &DatePattern::Yesterday, local(2026, 3, 3, 1)));
+    }
+
+    #[test]
+    fn test_week_and_month_boundaries() {
+        // 2026-03-02 是周一
+        let monday = file_modified_at(local(2026, 3, 2, 9));
+        assert!(check_date_pattern(&monday, &DatePattern::ThisWeek, local(2026, 3, 8, 22)));
+        assert!(!check_date_pattern(&monday, &DatePattern::ThisWeek, local(2026, 3, 9, 1)));
+        assert!(check_date_pattern(&monday, &DatePattern::LastWeek, local(2026, 3, 9, 1)));
+
+        assert!(check_date_pattern(&monday, &DatePattern::ThisMonth, local(2026, 3, 31, 23)));
+        assert!(!check_date_pattern(&monday, &DatePattern::ThisMonth, local(2026, 4, 1, 0)));
+        assert!(check_date_pattern(&monday, &DatePattern::LastMonth, local(2026, 4, 1, 0)));
+    }
+}
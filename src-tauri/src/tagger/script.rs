@@ -0,0 +1,232 @@
+use crate::db::File;
+use parking_lot::Mutex;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// 单个文件上脚本的最长执行时间
+const SCRIPT_TIME_LIMIT: Duration = Duration::from_millis(100);
+
+/// 单次执行的最大操作数（与时间限制一起防止死循环）
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// 预编译的脚本规则
+///
+/// 脚本运行在受限的 Rhai 引擎中：不能加载模块、不能 `eval`，`file` 为只读常量，
+/// 通过 `tag("名称")` 添加标签，脚本返回 `true` 时还会添加规则名称本身。
+pub struct ScriptRule {
+    engine: Engine,
+    ast: AST,
+    /// 本次执行中 `tag()` 收集到的标签
+    output: Arc<Mutex<Vec<String>>>,
+    /// 本次执行的开始时间，用于超时检查
+    started: Arc<Mutex<Instant>>,
+    /// 同一脚本的执行串行进行，保证 `output` 不被并发写入
+    running: Mutex<()>,
+}
+
+impl std::fmt::Debug for ScriptRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptRule").finish_non_exhaustive()
+    }
+}
+
+/// 脚本执行结果
+pub struct ScriptOutcome {
+    /// `tag()` 添加的标签
+    pub tags: Vec<String>,
+    /// 脚本是否返回 `true`
+    pub matched: bool,
+}
+
+impl ScriptRule {
+    /// 编译脚本，语法错误时返回带位置的错误信息
+    pub fn compile(source: &str) -> std::result::Result<Self, String> {
+        let output = Arc::new(Mutex::new(Vec::new()));
+        let started = Arc::new(Mutex::new(Instant::now()));
+
+        let mut engine = Engine::new();
+        engine.set_module_resolver(DummyModuleResolver::new());
+        engine.disable_symbol("eval");
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(32);
+        engine.set_max_expr_depths(64, 32);
+        engine.set_max_string_size(64 * 1024);
+        engine.set_max_array_size(10_000);
+        engine.set_max_map_size(10_000);
+        engine.on_print(|_| {});
+        engine.on_debug(|_, _, _| {});
+
+        let clock = started.clone();
+        engine.on_progress(move |_| {
+            if clock.lock().elapsed() > SCRIPT_TIME_LIMIT {
+                Some(Dynamic::UNIT)
+            } else {
+                None
+            }
+        });
+
+        let sink = output.clone();
+        engine.register_fn("tag", move |name: &str| -> std::result::Result<(), Box<EvalAltResult>> {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err("tag() 的标签名不能为空".into());
+            }
+            sink.lock().push(name.to_string());
+            Ok(())
+        });
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| format!("脚本语法错误: {}", e))?;
+
+        Ok(ScriptRule {
+            engine,
+            ast,
+            output,
+            started,
+            running: Mutex::new(()),
+        })
+    }
+
+    /// 对文件执行脚本
+    pub fn run(&self, file: &File) -> std::result::Result<ScriptOutcome, String> {
+        let _guard = self.running.lock();
+        self.output.lock().clear();
+        *self.started.lock() = Instant::now();
+
+        let mut scope = Scope::new();
+        scope.push_constant("file", file_to_map(file));
+
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| describe_error(*e))?;
+
+        Ok(ScriptOutcome {
+            tags: std::mem::take(&mut *self.output.lock()),
+            matched: result.as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// 将文件字段转换为脚本中的 `file` 对象
+fn file_to_map(file: &File) -> Map {
+    let mut map = Map::new();
+    map.insert("name".into(), file.name.clone().into());
+    map.insert("path".into(), file.path.clone().into());
+    map.insert("extension".into(), file.extension.clone().into());
+    map.insert("size".into(), file.size.into());
+    map.insert("type".into(), rhai::serde::to_dynamic(&file.file_type).unwrap_or(Dynamic::UNIT));
+    map.insert("status".into(), file.status.as_str().into());
+    map.insert("created_at".into(), file.created_at.timestamp().into());
+    map.insert("modified_at".into(), file.modified_at.timestamp().into());
+    map.insert("accessed_at".into(), file.accessed_at.timestamp().into());
+    map.insert(
+        "metadata".into(),
+        file.metadata
+            .as_ref()
+            .and_then(|m| rhai::serde::to_dynamic(m).ok())
+            .unwrap_or(Dynamic::UNIT),
+    );
+    map
+}
+
+/// 将运行时错误转换为可读信息
+fn describe_error(error: EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorTerminated(_, _) => {
+            format!("脚本执行超过 {}ms 被终止", SCRIPT_TIME_LIMIT.as_millis())
+        }
+        EvalAltResult::ErrorTooManyOperations(_) => "脚本执行步数超过上限".to_string(),
+        e => format!("脚本执行错误: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{FileStatus, FileType};
+    use chrono::Utc;
+
+    fn create_test_file(name: &str, size: i64) -> File {
+        File {
+            id: Some(1),
+            path: format!("/photos/{}", name),
+            name: name.to_string(),
+            extension: "jpg".to_string(),
+            size,
+            file_type: FileType::Image,
+            created_at: Utc::now(),
+            modified_at: Utc::now(),
+            accessed_at: Utc::now(),
+            status: FileStatus::Active,
+            indexed_at: Utc::now(),
+            metadata: Some(serde_json::json!({ "camera": "X100" })),
+            tags: None,
+        }
+    }
+
+    #[test]
+    fn test_tag_call() {
+        let script = ScriptRule::compile(
+            r#"if file.size > 1e6 && file.name.starts_with("IMG_") { tag("camera-roll") }"#,
+        )
+        .unwrap();
+
+        let outcome = script.run(&create_test_file("IMG_0001.jpg", 2_000_000)).unwrap();
+        assert_eq!(outcome.tags, vec!["camera-roll"]);
+
+        let outcome = script.run(&create_test_file("IMG_0002.jpg", 1_000)).unwrap();
+        assert!(outcome.tags.is_empty());
+    }
+
+    #[test]
+    fn test_tag_name_is_trimmed_and_required() {
+        let script = ScriptRule::compile(r#"tag("  raw  ")"#).unwrap();
+        let outcome = script.run(&create_test_file("a.jpg", 1)).unwrap();
+        assert_eq!(outcome.tags, vec!["raw"]);
+
+        let script = ScriptRule::compile(r#"tag("raw"); tag("   ")"#).unwrap();
+        let error = script.run(&create_test_file("a.jpg", 1)).err().unwrap();
+        assert!(error.contains("标签名不能为空"), "{}", error);
+    }
+
+    #[test]
+    fn test_boolean_result_and_metadata() {
+        let script = ScriptRule::compile(r#"file.type == "image" && file.metadata.camera == "X100""#).unwrap();
+        assert!(script.run(&create_test_file("a.jpg", 1)).unwrap().matched);
+    }
+
+    #[test]
+    fn test_file_is_read_only() {
+        let script = ScriptRule::compile(r#"file.size = 0; true"#);
+        let error = match script {
+            Ok(script) => script.run(&create_test_file("a.jpg", 1)).err().unwrap(),
+            Err(e) => e,
+        };
+        assert!(error.contains("file"), "{}", error);
+    }
+
+    #[test]
+    fn test_infinite_loop_is_terminated() {
+        let script = ScriptRule::compile("loop { }").unwrap();
+        let error = script.run(&create_test_file("a.jpg", 1)).err().unwrap();
+        assert!(error.contains("被终止") || error.contains("上限"), "{}", error);
+    }
+
+    #[test]
+    fn test_syntax_error_has_position() {
+        let error = ScriptRule::compile("if file.size > { tag(").err().unwrap();
+        assert!(error.contains("line 1"), "{}", error);
+    }
+
+    #[test]
+    fn test_sandbox_blocks_eval_and_import() {
+        assert!(ScriptRule::compile(r#"eval("1")"#).is_err());
+
+        let script = ScriptRule::compile(r#"import "os" as os; true"#).unwrap();
+        assert!(script.run(&create_test_file("a.jpg", 1)).is_err());
+    }
+}
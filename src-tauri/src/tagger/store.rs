@@ -0,0 +1,334 @@
+use super::provider::ProviderRegistry;
+use super::rules::{default_rules, CompiledRule, TagCondition, TagRule};
+use super::schedule::resync_auto_tags;
+use crate::db::Database;
+use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// 配置表中保存标签规则的键
+pub const TAG_RULES_KEY: &str = "tag_rules";
+
+/// 规则文件的格式版本
+const RULE_FILE_VERSION: u32 = 1;
+
+/// 读取当前启用的标签规则，尚未保存过时使用默认规则
+pub fn load_rules(db: &Database) -> Result<Vec<TagRule>> {
+    let mut rules: Vec<TagRule> = match db.get_setting(TAG_RULES_KEY)? {
+        Some(json) => serde_json::from_str(&json)
+            .map_err(|e| AppError::InvalidRule(format!("已保存的规则无法解析: {}", e)))?,
+        None => return Ok(default_rules()),
+    };
+
+    rename_legacy_rules(&mut rules);
+    ensure_unique_names(&rules)?;
+    Ok(rules)
+}
+
+/// 旧版默认规则中与文件类型规则同名的路径规则，及其新名称
+const LEGACY_RULE_NAMES: &[(&str, &str, &str)] = &[
+    ("图片", "pictures", "图片文件夹"),
+    ("视频", "videos", "视频文件夹"),
+];
+
+/// 将旧版默认规则中重名的路径规则改为新名称，其余规则保持不变
+fn rename_legacy_rules(rules: &mut [TagRule]) {
+    for rule in rules.iter_mut() {
+        let renamed = LEGACY_RULE_NAMES.iter().find(|(name, path, _)| {
+            rule.name == *name && rule.condition == TagCondition::PathContains(path.to_string())
+        });
+        if let Some((_, _, new_name)) = renamed {
+            rule.name = new_name.to_string();
+        }
+    }
+}
+
+/// 规则以名称区分，名称重复时返回错误
+pub(crate) fn ensure_unique_names(rules: &[TagRule]) -> Result<()> {
+    let mut seen = HashSet::new();
+    let duplicates: Vec<&str> = rules
+        .iter()
+        .filter(|r| !seen.insert(r.name.as_str()))
+        .map(|r| r.name.as_str())
+        .collect();
+
+    if duplicates.is_empty() {
+        Ok(())
+    } else {
+        Err(AppError::InvalidRule(format!("规则名称重复: {}", duplicates.join(", "))))
+    }
+}
+
+/// 将规则序列化为配置值
+pub fn rules_to_setting(rules: &[TagRule]) -> Result<String> {
+    serde_json::to_string(rules)
+        .map_err(|e| AppError::Unknown(format!("规则序列化失败: {}", e)))
+}
+
+/// 导入模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// 合并：导入的规则替换同名的已有规则，其余规则保留
+    Merge,
+    /// 替换：以导入的规则替换全部已有规则
+    Replace,
+}
+
+/// 导入结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// 导入的规则数
+    pub imported: usize,
+    /// 导入后启用的规则总数
+    pub total: usize,
+    /// 重新计算自动标签的文件数
+    pub retagged_files: usize,
+}
+
+/// 规则文件格式，按扩展名区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuleFormat {
+    Toml,
+    Json,
+}
+
+impl RuleFormat {
+    fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_deref() {
+            Some("toml") => Ok(RuleFormat::Toml),
+            Some("json") => Ok(RuleFormat::Json),
+            _ => Err(AppError::InvalidRule(format!(
+                "不支持的规则文件格式（仅支持 .toml 和 .json）: {}",
+                path.display()
+            ))),
+        }
+    }
+}
+
+/// 规则文件内容
+#[derive(Debug, Serialize)]
+struct RuleFile<'a> {
+    version: u32,
+    rules: &'a [TagRule],
+}
+
+/// 将规则序列化为文件内容
+fn encode_rules(rules: &[TagRule], format: RuleFormat) -> Result<String> {
+    let file = RuleFile {
+        version: RULE_FILE_VERSION,
+        rules,
+    };
+
+    match format {
+        RuleFormat::Toml => toml::to_string_pretty(&file)
+            .map_err(|e| AppError::Unknown(format!("规则序列化失败: {}", e))),
+        RuleFormat::Json => serde_json::to_string_pretty(&file)
+            .map_err(|e| AppError::Unknown(format!("规则序列化失败: {}", e))),
+    }
+}
+
+/// 解析文件内容并逐条校验规则，所有错误一并报告
+fn decode_rules(content: &str, format: RuleFormat) -> Result<Vec<TagRule>> {
+    let document: JsonValue = match format {
+        RuleFormat::Toml => toml::from_str(content)
+            .map_err(|e| AppError::InvalidRule(format!("TOML 解析失败: {}", e)))?,
+        RuleFormat::Json => serde_json::from_str(content)
+            .map_err(|e| AppError::InvalidRule(format!("JSON 解析失败: {}", e)))?,
+    };
+
+    if let Some(version) = document.get("version").and_then(|v| v.as_u64()) {
+        if version > RULE_FILE_VERSION as u64 {
+            return Err(AppError::InvalidRule(format!(
+                "规则文件版本 {} 高于当前支持的版本 {}",
+                version, RULE_FILE_VERSION
+            )));
+        }
+    }
+
+    let entries = document
+        .get("rules")
+        .and_then(|r| r.as_array())
+        .ok_or_else(|| AppError::InvalidRule("规则文件缺少 rules 列表".to_string()))?;
+
+    let mut rules = Vec::with_capacity(entries.len());
+    let mut errors = Vec::new();
+
+    for (index, entry) in entries.iter().enumerate() {
+        let label = match entry.get("name").and_then(|n| n.as_str()) {
+            Some(name) => format!("第 {} 条规则 '{}'", index + 1, name),
+            None => format!("第 {} 条规则", index + 1),
+        };
+
+        let rule = match TagRule::deserialize(entry) {
+            Ok(rule) => rule,
+            Err(e) => {
+                errors.push(format!("{}: {}", label, e));
+                continue;
+            }
+        };
+
+        if rule.name.trim().is_empty() {
+            errors.push(format!("{}: 标签名不能为空", label));
+            continue;
+        }
+
+        match CompiledRule::compile(rule.clone()) {
+            Ok(_) => rules.push(rule),
+            Err(AppError::InvalidRule(e)) => errors.push(format!("{}: {}", label, e)),
+            Err(e) => errors.push(format!("{}: {}", label, e)),
+        }
+    }
+
+    if let Err(AppError::InvalidRule(e)) = ensure_unique_names(&rules) {
+        errors.push(e);
+    }
+
+    if !errors.is_empty() {
+        return Err(AppError::InvalidRule(errors.join("\n")));
+    }
+
+    Ok(rules)
+}
+
+/// 按导入模式合并规则
+fn merge_rules(existing: Vec<TagRule>, imported: Vec<TagRule>, mode: ImportMode) -> Vec<TagRule> {
+    match mode {
+        ImportMode::Replace => imported,
+        ImportMode::Merge => {
+            let mut rules: Vec<TagRule> = existing
+                .into_iter()
+                .filter(|r| !imported.iter().any(|i| i.name == r.name))
+                .collect();
+            rules.extend(imported);
+            rules
+        }
+    }
+}
+
+/// 导出当前启用的标签规则到文件，返回导出的规则数
+pub fn export_rules(db: &Database, path: &Path) -> Result<usize> {
+    let format = RuleFormat::from_path(path)?;
+    let rules = load_rules(db)?;
+
+    std::fs::write(path, encode_rules(&rules, format)?)?;
+
+    Ok(rules.len())
+}
+
+/// 从文件导入标签规则，保存后按新规则重新计算所有自动标签
+///
+/// 任一规则校验失败时不做任何修改。
+pub fn import_rules(db: &Database, path: &Path, mode: ImportMode) -> Result<ImportSummary> {
+    let format = RuleFormat::from_path(path)?;
+    let content = std::fs::read_to_string(path)?;
+    let imported = decode_rules(&content, format)?;
+    let imported_count = imported.len();
+
+    let rules = merge_rules(load_rules(db)?, imported, mode);
+    let setting = rules_to_setting(&rules)?;
+
+    db.set_setting(TAG_RULES_KEY, &setting)?;
+    let registry = ProviderRegistry::load(db)?;
+    let retagged_files = resync_auto_tags(db, &registry)?;
+
+    Ok(ImportSummary {
+        imported: imported_count,
+        total: rules.len(),
+        retagged_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules_round_trip() {
+        for format in [RuleFormat::Toml, RuleFormat::Json] {
+            let encoded = encode_rules(&default_rules(), format).unwrap();
+            let decoded = decode_rules(&encoded, format).unwrap();
+            assert_eq!(decoded, default_rules(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_decode_reports_each_invalid_rule() {
+        let content = r#"
+version = 1
+
+[[rules]]
+name = "正常"
+condition = { type = "extension", value = ["rs"] }
+
+[[rules]]
+name = "坏正则"
+condition = { type = "nameRegex", value = "([a-z" }
+
+[[rules]]
+name = "未知条件"
+condition = { type = "colorIs", value = "red" }
+"#;
+
+        let err = decode_rules(content, RuleFormat::Toml).unwrap_err().to_string();
+        assert!(!err.contains("正常"));
+        assert!(err.contains("第 2 条规则 '坏正则'"));
+        assert!(err.contains("第 3 条规则 '未知条件'"));
+    }
+
+    #[test]
+    fn test_merge_and_replace() {
+        let rule = |name: &str, ext: &str| TagRule {
+            name: name.to_string(),
+            condition: TagCondition::Extension(vec![ext.to_string()]),
+        };
+        let existing = vec![rule("代码", "rs"), rule("配置", "toml"), rule("文档", "md")];
+        let imported = vec![rule("代码", "py")];
+
+        let merged = merge_rules(existing.clone(), imported.clone(), ImportMode::Merge);
+        assert_eq!(merged, vec![rule("配置", "toml"), rule("文档", "md"), rule("代码", "py")]);
+
+        let replaced = merge_rules(existing, imported.clone(), ImportMode::Replace);
+        assert_eq!(replaced, imported);
+    }
+
+    #[test]
+    fn test_duplicate_rule_names() {
+        assert!(ensure_unique_names(&default_rules()).is_ok());
+
+        let content = r#"{"rules": [
+            {"name": "代码", "condition": {"type": "extension", "value": ["rs"]}},
+            {"name": "代码", "condition": {"type": "extension", "value": ["go"]}}
+        ]}"#;
+        let err = decode_rules(content, RuleFormat::Json).unwrap_err().to_string();
+        assert!(err.contains("规则名称重复: 代码"));
+    }
+
+    #[test]
+    fn test_legacy_duplicate_rules_are_renamed() {
+        let db = Database::in_memory().unwrap();
+        let mut legacy = default_rules();
+        for rule in legacy.iter_mut() {
+            if let Some((old, _, _)) = LEGACY_RULE_NAMES.iter().find(|(_, _, new)| rule.name == *new) {
+                rule.name = old.to_string();
+            }
+        }
+        db.set_setting(TAG_RULES_KEY, &rules_to_setting(&legacy).unwrap()).unwrap();
+
+        assert_eq!(load_rules(&db).unwrap(), default_rules());
+    }
+
+    #[test]
+    fn test_unsupported_extension() {
+        assert!(RuleFormat::from_path(Path::new("rules.yaml")).is_err());
+        assert_eq!(RuleFormat::from_path(Path::new("Rules.TOML")).unwrap(), RuleFormat::Toml);
+    }
+}
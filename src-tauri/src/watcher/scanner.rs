@@ -1,5 +1,5 @@
 use crate::db::{File, FileStatus, FileType};
-use crate::tagger::AutoTagger;
+use crate::tagger::ProviderRegistry;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -30,16 +30,22 @@ impl Default for ScanConfig {
 /// 目录扫描器
 pub struct DirectoryScanner<'a> {
     db: &'a crate::db::Database,
-    tagger: AutoTagger,
+    /// 标签提供者，加载失败时保留错误并跳过自动标签同步，避免误删已有的自动标签
+    providers: crate::error::Result<ProviderRegistry>,
     config: ScanConfig,
 }
 
 impl<'a> DirectoryScanner<'a> {
-    /// 创建新的扫描器
+    /// 创建新的扫描器（使用已保存的标签规则和各目录的提供者配置）
     pub fn new(db: &'a crate::db::Database) -> Self {
+        let providers = ProviderRegistry::load(db);
+        if let Err(ref e) = providers {
+            eprintln!("[Scanner] 加载标签提供者失败，跳过自动标签同步: {}", e);
+        }
+
         DirectoryScanner {
             db,
-            tagger: AutoTagger::new(),
+            providers,
             config: ScanConfig::default(),
         }
     }
@@ -54,6 +60,10 @@ impl<'a> DirectoryScanner<'a> {
     pub fn scan(&self, path: &PathBuf) -> ScanResult {
         let mut result = ScanResult::new(path.clone());
 
+        if let Err(ref e) = self.providers {
+            result.add_error(path.clone(), format!("加载标签提供者失败，已跳过自动标签同步: {}", e));
+        }
+
         // 获取该目录下已记录的所有文件
         let path_str = path.to_string_lossy().to_string();
         let existing_files = match self.db.get_files_by_path_prefix(&path_str) {
@@ -194,9 +204,9 @@ impl<'a> DirectoryScanner<'a> {
             extension,
             size: metadata.len() as i64,
             file_type,
-            created_at: chrono::Utc::now(),
-            modified_at: chrono::Utc::now(),
-            accessed_at: chrono::Utc::now(),
+            created_at: to_datetime(metadata.created()),
+            modified_at: to_datetime(metadata.modified()),
+            accessed_at: to_datetime(metadata.accessed()),
             status: FileStatus::Active,
             indexed_at: chrono::Utc::now(),
             metadata: None,
@@ -210,7 +220,12 @@ impl<'a> DirectoryScanner<'a> {
         }
 
         // 创建或更新文件
-        let file_id = match self.db.create_file(&file) {
+        let saved = if existing_file.is_some() {
+            self.db.update_file(&file)
+        } else {
+            self.db.create_file(&file)
+        };
+        let file_id = match saved {
             Ok(id) => id,
             Err(e) => {
                 result.add_error(path.clone(), format!("创建文件失败: {}", e));
@@ -218,11 +233,14 @@ impl<'a> DirectoryScanner<'a> {
             }
         };
 
-        // 为文件生成并添加自动标签
-        let tags: Vec<String> = self.tagger.generate_tags(&file);
-        for tag_name in tags {
-            // 忽略错误，继续处理其他标签
-            let _ = self.db.add_tag_to_file_by_name(file_id, &tag_name, true);
+        // 同步自动标签：添加新匹配的标签，移除已不再匹配的自动标签
+        let providers = match self.providers {
+            Ok(ref providers) => providers,
+            Err(_) => return,
+        };
+        let generated = providers.generate(&file);
+        if let Err(e) = self.db.sync_auto_tags(file_id, &generated.tags, &generated.failed_rules) {
+            result.add_error(path.clone(), format!("更新自动标签失败: {}", e));
         }
     }
 
@@ -240,6 +258,12 @@ impl<'a> DirectoryScanner<'a> {
     }
 }
 
+/// 将文件系统时间转换为 UTC 时间（时间相关标签依赖真实的修改时间），获取失败时使用当前时间
+fn to_datetime(time: std::io::Result<std::time::SystemTime>) -> chrono::DateTime<chrono::Utc> {
+    time.map(chrono::DateTime::<chrono::Utc>::from)
+        .unwrap_or_else(|_| chrono::Utc::now())
+}
+
 /// 扫描错误
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ScanError {
@@ -322,3 +346,42 @@ impl std::fmt::Display for ScanResult {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_provider_load_failure_keeps_auto_tags() {
+        let dir = std::env::temp_dir().join(format!("scanner-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("note.txt"), "hello").unwrap();
+
+        let db = Database::in_memory().unwrap();
+        let result = DirectoryScanner::new(&db).scan(&dir);
+        assert!(result.errors.is_empty());
+
+        let file_id = db
+            .get_file_by_path(&dir.join("note.txt").to_string_lossy())
+            .unwrap()
+            .unwrap()
+            .id
+            .unwrap();
+        let before = db.get_tags_by_file(file_id).unwrap();
+        assert!(before.iter().any(|t| t.name == "文本"));
+
+        // 已保存的规则损坏时不应回退到默认规则而删除自动标签
+        db.set_setting("tag_rules", "not json").unwrap();
+        let result = DirectoryScanner::new(&db).scan(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.updated_files, 1);
+        let after = db.get_tags_by_file(file_id).unwrap();
+        assert_eq!(
+            after.iter().map(|t| &t.name).collect::<Vec<_>>(),
+            before.iter().map(|t| &t.name).collect::<Vec<_>>()
+        );
+    }
+}
@@ -8,6 +8,7 @@ import Toast from './components/Toast';
 import SettingsPanel from './components/SettingsPanel';
 import { useStore } from './stores/useStore';
 import { api } from './lib/api';
+import { quoteSearchTerm } from './lib/utils';
 
 export default function App() {
   const [activeTab, setActiveTab] = useState<'tags' | 'directories'>('tags');
@@ -77,14 +78,15 @@ export default function App() {
   const performSearch = async () => {
     setIsLoading(true);
     try {
-      const results = await api.searchFiles(
-        searchKeywords,
-        searchOperator,
-        undefined,
-        selectedTags.length > 0 ? selectedTags : undefined,
-        50,
-        0
-      );
+      // 每个关键字作为一个短语，选中的标签追加为 tag: 条件；均加引号，避免用户输入被解析为搜索语法
+      const keywordQuery = searchKeywords
+        .filter((k) => k.trim())
+        .map(quoteSearchTerm)
+        .join(searchOperator === 'OR' ? ' OR ' : ' ');
+      const tagQuery = selectedTags.map((t) => `tag:${quoteSearchTerm(t)}`).join(' ');
+      const query = [keywordQuery && `(${keywordQuery})`, tagQuery].filter(Boolean).join(' ');
+
+      const results = await api.searchFiles(query, 50, 0);
       setSearchResults(results);
     } catch (error) {
       console.error('搜索失败:', error);
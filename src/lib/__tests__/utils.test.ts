@@ -1,5 +1,5 @@
 import { describe, it, expect } from 'vitest';
-import { formatFileSize, formatDate, getFileIcon, getFileTypeIcon } from '../utils';
+import { formatFileSize, formatDate, getFileIcon, getFileTypeIcon, quoteSearchTerm, toUtf16Range } from '../utils';
 
 describe('formatFileSize', () => {
   it('应该正确格式化字节', () => {
@@ -79,3 +79,28 @@ describe('getFileTypeIcon', () => {
     expect(getFileTypeIcon('binary')).toBe('⚙️');
   });
 });
+
+describe('quoteSearchTerm', () => {
+  it('应该将文本整体加引号', () => {
+    expect(quoteSearchTerm('quarterly report')).toBe('"quarterly report"');
+    expect(quoteSearchTerm('a OR (b')).toBe('"a OR (b"');
+  });
+
+  it('应该转义文本中的引号', () => {
+    expect(quoteSearchTerm('12" vinyl')).toBe('"12"" vinyl"');
+    expect(quoteSearchTerm('"')).toBe('""""');
+  });
+});
+
+describe('toUtf16Range', () => {
+  it('基本平面字符的下标不变', () => {
+    expect(toUtf16Range('报告 )', 3, 4)).toEqual([3, 4]);
+  });
+
+  it('应该换算辅助平面字符', () => {
+    const text = '😀 )';
+    const [start, end] = toUtf16Range(text, 2, 3);
+    expect([start, end]).toEqual([3, 4]);
+    expect(text.slice(start, end)).toBe(')');
+  });
+});
@@ -81,23 +81,23 @@ export const api = {
 
   // ===== 搜索操作 =====
   searchFiles: async (
-    keywords: string[],
-    operator: 'AND' | 'OR',
-    fileTypeFilter?: string,
-    tags?: string[],
+    query: string,
     limit?: number,
-    offset?: number
+    offset?: number,
+    fuzzy?: Api.FuzzyOptions
   ): Promise<Api.SearchResultResponse> => {
     return invoke('search_files', {
-      keywords,
-      operator,
-      fileTypeFilter,
-      tags,
+      query,
       limit: limit ?? 50,
       offset: offset ?? 0,
+      fuzzy,
     });
   },
 
+  parseSearchQuery: async (query: string): Promise<Api.SearchQuery> => {
+    return invoke('parse_search_query', { query });
+  },
+
   // ===== 监控目录操作 =====
   getWatchedDirectories: async (): Promise<Api.WatchedDirectory[]> => {
     return invoke('get_watched_directories');
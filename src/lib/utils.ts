@@ -84,3 +84,21 @@ export function getFileTypeIcon(fileType: string): string {
 
   return iconMap[fileType] || '📄';
 }
+
+/**
+ * 将文本作为搜索语句中的短语：整体加引号，文本中的引号写作 `""`
+ */
+export function quoteSearchTerm(text: string): string {
+  return `"${text.replace(/"/g, '""')}"`;
+}
+
+/**
+ * 将后端返回的字符区间（Unicode 码点下标）换算为 JavaScript 字符串的 UTF-16 下标
+ *
+ * 搜索语句解析错误和文件名匹配区间都按码点计，文本含 emoji 等辅助平面字符时两者不同。
+ */
+export function toUtf16Range(text: string, start: number, end: number): [number, number] {
+  const chars = Array.from(text);
+  const offset = (index: number) => chars.slice(0, index).join('').length;
+  return [offset(start), offset(end)];
+}
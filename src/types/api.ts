@@ -29,21 +29,74 @@ export interface Tag {
   color: string;
   icon?: string;
   useCount: number;
+  /** 父标签 ID，层级标签的 name 为完整路径（如 `work/clientA/invoices`） */
+  parentId?: number | null;
+  /** 键值标签在该文件上的值（仅在获取文件的标签时填充） */
+  value?: TagValue;
+  /** 继承自目录标签时为来源目录（仅在获取文件的标签时填充） */
+  inheritedFrom?: string;
+  /** 由推导规则添加时为来源标签 ID（仅在获取文件的标签时填充） */
+  impliedBy?: number;
 }
 
+/** 键值标签的值，如 rating=4、status=review、due=2024-06-30 */
+export type TagValue =
+  | { type: 'string'; value: string }
+  | { type: 'number'; value: number }
+  | { type: 'date'; value: string };
+
 // ===== 搜索相关 =====
+export type QueryTerm =
+  | { type: 'keyword'; value: string }
+  | { type: 'phrase'; value: string }
+  | { type: 'tag'; value: string }
+  | { type: 'type'; value: string }
+  | { type: 'extension'; value: string }
+  | { type: 'size'; value: { op: string; bytes: number } }
+  | { type: 'modified'; value: { from?: string | null; until?: string | null } }
+  | { type: 'path'; value: string }
+  | { type: 'attribute'; value: { key: string; op: string; value: string } };
+
+export type QueryExpr =
+  | { type: 'and'; value: QueryExpr[] }
+  | { type: 'or'; value: QueryExpr[] }
+  | { type: 'not'; value: QueryExpr }
+  | { type: 'term'; value: QueryTerm };
+
 export interface SearchQuery {
-  keywords: string[];
-  operator: 'AND' | 'OR';
-  fileTypeFilter?: string;
+  expr: QueryExpr | null;
   limit: number;
   offset: number;
 }
 
+/** 位置为 Unicode 码点下标（`end` 不含），截取字符串前用 `toUtf16Range` 换算 */
+export interface QueryParseError {
+  message: string;
+  start: number;
+  end: number;
+}
+
+/** search_files 失败时的错误，搜索语句有误时为带位置的解析错误 */
+export type SearchError =
+  | ({ kind: 'parse' } & QueryParseError)
+  | { kind: 'database'; message: string };
+
+/** 文件名中的匹配区间，位置同样为 Unicode 码点下标 */
+export interface MatchHighlight {
+  start: number;
+  end: number;
+  distance: number;
+}
+
 export interface SearchResult {
   file: File;
   tags: Tag[];
   relevance: number;
+  highlights: MatchHighlight[];
+}
+
+export interface FuzzyOptions {
+  maxDistance?: number;
 }
 
 export interface SearchResultResponse {